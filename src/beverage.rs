@@ -0,0 +1,47 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::containers::IngredientsContainer;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Beverage {
+    pub name: String,
+    pub ingredients: IngredientsContainer,
+    pub brewed_at: SystemTime,
+}
+
+impl Beverage {
+    /// Create a new beverage
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the beverage
+    /// * `ingredients` - The ingredients used to make the beverage
+    /// * `brewed_at` - The time the beverage was brewed
+    ///
+    /// # Returns
+    ///
+    /// A new beverage
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::Beverage;
+    /// use rusty_coffeemachine::containers::IngredientsContainer;
+    /// let beverage = Beverage::new("Espresso".to_string(), IngredientsContainer {
+    ///    water: 30.0,
+    ///    coffee: 30.0,
+    ///    milk: 0.0,
+    ///    sugar: 0.0,
+    ///    cacao: 0.0,
+    /// }, std::time::SystemTime::now());
+    /// ```
+    pub fn new(name: String, ingredients: IngredientsContainer, brewed_at: SystemTime) -> Self {
+        Beverage {
+            name,
+            ingredients,
+            brewed_at,
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+/// The temperature of the room the coffee machine stands in, in °C
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+/// The temperature espresso is brewed at, in °C
+pub const BREW_TEMPERATURE: f32 = 93.0;
+
+/// The temperature needed to steam and froth milk, in °C
+pub const STEAM_TEMPERATURE: f32 = 125.0;
+
+/// How far below its target the boiler may be to count as ready, in °C
+pub const TEMPERATURE_TOLERANCE: f32 = 0.5;
+
+/// The hottest target a reciepe may ask for, in °C
+pub const MAX_TEMPERATURE: f32 = 140.0;
+
+/// How often the coffee machine checks the temperature while waiting for the boiler
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long the coffee machine waits for the boiler before giving up
+pub const HEAT_UP_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The thermal model of a boiler with a thermostat
+///
+/// While the heater is on the boiler heats up towards the temperature where the heating power
+/// equals the heat loss, and the thermostat holds it at the target once reached. Without heating
+/// it cools down to the ambient temperature. Both follow Newton's law of cooling, so the model is
+/// exact for any time step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThermalModel {
+    /// The water temperature in °C
+    pub temperature: f32,
+    /// The temperature of the surroundings in °C
+    pub ambient: f32,
+    /// The heating power in W
+    pub power: f32,
+    /// The energy needed to heat the boiler by one degree in J/K
+    pub heat_capacity: f32,
+    /// The heat lost to the surroundings per degree above ambient in W/K
+    pub heat_loss: f32,
+    /// Whether the heater is switched on
+    pub heating: bool,
+    /// The temperature the thermostat holds in °C
+    pub target: f32,
+}
+
+impl Default for ThermalModel {
+    /// A cold thermoblock boiler of 1500 W, reaching the brewing temperature in about 20 s
+    fn default() -> Self {
+        ThermalModel {
+            temperature: AMBIENT_TEMPERATURE,
+            ambient: AMBIENT_TEMPERATURE,
+            power: 1500.0,
+            heat_capacity: 400.0,
+            heat_loss: 2.0,
+            heating: false,
+            target: BREW_TEMPERATURE,
+        }
+    }
+}
+
+impl ThermalModel {
+    /// Get the time constant of heating up and cooling down
+    fn time_constant(&self) -> f32 {
+        self.heat_capacity / self.heat_loss
+    }
+
+    /// Get the temperature the boiler would reach heating without a thermostat
+    fn limit(&self) -> f32 {
+        self.ambient + self.power / self.heat_loss
+    }
+
+    /// Get the time the heater needs to reach a temperature
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The temperature in °C
+    ///
+    /// # Returns
+    ///
+    /// The time, zero if the boiler is already hot enough and `None` if the heater is too weak
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::boiler::{ThermalModel, BREW_TEMPERATURE};
+    /// let boiler = ThermalModel::default();
+    /// let heat_up = boiler.time_to_reach(BREW_TEMPERATURE).unwrap();
+    /// assert_eq!(heat_up.as_secs(), 20);
+    /// assert_eq!(boiler.time_to_reach(1000.0), None);
+    /// ```
+    pub fn time_to_reach(&self, target: f32) -> Option<Duration> {
+        if self.temperature >= target {
+            return Some(Duration::ZERO);
+        }
+        let limit = self.limit();
+        if target >= limit {
+            return None;
+        }
+        let seconds = self.time_constant() * ((limit - self.temperature) / (limit - target)).ln();
+
+        Some(Duration::from_secs_f32(seconds))
+    }
+
+    /// Let time pass
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - The time passed since the last update
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use rusty_coffeemachine::boiler::{ThermalModel, BREW_TEMPERATURE};
+    /// let mut boiler = ThermalModel {
+    ///     heating: true,
+    ///     ..Default::default()
+    /// };
+    /// boiler.advance(Duration::from_secs(10));
+    /// assert!(boiler.temperature > 50.0 && boiler.temperature < BREW_TEMPERATURE);
+    /// boiler.advance(Duration::from_secs(60));
+    /// assert_eq!(boiler.temperature, BREW_TEMPERATURE);
+    /// ```
+    pub fn advance(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f32();
+        let decay = (-seconds / self.time_constant()).exp();
+        if self.heating && self.temperature < self.target {
+            // Heating up, until the thermostat holds the target
+            let limit = self.limit();
+            let heated = limit + (self.temperature - limit) * decay;
+            self.temperature = heated.min(self.target);
+        } else {
+            // Cooling down, until the thermostat switches the heater back on
+            let cooled = self.ambient + (self.temperature - self.ambient) * decay;
+            self.temperature = if self.heating {
+                cooled.max(self.target)
+            } else {
+                cooled
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heat_up_and_hold() {
+        let mut boiler = ThermalModel {
+            heating: true,
+            ..Default::default()
+        };
+        let heat_up = boiler.time_to_reach(BREW_TEMPERATURE).unwrap();
+        boiler.advance(heat_up - Duration::from_secs(1));
+        assert!(boiler.temperature < BREW_TEMPERATURE);
+        boiler.advance(Duration::from_secs(1));
+        assert!(boiler.temperature > BREW_TEMPERATURE - 0.01);
+        boiler.advance(Duration::from_secs(600));
+        assert_eq!(boiler.temperature, BREW_TEMPERATURE);
+
+        boiler.target = STEAM_TEMPERATURE;
+        let steam_up = boiler.time_to_reach(STEAM_TEMPERATURE).unwrap();
+        assert!(steam_up > Duration::from_secs(5) && steam_up < heat_up);
+    }
+
+    #[test]
+    fn test_cool_down() {
+        let mut boiler = ThermalModel {
+            temperature: STEAM_TEMPERATURE,
+            heating: true,
+            ..Default::default()
+        };
+        // Lowering the target lets the boiler cool down to it
+        boiler.advance(Duration::from_secs(600));
+        assert_eq!(boiler.temperature, BREW_TEMPERATURE);
+
+        boiler.heating = false;
+        boiler.advance(Duration::from_secs(200));
+        let expected = AMBIENT_TEMPERATURE + (BREW_TEMPERATURE - AMBIENT_TEMPERATURE) / 1f32.exp();
+        assert!((boiler.temperature - expected).abs() < 0.01);
+        boiler.advance(Duration::from_secs(36_000));
+        assert!((boiler.temperature - AMBIENT_TEMPERATURE).abs() < 0.01);
+    }
+}
@@ -0,0 +1,1252 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde_json::{json, Value};
+
+use crate::beverage::Beverage;
+use crate::coffeemachine::CoffeeMachine;
+use crate::console::Console;
+use crate::containers::{Container, Ingredient, IngredientTanks};
+use crate::customization::{Customization, Order};
+use crate::error::MachineError;
+use crate::journal::Journal;
+use crate::ledger::unix_seconds;
+use crate::maintenance::{MaintenanceStatus, MaintenanceTask};
+use crate::payment::Payment;
+use crate::queue::{OrderId, OrderQueue, OrderStatus, Priority, QueuedOrder};
+use crate::report::{format_timestamp, parse_date, parse_time, Period, Report, ReportFormat};
+use crate::service::{ServiceAction, ServiceRecord};
+use crate::state::MachineState;
+use crate::stock::{StockLevel, StockThresholds};
+use crate::{capitalize, format_price, persistence, recipe_book};
+
+pub const USAGE: &str = "\
+Usage: rusty-coffeemachine [OPTIONS] [COMMAND]
+
+Commands:
+  (none)                        Run the interactive coffee machine, full-screen with the tui
+                                feature unless the terminal is dumb
+  brew <recipe>                 Brew a recipe paid by card, given by name or menu number
+  status                        Show the ingredients, the grounds bin and the counters
+  service                       Refill all ingredients and empty the grounds bin
+  recipes list                  List the recipes
+  refill <ingredient> <amount>  Refill a single ingredient
+  report <daily|weekly>         Summarize the sales of a day or a week
+  forecast                      Show the cups left and when the ingredients run out
+  maintain <descale|clean|rinse>
+                                Run a descaling, milk system cleaning or rinsing program
+  queue add <recipe>            Submit an order to the queue
+  queue list                    List the queued and the recently finished orders
+  queue cancel <id>             Cancel a queued order
+  queue run                     Brew the queued orders one at a time, paid by card
+  serve                         Take orders over HTTP on localhost (needs the server feature)
+  journal list                  List every recorded change of the levels and the state
+  journal levels <time>         Show the levels at a time, as YYYY-MM-DD [HH:MM[:SS]]
+  journal compact [<time>]      Fold the changes before a time, by default all, into a snapshot
+  help                          Show this help
+
+Options:
+  --recipes <path>  Load the recipes from a TOML or JSON recipe book
+  --state <path>    Use another state file
+  --ledger <path>   Use another ledger file, by default next to the state file
+  --journal <path>  Use another journal file, by default next to the state file
+  --date <date>     Report on another day than today, as YYYY-MM-DD
+  --format <format> Write the report as text, csv or json
+  --size <size>     Brew a small, medium or large beverage
+  --strength <s>    Brew a weak, normal or strong beverage
+  --extra-sugar     Brew with extra sugar
+  --extra-milk      Brew with extra milk
+  --priority <p>    Queue an order with normal or high priority
+  --port <port>     Serve on another port than 8080
+  --json            Write the output as JSON
+  --plain           Run the interactive mode line by line, without the full-screen UI";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Interactive,
+    Brew(Order),
+    Status,
+    Service,
+    ListRecipes,
+    Refill(Ingredient, f32),
+    Report {
+        period: Period,
+        date: Option<SystemTime>,
+        format: ReportFormat,
+    },
+    Forecast,
+    Maintain(MaintenanceTask),
+    QueueAdd(Order, Priority),
+    QueueList,
+    QueueCancel(OrderId),
+    QueueRun,
+    JournalList,
+    JournalLevels(SystemTime),
+    JournalCompact(Option<SystemTime>),
+    #[cfg(feature = "server")]
+    Serve(u16),
+    Help,
+}
+
+/// The parsed command line of the coffee machine
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub recipes: Option<PathBuf>,
+    pub state: Option<PathBuf>,
+    pub ledger: Option<PathBuf>,
+    pub journal: Option<PathBuf>,
+    pub json: bool,
+    pub plain: bool,
+}
+
+impl Cli {
+    /// Parse the command line arguments
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The arguments without the program name
+    ///
+    /// # Returns
+    ///
+    /// The parsed command line
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the arguments are invalid
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::cli::{Cli, Command};
+    /// let cli = Cli::parse(["brew", "Latte", "--json"]).unwrap();
+    /// assert_eq!(cli.command, Command::Brew("Latte".into()));
+    /// assert!(cli.json);
+    /// ```
+    pub fn parse<I, S>(args: I) -> Result<Self, MachineError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut recipes = None;
+        let mut state = None;
+        let mut ledger = None;
+        let mut journal = None;
+        let mut date = None;
+        let mut format = None;
+        let mut port = None;
+        let mut priority = None;
+        let mut customization = Customization::default();
+        let mut json = false;
+        let mut plain = false;
+        let mut words = Vec::new();
+        let mut args = args.into_iter().map(Into::into);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--recipes" => recipes = Some(PathBuf::from(Self::value(&arg, args.next())?)),
+                "--state" => state = Some(PathBuf::from(Self::value(&arg, args.next())?)),
+                "--ledger" => ledger = Some(PathBuf::from(Self::value(&arg, args.next())?)),
+                "--journal" => journal = Some(PathBuf::from(Self::value(&arg, args.next())?)),
+                "--date" => date = Some(parse_date(&Self::value(&arg, args.next())?)?),
+                "--format" => format = Some(Self::value(&arg, args.next())?.parse()?),
+                "--size" => customization.size = Self::value(&arg, args.next())?.parse()?,
+                "--strength" => customization.strength = Self::value(&arg, args.next())?.parse()?,
+                "--extra-sugar" => customization.extra_sugar = true,
+                "--extra-milk" => customization.extra_milk = true,
+                "--priority" => priority = Some(Self::value(&arg, args.next())?.parse()?),
+                "--port" => {
+                    let value = Self::value(&arg, args.next())?;
+                    port = Some(value.parse::<u16>().map_err(|_| {
+                        MachineError::InvalidInput(format!("Invalid port: {}", value))
+                    })?)
+                }
+                "--json" => json = true,
+                "--plain" => plain = true,
+                "--help" | "-h" => words = vec!["help".to_string()],
+                _ if arg.starts_with("--") => {
+                    return Err(MachineError::InvalidInput(format!(
+                        "Unknown option: {}",
+                        arg
+                    )))
+                }
+                _ => words.push(arg),
+            }
+        }
+
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let command = match words.as_slice() {
+            [] => Command::Interactive,
+            ["brew", recipe @ ..] if !recipe.is_empty() => {
+                Command::Brew(Self::order(recipe, &mut customization))
+            }
+            ["queue", "add", recipe @ ..] if !recipe.is_empty() => Command::QueueAdd(
+                Self::order(recipe, &mut customization),
+                priority.take().unwrap_or_default(),
+            ),
+            ["queue"] | ["queue", "list"] => Command::QueueList,
+            ["queue", "cancel", id] => {
+                Command::QueueCancel(id.trim_start_matches('#').parse().map_err(|_| {
+                    MachineError::InvalidInput(format!("Invalid order number: {}", id))
+                })?)
+            }
+            ["queue", "run"] => Command::QueueRun,
+            ["journal"] | ["journal", "list"] => Command::JournalList,
+            ["journal", "levels", time @ ..] if !time.is_empty() => {
+                Command::JournalLevels(parse_time(&time.join(" "))?)
+            }
+            ["journal", "compact"] => Command::JournalCompact(None),
+            ["journal", "compact", time @ ..] => {
+                Command::JournalCompact(Some(parse_time(&time.join(" "))?))
+            }
+            ["status"] => Command::Status,
+            ["forecast"] => Command::Forecast,
+            ["maintain", task] => Command::Maintain(task.parse()?),
+            ["service"] => Command::Service,
+            ["recipes"] | ["recipes", "list"] => Command::ListRecipes,
+            ["refill", ingredient, amount] => Command::Refill(
+                ingredient.parse()?,
+                amount.parse().map_err(|_| {
+                    MachineError::InvalidInput(format!("Invalid amount: {}", amount))
+                })?,
+            ),
+            ["report", period] => Command::Report {
+                period: period.parse()?,
+                date: date.take(),
+                format: format.take().unwrap_or(if json {
+                    ReportFormat::Json
+                } else {
+                    ReportFormat::Text
+                }),
+            },
+            #[cfg(feature = "server")]
+            ["serve"] => Command::Serve(port.take().unwrap_or(crate::server::DEFAULT_PORT)),
+            #[cfg(not(feature = "server"))]
+            ["serve"] => {
+                return Err(MachineError::InvalidInput(
+                    "The server is not built in, build with --features server".to_string(),
+                ))
+            }
+            ["help"] => Command::Help,
+            _ => {
+                return Err(MachineError::InvalidInput(format!(
+                    "Unknown command: {}",
+                    words.join(" ")
+                )))
+            }
+        };
+        if date.is_some() || format.is_some() {
+            return Err(MachineError::InvalidInput(
+                "--date and --format only apply to report".to_string(),
+            ));
+        }
+        if priority.is_some() {
+            return Err(MachineError::InvalidInput(
+                "--priority only applies to queue add".to_string(),
+            ));
+        }
+        if port.is_some() {
+            return Err(MachineError::InvalidInput(
+                "--port only applies to serve".to_string(),
+            ));
+        }
+        if plain && command != Command::Interactive {
+            return Err(MachineError::InvalidInput(
+                "--plain only applies to the interactive mode".to_string(),
+            ));
+        }
+        if !customization.is_default() {
+            return Err(MachineError::InvalidInput(
+                "--size, --strength and the extras only apply to brew and queue add".to_string(),
+            ));
+        }
+
+        Ok(Cli {
+            command,
+            recipes,
+            state,
+            ledger,
+            journal,
+            json,
+            plain,
+        })
+    }
+
+    /// Build an order from the words naming its reciepe, taking the customization of the options
+    ///
+    /// # Arguments
+    ///
+    /// * `recipe` - The menu number or the words of the name of the reciepe
+    /// * `customization` - The customization given by the options, left at the default
+    ///
+    /// # Returns
+    ///
+    /// The order
+    fn order(recipe: &[&str], customization: &mut Customization) -> Order {
+        let recipe = recipe.join(" ");
+        let mut order = match recipe.parse::<usize>() {
+            Ok(id) => Order::new(id),
+            Err(_) => Order::new(recipe),
+        };
+        order.customization = std::mem::take(customization);
+        order
+    }
+
+    /// Get the value of an option
+    fn value(option: &str, value: Option<String>) -> Result<String, MachineError> {
+        value.ok_or_else(|| MachineError::InvalidInput(format!("{} needs a value", option)))
+    }
+
+    /// Create the coffee machine described by the command line
+    ///
+    /// The coffee machine is restored from the state file, which defaults to the one in the data
+    /// directory. The orders are recorded in the ledger file, which defaults to the state file
+    /// with the extension `.ledger.jsonl`, and the changes of the levels and the state in the
+    /// journal file, which defaults to the state file with the extension `.journal.jsonl`.
+    ///
+    /// # Returns
+    ///
+    /// The coffee machine
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the state file or the recipe book can't be loaded
+    pub fn machine(&self) -> Result<CoffeeMachine, MachineError> {
+        let mut machine = CoffeeMachine::new()?;
+        let state = self.state.clone().or_else(persistence::default_state_path);
+        if let Some(path) = &state {
+            machine = machine.with_state_file(path)?;
+        }
+        let ledger = self.ledger.clone().or_else(|| {
+            state
+                .as_ref()
+                .map(|path| path.with_extension("ledger.jsonl"))
+        });
+        if let Some(path) = &ledger {
+            machine = machine.with_ledger_file(path)?;
+        }
+        let journal = self.journal.clone().or_else(|| {
+            state
+                .as_ref()
+                .map(|path| path.with_extension("journal.jsonl"))
+        });
+        if let Some(path) = &journal {
+            machine = machine.with_journal_file(path)?;
+        }
+        if let Some(path) = &self.recipes {
+            machine = machine.with_recipes(recipe_book::load(path)?);
+        }
+
+        Ok(machine)
+    }
+
+    /// Execute a non-interactive command
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The coffee machine to operate on
+    /// * `console` - The console to write the output to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the command fails
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::cli::Cli;
+    /// use rusty_coffeemachine::console::ScriptedConsole;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let cli = Cli::parse(["recipes", "list"]).unwrap();
+    /// let mut machine = CoffeeMachine::new().unwrap();
+    /// let mut console = ScriptedConsole::new(&[]);
+    /// cli.execute(&mut machine, &mut console).unwrap();
+    /// assert!(console.transcript().starts_with("1. Espresso"));
+    /// ```
+    pub fn execute(
+        &self,
+        machine: &mut CoffeeMachine,
+        console: &mut dyn Console,
+    ) -> Result<(), MachineError> {
+        match &self.command {
+            Command::Interactive => {
+                #[cfg(feature = "tui")]
+                if !self.plain && crate::tui::supported() {
+                    return crate::tui::run(machine);
+                }
+                machine.run(console)
+            }
+            Command::Help => console.write_line(USAGE),
+            Command::Brew(order) => {
+                power_on(machine)?;
+                let sale = machine.buy(order.clone(), Payment::Card)?;
+                if self.json {
+                    let mut value = beverage_json(&sale.beverage);
+                    value["price"] = json!(sale.price);
+                    value["payment"] = json!(sale.method.to_string());
+                    self.write_json(console, value)
+                } else {
+                    console.write_line(&format!(
+                        "{} ready to go, {} charged to the card.",
+                        sale.beverage.name,
+                        format_price(sale.price)
+                    ))
+                }
+            }
+            Command::Status => {
+                if self.json {
+                    self.write_json(console, status_json(machine))
+                } else {
+                    console.write_line(&format!("State: {}", machine.state()))?;
+                    if let Ok(temperature) = machine.temperature() {
+                        console.write_line(&format!(
+                            "Boiler: {:.1} °C (target {:.1} °C)",
+                            temperature,
+                            machine.target_temperature()
+                        ))?;
+                    }
+                    for ingredient in Ingredient::ALL {
+                        let tank = machine.tanks.get(ingredient);
+                        let mut line = format!("{}: {}", capitalize(&ingredient.to_string()), tank);
+                        match machine.thresholds.level(ingredient, tank.level) {
+                            StockLevel::Ok => {}
+                            level => line.push_str(&format!(" ({})", level)),
+                        }
+                        console.write_line(&line)?;
+                    }
+                    console.write_line(&format!(
+                        "Coffee grounds: {}",
+                        machine.garbage_container.coffee_grounds
+                    ))?;
+                    console.write_line(&format!(
+                        "Beverages brewed: {}",
+                        machine.counters.beverages_brewed
+                    ))?;
+                    console.write_line(&format!("Services: {}", machine.counters.services))?;
+                    console.write_line(&format!(
+                        "Cash: {} ({})",
+                        format_price(machine.coins.total()),
+                        machine.coins
+                    ))?;
+                    for unavailable in machine.unavailable_recipes() {
+                        console.write_line(&format!("Unavailable: {}", unavailable))?;
+                    }
+                    console.write_line(&format!(
+                        "Descaling: {:.2}/{} l ({})",
+                        machine.maintenance_counters.litres_since_descale,
+                        machine.maintenance.descale_interval(),
+                        status_name(machine.maintenance_status(MaintenanceTask::Descale))
+                    ))?;
+                    console.write_line(&format!(
+                        "Milk system cleaning: {}/{} milk drinks ({})",
+                        machine.maintenance_counters.milk_drinks_since_clean,
+                        machine.maintenance.milk_drinks,
+                        status_name(machine.maintenance_status(MaintenanceTask::CleanMilkSystem))
+                    ))
+                }
+            }
+            Command::Service => {
+                power_on(machine)?;
+                let logged = machine.service_log.len();
+                machine.take_service()?;
+                self.write_records(console, &machine.service_log[logged..])
+            }
+            Command::Refill(ingredient, amount) => {
+                power_on(machine)?;
+                let logged = machine.service_log.len();
+                machine.refill(*ingredient, *amount)?;
+                self.write_records(console, &machine.service_log[logged..])
+            }
+            Command::Maintain(task) => {
+                power_on(machine)?;
+                machine.maintain(*task)?;
+                if self.json {
+                    self.write_json(console, json!({ "performed": task.to_string() }))
+                } else {
+                    console.write_line(&format!("{} done.", capitalize(&task.to_string())))
+                }
+            }
+            Command::QueueAdd(order, priority) => {
+                let id = machine.enqueue(order.clone(), *priority)?;
+                let queued = machine
+                    .queue
+                    .get(id)
+                    .ok_or(MachineError::UnknownOrder(id))?;
+                if self.json {
+                    self.write_json(console, order_json(&machine.queue, queued))
+                } else {
+                    console.write_line(&format!(
+                        "Order #{} queued: {}, position {}",
+                        id,
+                        queued.name,
+                        machine.queue.position(id).unwrap_or_default()
+                    ))
+                }
+            }
+            Command::QueueList => {
+                let queue = &machine.queue;
+                if self.json {
+                    let orders: Vec<Value> = queue
+                        .orders()
+                        .iter()
+                        .map(|queued| order_json(queue, queued))
+                        .collect();
+                    self.write_json(console, Value::Array(orders))
+                } else if queue.orders().is_empty() {
+                    console.write_line("The queue is empty")
+                } else {
+                    for queued in queue.orders() {
+                        console.write_line(&queued.to_string())?;
+                    }
+                    Ok(())
+                }
+            }
+            Command::QueueCancel(id) => {
+                machine.cancel_order(*id)?;
+                if self.json {
+                    self.write_json(console, json!({ "cancelled": id }))
+                } else {
+                    console.write_line(&format!("Order #{} cancelled.", id))
+                }
+            }
+            Command::QueueRun => {
+                power_on(machine)?;
+                let mut processed = Vec::new();
+                while let Some(id) = machine.process_next() {
+                    // The queue keeps the order it just finished, but don't rely on it
+                    let Some(queued) = machine.queue.get(id) else {
+                        continue;
+                    };
+                    if !self.json {
+                        console.write_line(&queued.to_string())?;
+                    }
+                    processed.push(order_json(&machine.queue, queued));
+                }
+                if self.json {
+                    self.write_json(console, Value::Array(processed))
+                } else if processed.is_empty() {
+                    console.write_line("The queue is empty")
+                } else {
+                    Ok(())
+                }
+            }
+            Command::JournalList => {
+                let entries = journal(machine)?.entries();
+                if self.json {
+                    let entries = serde_json::to_value(entries)
+                        .map_err(|e| MachineError::Io(std::io::Error::other(e)))?;
+                    self.write_json(console, entries)
+                } else if entries.is_empty() {
+                    console.write_line("The journal is empty")
+                } else {
+                    for entry in entries {
+                        console.write_line(&entry.to_string())?;
+                    }
+                    Ok(())
+                }
+            }
+            Command::JournalLevels(at) => {
+                let time = format_timestamp(unix_seconds(*at));
+                let levels = journal(machine)?.state_at(*at).ok_or_else(|| {
+                    MachineError::InvalidInput(format!("The journal starts after {}", time))
+                })?;
+                if self.json {
+                    self.write_json(
+                        console,
+                        json!({
+                            "at": unix_seconds(*at),
+                            "state": levels.state.to_string(),
+                            "ingredients": ingredients_json(&levels.tanks, &machine.thresholds),
+                            "coffee_grounds": container_json(
+                                &levels.garbage_container.coffee_grounds
+                            ),
+                        }),
+                    )
+                } else {
+                    console.write_line(&format!("Levels at {} ({}):", time, levels.state))?;
+                    for ingredient in Ingredient::ALL {
+                        console.write_line(&format!(
+                            "{}: {}",
+                            capitalize(&ingredient.to_string()),
+                            levels.tanks.get(ingredient)
+                        ))?;
+                    }
+                    console.write_line(&format!(
+                        "Coffee grounds: {}",
+                        levels.garbage_container.coffee_grounds
+                    ))
+                }
+            }
+            Command::JournalCompact(before) => {
+                let before = before.unwrap_or_else(|| machine.clock().now());
+                let compacted = journal_mut(machine)?.compact(before)?;
+                if self.json {
+                    self.write_json(console, json!({ "compacted": compacted }))
+                } else {
+                    console.write_line(&format!(
+                        "Folded {} journal entries into a snapshot.",
+                        compacted
+                    ))
+                }
+            }
+            #[cfg(feature = "server")]
+            Command::Serve(port) => {
+                let server =
+                    crate::server::Server::new(std::mem::replace(machine, CoffeeMachine::new()?));
+                let result = server.serve(*port, console);
+                if let Some(served) = server.into_machine() {
+                    *machine = served;
+                }
+                result
+            }
+            Command::Report {
+                period,
+                date,
+                format,
+            } => {
+                let day = date.unwrap_or_else(|| machine.clock().now());
+                console.write(&Report::new(&machine.ledger, *period, day).render(*format))
+            }
+            Command::Forecast => {
+                let forecast = machine.forecast();
+                if self.json {
+                    let recipes: Vec<Value> = forecast
+                        .recipes
+                        .iter()
+                        .map(|recipe| {
+                            json!({
+                                "id": recipe.id,
+                                "name": recipe.name,
+                                "cups": recipe.cups,
+                                "limiting": recipe.limiting.map(|i| i.to_string()),
+                            })
+                        })
+                        .collect();
+                    let ingredients: Vec<Value> = forecast
+                        .ingredients
+                        .iter()
+                        .map(|ingredient| {
+                            json!({
+                                "ingredient": ingredient.ingredient.to_string(),
+                                "level": ingredient.level,
+                                "unit": ingredient.ingredient.unit().to_string(),
+                                "per_hour": ingredient.per_hour,
+                                "remaining_seconds": ingredient.remaining.map(|r| r.as_secs()),
+                            })
+                        })
+                        .collect();
+                    self.write_json(
+                        console,
+                        json!({
+                            "recipes": recipes,
+                            "ingredients": ingredients,
+                            "runs_out_first": forecast
+                                .runs_out_first()
+                                .map(|first| first.ingredient.to_string()),
+                        }),
+                    )
+                } else {
+                    for recipe in &forecast.recipes {
+                        console.write_line(&recipe.to_string())?;
+                    }
+                    for ingredient in &forecast.ingredients {
+                        console.write_line(&ingredient.to_string())?;
+                    }
+                    Ok(())
+                }
+            }
+            Command::ListRecipes => {
+                if self.json {
+                    self.write_json(console, recipes_json(machine))
+                } else {
+                    for (i, reciepe) in machine.reciepes.iter().enumerate() {
+                        let mut line = format!(
+                            "{}. {} ({})",
+                            i + 1,
+                            reciepe.name,
+                            format_price(reciepe.price)
+                        );
+                        if !reciepe.description.is_empty() {
+                            line.push_str(&format!(" - {}", reciepe.description));
+                        }
+                        console.write_line(&line)?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Write service records
+    fn write_records(
+        &self,
+        console: &mut dyn Console,
+        records: &[ServiceRecord],
+    ) -> Result<(), MachineError> {
+        if self.json {
+            self.write_json(console, records_json(records))
+        } else {
+            for record in records {
+                console.write_line(&record.to_string())?;
+            }
+            console.write_line("Service done.")
+        }
+    }
+
+    /// Write a JSON value on a single line
+    fn write_json(&self, console: &mut dyn Console, value: Value) -> Result<(), MachineError> {
+        console.write_line(&value.to_string())
+    }
+}
+
+/// Format an error as JSON
+///
+/// # Arguments
+///
+/// * `error` - The error to format
+///
+/// # Returns
+///
+/// A JSON object with the error message and the exit code
+///
+/// # Examples
+///
+/// ```
+/// use rusty_coffeemachine::cli::error_json;
+/// use rusty_coffeemachine::MachineError;
+/// let json = error_json(&MachineError::UnknownRecipe("Tea".to_string()));
+/// assert_eq!(json, r#"{"code":4,"error":"Unknown reciepe: Tea"}"#);
+/// ```
+pub fn error_json(error: &MachineError) -> String {
+    error_value(error).to_string()
+}
+
+/// Describe an error as a JSON value with the message and the exit code
+pub(crate) fn error_value(error: &MachineError) -> Value {
+    json!({ "error": error.to_string(), "code": error.exit_code() })
+}
+
+/// Describe a brewed beverage as JSON
+pub(crate) fn beverage_json(beverage: &Beverage) -> Value {
+    json!({
+        "name": beverage.name,
+        "ingredients": beverage.ingredients,
+        "brewed_at": unix_seconds(beverage.brewed_at),
+    })
+}
+
+/// Describe an order in the queue as JSON
+pub(crate) fn order_json(queue: &OrderQueue, queued: &QueuedOrder) -> Value {
+    let mut value = json!({
+        "id": queued.id,
+        "name": queued.name,
+        "priority": queued.priority.to_string(),
+        "status": queued.status.to_string(),
+    });
+    match &queued.status {
+        OrderStatus::Queued => value["position"] = json!(queue.position(queued.id)),
+        OrderStatus::Ready(beverage) => value["beverage"] = beverage_json(beverage),
+        OrderStatus::Failed(reason) => value["error"] = json!(reason),
+        OrderStatus::Brewing | OrderStatus::Cancelled => {}
+    }
+
+    value
+}
+
+/// Describe the reciepes of a coffee machine as JSON
+pub(crate) fn recipes_json(machine: &CoffeeMachine) -> Value {
+    let reciepes: Vec<Value> = machine
+        .reciepes
+        .iter()
+        .enumerate()
+        .map(|(i, reciepe)| {
+            json!({
+                "id": i + 1,
+                "name": reciepe.name,
+                "description": reciepe.description,
+                "price": format_price(reciepe.price),
+                "ingredients": reciepe.ingredients,
+                "steps": reciepe.steps,
+                "seconds": reciepe.duration().as_secs_f32(),
+            })
+        })
+        .collect();
+
+    Value::Array(reciepes)
+}
+
+/// Describe performed service actions as JSON
+pub(crate) fn records_json(records: &[ServiceRecord]) -> Value {
+    let records: Vec<Value> = records
+        .iter()
+        .map(|record| {
+            let action = match record.action {
+                ServiceAction::Refill(ingredient) => format!("refill {}", ingredient),
+                ServiceAction::EmptyGroundsBin => "empty grounds bin".to_string(),
+            };
+            json!({
+                "action": action,
+                "amount": record.amount,
+                "unit": record.unit.to_string(),
+            })
+        })
+        .collect();
+
+    json!({ "performed": records })
+}
+
+/// Describe the ingredient tanks with their stock levels as JSON
+pub(crate) fn ingredients_json(tanks: &IngredientTanks, thresholds: &StockThresholds) -> Value {
+    let mut ingredients = serde_json::Map::new();
+    for ingredient in Ingredient::ALL {
+        let tank = tanks.get(ingredient);
+        let mut value = container_json(tank);
+        value["stock"] = json!(thresholds.level(ingredient, tank.level).to_string());
+        ingredients.insert(ingredient.to_string(), value);
+    }
+
+    Value::Object(ingredients)
+}
+
+/// Describe the state, the containers and the counters of a coffee machine as JSON
+pub(crate) fn status_json(machine: &CoffeeMachine) -> Value {
+    let unavailable: Vec<Value> = machine
+        .unavailable_recipes()
+        .iter()
+        .map(|unavailable| {
+            let blocking: Vec<String> = unavailable
+                .shortfalls
+                .iter()
+                .map(|shortfall| shortfall.ingredient.to_string())
+                .collect();
+            json!({
+                "id": unavailable.id,
+                "name": unavailable.name,
+                "blocking": blocking,
+            })
+        })
+        .collect();
+    let counters = &machine.maintenance_counters;
+    let maintenance = json!({
+        "litres_since_descale": counters.litres_since_descale,
+        "descale_interval": machine.maintenance.descale_interval(),
+        "milk_drinks_since_clean": counters.milk_drinks_since_clean,
+        "milk_drinks_interval": machine.maintenance.milk_drinks,
+        "descaling": status_name(
+            machine.maintenance_status(MaintenanceTask::Descale)
+        ),
+        "milk_system_cleaning": status_name(
+            machine.maintenance_status(MaintenanceTask::CleanMilkSystem)
+        ),
+    });
+    json!({
+        "state": machine.state().to_string(),
+        "ingredients": ingredients_json(&machine.tanks, &machine.thresholds),
+        "coffee_grounds": container_json(&machine.garbage_container.coffee_grounds),
+        "counters": machine.counters,
+        "cash": format_price(machine.coins.total()),
+        "unavailable": unavailable,
+        "boiler": {
+            "temperature": machine.temperature().ok(),
+            "target": machine.target_temperature(),
+            "heated_up": machine.is_heated_up(),
+        },
+        "maintenance": maintenance,
+    })
+}
+
+/// Power on the coffee machine unless it is running already
+fn power_on(machine: &mut CoffeeMachine) -> Result<(), MachineError> {
+    if *machine.state() == MachineState::Off {
+        machine.power_on()?;
+    }
+
+    Ok(())
+}
+
+/// Get the journal of the coffee machine
+fn journal(machine: &CoffeeMachine) -> Result<&Journal, MachineError> {
+    machine.journal.as_ref().ok_or_else(no_journal)
+}
+
+/// Get the journal of the coffee machine to change it
+fn journal_mut(machine: &mut CoffeeMachine) -> Result<&mut Journal, MachineError> {
+    machine.journal.as_mut().ok_or_else(no_journal)
+}
+
+/// The error for a coffee machine without a journal
+fn no_journal() -> MachineError {
+    MachineError::InvalidInput(
+        "The coffee machine has no journal, choose one with --journal".to_string(),
+    )
+}
+
+/// Name a maintenance status
+fn status_name(status: MaintenanceStatus) -> &'static str {
+    match status {
+        MaintenanceStatus::Ok => "ok",
+        MaintenanceStatus::Due => "due",
+        MaintenanceStatus::Overdue => "overdue",
+    }
+}
+
+/// Describe a container as JSON
+pub(crate) fn container_json(container: &Container) -> Value {
+    json!({
+        "level": container.level,
+        "capacity": container.capacity,
+        "unit": container.unit.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::console::ScriptedConsole;
+    use crate::customization::Size;
+    use std::time::Duration;
+
+    fn execute(args: &[&str], machine: &mut CoffeeMachine) -> Result<String, MachineError> {
+        let cli = Cli::parse(args.iter().copied())?;
+        let mut console = ScriptedConsole::new(&[]);
+        cli.execute(machine, &mut console)?;
+        Ok(console.transcript().to_string())
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let cli = Cli::parse(["--state", "machine.json", "status"]).unwrap();
+        assert_eq!(cli.command, Command::Status);
+        assert_eq!(cli.state, Some(PathBuf::from("machine.json")));
+        assert!(!cli.json);
+        assert_eq!(
+            Cli::parse(["brew", "Hot", "Chocolate"]).unwrap().command,
+            Command::Brew("Hot Chocolate".into())
+        );
+        assert_eq!(
+            Cli::parse(["brew", "3", "--size", "large", "--extra-milk"])
+                .unwrap()
+                .command,
+            Command::Brew(Order::new(3).with_size(Size::Large).with_extra_milk())
+        );
+        assert_eq!(
+            Cli::parse(["refill", "water", "25.5"]).unwrap().command,
+            Command::Refill(Ingredient::Water, 25.5)
+        );
+        assert_eq!(
+            Cli::parse(["recipes", "list"]).unwrap().command,
+            Command::ListRecipes
+        );
+        assert_eq!(
+            Cli::parse(Vec::<String>::new()).unwrap().command,
+            Command::Interactive
+        );
+        assert_eq!(Cli::parse(["--help"]).unwrap().command, Command::Help);
+        assert!(Cli::parse(["--plain"]).unwrap().plain);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_parse_serve() {
+        let cli = Cli::parse(["serve"]).unwrap();
+        assert_eq!(cli.command, Command::Serve(crate::server::DEFAULT_PORT));
+        let cli = Cli::parse(["serve", "--port", "8000"]).unwrap();
+        assert_eq!(cli.command, Command::Serve(8000));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for args in [
+            vec!["brew"],
+            vec!["brew", "Latte", "--size", "huge"],
+            vec!["status", "--strength", "strong"],
+            vec!["refill", "honey", "10"],
+            vec!["refill", "water", "lots"],
+            vec!["dance"],
+            vec!["--verbose"],
+            vec!["--state"],
+            vec!["status", "--port", "8000"],
+            vec!["serve", "--port", "http"],
+            vec!["status", "--plain"],
+        ] {
+            match Cli::parse(args.clone()) {
+                Err(e) => assert_eq!(e.exit_code(), 2, "{:?}", args),
+                Ok(cli) => panic!("{:?} parsed as {:?}", args, cli),
+            }
+        }
+    }
+
+    #[test]
+    fn test_brew_and_status() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        assert_eq!(
+            execute(&["brew", "latte"], &mut machine).unwrap(),
+            "Latte ready to go, 3.00 charged to the card.\n"
+        );
+        let status = execute(&["status"], &mut machine).unwrap();
+        assert!(status.contains("Water: 70/100 ml\n"));
+        assert!(status.contains("Milk: 50/100 ml\n"));
+        assert!(status.contains("Coffee grounds: 30/100 g\n"));
+        assert!(status.contains("Beverages brewed: 1\n"));
+        assert!(status.contains("Cash: 38.50 (10x 2.00, "));
+        assert!(!status.contains("Unavailable"));
+
+        execute(&["brew", "latte"], &mut machine).unwrap();
+        let status = execute(&["status"], &mut machine).unwrap();
+        assert!(status.contains("Milk: 0/100 ml (critical)\n"));
+        assert!(status.contains("Unavailable: Cappuccino (out of milk)\n"));
+        assert!(status.contains("Unavailable: Latte (out of milk)\n"));
+    }
+
+    #[test]
+    fn test_brew_by_number_as_json() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let output = execute(&["--json", "brew", "2"], &mut machine).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value["name"], "Americano");
+        assert_eq!(value["ingredients"]["water"], 60.0);
+        assert_eq!(value["price"], 220);
+        assert_eq!(value["payment"], "card");
+    }
+
+    #[test]
+    fn test_brew_failure_exit_code() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.tanks.milk.level = 0.0;
+        let error = execute(&["brew", "Latte"], &mut machine).unwrap_err();
+        assert_eq!(error.exit_code(), 3);
+        let value: Value = serde_json::from_str(&error_json(&error)).unwrap();
+        assert_eq!(value["code"], 3);
+    }
+
+    #[test]
+    fn test_refill_and_service() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        execute(&["brew", "Mocha"], &mut machine).unwrap();
+        assert_eq!(
+            execute(&["refill", "sugar", "10"], &mut machine).unwrap(),
+            "Added 10 g sugar\nService done.\n"
+        );
+        let output = execute(&["service", "--json"], &mut machine).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        let performed = value["performed"].as_array().unwrap();
+        assert_eq!(performed.len(), 6);
+        assert_eq!(performed[0]["action"], "refill water");
+        assert_eq!(performed[5]["action"], "empty grounds bin");
+        assert_eq!(machine.counters.services, 1);
+    }
+
+    #[test]
+    fn test_status_as_json() {
+        let mut machine = CoffeeMachine::new().unwrap();
+        let output = execute(&["status", "--json"], &mut machine).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value["state"], "off");
+        assert_eq!(value["ingredients"]["milk"]["unit"], "ml");
+        assert_eq!(value["coffee_grounds"]["capacity"], 100.0);
+        assert_eq!(value["counters"]["beverages_brewed"], 0);
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let cli = Cli::parse([
+            "report",
+            "weekly",
+            "--date",
+            "2024-05-15",
+            "--format",
+            "csv",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Report {
+                period: Period::Weekly,
+                date: Some(parse_date("2024-05-15").unwrap()),
+                format: ReportFormat::Csv,
+            }
+        );
+        assert!(matches!(
+            Cli::parse(["--json", "report", "daily"]).unwrap().command,
+            Command::Report {
+                format: ReportFormat::Json,
+                ..
+            }
+        ));
+        assert!(Cli::parse(["status", "--date", "2024-05-15"]).is_err());
+        assert!(Cli::parse(["report", "monthly"]).is_err());
+    }
+
+    #[test]
+    fn test_report_of_today() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::starting_at(
+                parse_date("2024-05-15").unwrap() + Duration::from_secs(3600),
+            ));
+        execute(&["brew", "Espresso"], &mut machine).unwrap();
+        machine.tanks.milk.level = 0.0;
+        assert!(execute(&["brew", "Latte"], &mut machine).is_err());
+        let report = execute(&["report", "daily"], &mut machine).unwrap();
+        assert!(report.starts_with("Daily report 2024-05-15\n"));
+        assert!(report.contains("Espresso: 1 sold, 1.80\n"));
+        assert!(report.contains("Failed orders: 1\n  2024-05-15 01:00 Latte: Not enough"));
+    }
+
+    #[test]
+    fn test_forecast() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let output = execute(&["forecast"], &mut machine).unwrap();
+        assert!(output.starts_with("Espresso: 3 cups left, limited by water\n"));
+        assert!(output.contains("Water: 100 ml left, not used recently\n"));
+
+        execute(&["brew", "mocha"], &mut machine).unwrap();
+        let output = execute(&["forecast", "--json"], &mut machine).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value["recipes"][4]["cups"], 2);
+        assert_eq!(value["ingredients"][3]["remaining_seconds"], 8400);
+        assert_eq!(value["runs_out_first"], "water");
+    }
+
+    #[test]
+    fn test_queue() {
+        assert_eq!(
+            Cli::parse([
+                "queue",
+                "add",
+                "Latte",
+                "--priority",
+                "high",
+                "--size",
+                "small"
+            ])
+            .unwrap()
+            .command,
+            Command::QueueAdd(Order::new("Latte").with_size(Size::Small), Priority::High)
+        );
+        assert!(Cli::parse(["brew", "Latte", "--priority", "high"]).is_err());
+
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let output = execute(&["queue", "add", "Espresso"], &mut machine).unwrap();
+        assert_eq!(output, "Order #1 queued: Espresso, position 1\n");
+        let output = execute(
+            &["queue", "add", "4", "--priority", "high", "--json"],
+            &mut machine,
+        )
+        .unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value["name"], "Latte");
+        assert_eq!(value["position"], 1);
+        execute(&["queue", "add", "Mocha"], &mut machine).unwrap();
+        let output = execute(&["queue", "cancel", "#3"], &mut machine).unwrap();
+        assert_eq!(output, "Order #3 cancelled.\n");
+        let error = execute(&["queue", "cancel", "9"], &mut machine).unwrap_err();
+        assert_eq!(error.exit_code(), 14);
+
+        let output = execute(&["queue", "run"], &mut machine).unwrap();
+        assert_eq!(
+            output,
+            "#2 Latte (high priority): ready\n#1 Espresso: ready\n"
+        );
+        let output = execute(&["queue", "list"], &mut machine).unwrap();
+        assert_eq!(
+            output,
+            "#1 Espresso: ready\n#2 Latte (high priority): ready\n#3 Mocha: cancelled\n"
+        );
+        let output = execute(&["queue", "run"], &mut machine).unwrap();
+        assert_eq!(output, "The queue is empty\n");
+    }
+
+    #[test]
+    fn test_journal() {
+        let clock = VirtualClock::new();
+        let mut machine = CoffeeMachine::new().unwrap().with_clock(clock.clone());
+        assert!(execute(&["journal"], &mut machine).is_err());
+        machine = machine.with_journal(Journal::new());
+        execute(&["brew", "Latte"], &mut machine).unwrap();
+        clock.advance(std::time::Duration::from_secs(3600));
+        execute(&["refill", "milk", "50"], &mut machine).unwrap();
+
+        let output = execute(&["journal", "list"], &mut machine).unwrap();
+        assert!(output.contains("  Latte used 50 ml milk\n"));
+        assert!(output.ends_with("01:00  State changed to idle\n"));
+        let output = execute(&["journal", "levels", "1970-01-01", "00:30"], &mut machine).unwrap();
+        assert!(output.starts_with("Levels at 1970-01-01 00:30 (idle):\n"));
+        assert!(output.contains("Milk: 50/100 ml\n"));
+        assert!(output.contains("Coffee grounds: 30/100 g\n"));
+        assert!(execute(&["journal", "levels", "tomorrow"], &mut machine).is_err());
+
+        let output = execute(&["journal", "compact"], &mut machine).unwrap();
+        assert!(output.starts_with("Folded "));
+        let output = execute(
+            &["journal", "levels", "1970-01-01T02:00", "--json"],
+            &mut machine,
+        )
+        .unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value["ingredients"]["milk"]["level"], 100.0);
+        assert_eq!(value["state"], "idle");
+        let error = execute(&["journal", "levels", "1970-01-01"], &mut machine).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid input: The journal starts after 1970-01-01 00:00"
+        );
+    }
+
+    #[test]
+    fn test_maintain() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.maintenance_counters.milk_drinks_since_clean = 30;
+        let error = execute(&["brew", "Espresso"], &mut machine).unwrap_err();
+        assert_eq!(error.exit_code(), 12);
+        let output = execute(&["status"], &mut machine).unwrap();
+        assert!(output.contains("Milk system cleaning: 30/20 milk drinks (overdue)\n"));
+
+        let output = execute(&["maintain", "clean"], &mut machine).unwrap();
+        assert_eq!(output, "Milk system cleaning done.\n");
+        let output = execute(&["maintain", "descale", "--json"], &mut machine).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value["performed"], "descaling");
+        execute(&["brew", "Latte"], &mut machine).unwrap();
+
+        let output = execute(&["status", "--json"], &mut machine).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value["maintenance"]["milk_drinks_since_clean"], 1);
+        assert_eq!(value["maintenance"]["descaling"], "ok");
+        assert_eq!(value["counters"]["maintenance"], 2);
+        assert_eq!(value["counters"]["milk_drinks"], 1);
+        assert!(Cli::parse(["maintain", "polish"]).is_err());
+    }
+
+    #[test]
+    fn test_list_recipes() {
+        let mut machine = CoffeeMachine::new().unwrap();
+        let output = execute(&["recipes", "list"], &mut machine).unwrap();
+        assert!(output.starts_with("1. Espresso (1.80) - A short and strong shot of coffee\n"));
+        let output = execute(&["recipes", "list", "--json"], &mut machine).unwrap();
+        let value: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 5);
+        assert_eq!(value[4]["name"], "Mocha");
+        assert_eq!(value[4]["price"], "3.50");
+        assert_eq!(value[4]["steps"][3]["kind"], "add_cacao");
+        assert_eq!(value[4]["seconds"], 17.0);
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// The source of time for the coffee machine
+pub trait Clock: Debug + Send {
+    /// Get the current time
+    fn now(&self) -> SystemTime;
+
+    /// Wait for the given duration
+    fn sleep(&self, duration: Duration);
+}
+
+/// A clock using the system time and really sleeping
+#[derive(Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock advancing instantly when sleeping
+///
+/// Clones share the same time, so a clone kept by a test observes the time simulated by the
+/// machine.
+#[derive(Clone, Debug)]
+pub struct VirtualClock {
+    start: SystemTime,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl VirtualClock {
+    /// Create a new virtual clock starting at the unix epoch
+    ///
+    /// # Returns
+    ///
+    /// A new virtual clock
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// let clock = VirtualClock::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::UNIX_EPOCH)
+    }
+
+    /// Create a new virtual clock starting at the given time
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The time the clock starts at
+    ///
+    /// # Returns
+    ///
+    /// A new virtual clock
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// let clock = VirtualClock::starting_at(std::time::SystemTime::now());
+    /// ```
+    pub fn starting_at(start: SystemTime) -> Self {
+        VirtualClock {
+            start,
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Get the time simulated since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Advance the clock
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The duration to advance the clock by
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// let clock = VirtualClock::new();
+    /// clock.advance(Duration::from_secs(2));
+    /// assert_eq!(clock.elapsed(), Duration::from_secs(2));
+    /// ```
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// The time an operation of the coffee machine took
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperationTiming {
+    pub operation: String,
+    pub elapsed: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_virtual_clock_sleep() {
+        let clock = VirtualClock::new();
+        clock.sleep(Duration::from_millis(1500));
+        clock.sleep(Duration::from_millis(500));
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH + Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_virtual_clock_clones_share_time() {
+        let clock = VirtualClock::new();
+        let clone = clock.clone();
+        clone.sleep(Duration::from_secs(20));
+        assert_eq!(clock.elapsed(), Duration::from_secs(20));
+    }
+}
@@ -1,12 +1,59 @@
-use crate::containers::{GarbageContainer, IngredientsContainer};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::beverage::Beverage;
+use crate::boiler::{BREW_TEMPERATURE, HEAT_UP_TIMEOUT, POLL_INTERVAL, TEMPERATURE_TOLERANCE};
+use crate::clock::{Clock, OperationTiming, SystemClock};
+use crate::console::Console;
+use crate::containers::{
+    GarbageContainer, Ingredient, IngredientTanks, IngredientsContainer, Unit,
+};
+use crate::counters::Counters;
+use crate::customization::{Customization, Order, Size, Strength, EXTRA_PRICE};
+use crate::error::{MachineError, Shortfall};
+use crate::events::{Event, EventKind, Subscriber};
+use crate::forecast::{format_duration, Forecast};
+use crate::hardware::Hardware;
+use crate::journal::{Change, Journal, JournalState};
+use crate::ledger::{unix_seconds, Ledger, LedgerEntry};
+use crate::maintenance::{
+    MaintenanceConfig, MaintenanceCounters, MaintenanceStatus, MaintenanceTask,
+};
+use crate::payment::{
+    CardReader, CoinInventory, Payment, PaymentMethod, Sale, SimulatedCardReader,
+};
+use crate::persistence::SavedState;
+use crate::program::{self, Step, StepKind};
+use crate::queue::{OrderId, OrderQueue, OrderStatus, Priority};
 use crate::reciepes::Reciepes;
-use crate::{clean_terminal, get_input, print_line, ProgressBar};
+use crate::service::{ServiceAction, ServiceRecord};
+use crate::state::{MachineState, Operation};
+use crate::stock::{StockLevel, StockThresholds, StockWarning, UnavailableRecipe};
+use crate::{capitalize, format_price, parse_price, ProgressBar};
 
 #[derive(Debug)]
 pub struct CoffeeMachine {
-    pub ingredients_container: IngredientsContainer,
+    pub tanks: IngredientTanks,
     pub garbage_container: GarbageContainer,
     pub reciepes: Vec<Reciepes>,
+    pub timings: Vec<OperationTiming>,
+    pub service_log: Vec<ServiceRecord>,
+    pub counters: Counters,
+    pub coins: CoinInventory,
+    pub ledger: Ledger,
+    pub thresholds: StockThresholds,
+    pub maintenance: MaintenanceConfig,
+    pub maintenance_counters: MaintenanceCounters,
+    pub queue: OrderQueue,
+    pub journal: Option<Journal>,
+    state: MachineState,
+    state_file: Option<PathBuf>,
+    unsaved: bool,
+    clock: Box<dyn Clock>,
+    card_reader: Box<dyn CardReader>,
+    hardware: Hardware,
+    subscribers: Vec<Box<dyn Subscriber>>,
 }
 
 impl CoffeeMachine {
@@ -26,249 +73,448 @@ impl CoffeeMachine {
     /// use rusty_coffeemachine::CoffeeMachine;
     /// let machine = CoffeeMachine::new();
     /// ```
-    pub fn new() -> Result<Self, std::io::Error> {
+    pub fn new() -> Result<Self, MachineError> {
         let machine = CoffeeMachine {
-            ingredients_container: IngredientsContainer {
-                water: 100.0,
-                coffee: 100.0,
-                milk: 100.0,
-                sugar: 100.0,
-                cacao: 100.0,
-            },
-            garbage_container: GarbageContainer {
-                coffee_grounds: 0.0,
-            },
+            tanks: IngredientTanks::default(),
+            garbage_container: GarbageContainer::default(),
             reciepes: Reciepes::get_reciepes()?,
+            timings: Vec::new(),
+            service_log: Vec::new(),
+            counters: Counters::default(),
+            coins: CoinInventory::starting_float(),
+            ledger: Ledger::new(),
+            thresholds: StockThresholds::default(),
+            maintenance: MaintenanceConfig::default(),
+            maintenance_counters: MaintenanceCounters::default(),
+            queue: OrderQueue::new(),
+            journal: None,
+            state: MachineState::Off,
+            state_file: None,
+            unsaved: false,
+            clock: Box::new(SystemClock),
+            card_reader: Box::new(SimulatedCardReader::new()),
+            hardware: Hardware::default(),
+            subscribers: Vec::new(),
         };
 
         Ok(machine)
     }
 
-    /// Run the coffee machine
+    /// Use another clock
     ///
-    /// This function runs the coffee machine and allows the user to interact with it
+    /// All waiting of the coffee machine is done through this clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock to use
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The coffee machine using the clock
     ///
-    /// # Errors
+    /// # Examples
     ///
-    /// This function will return an error if running the coffee machine fails
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// ```
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Use another card reader
+    ///
+    /// # Arguments
+    ///
+    /// * `card_reader` - The card reader charging the card payments
+    ///
+    /// # Returns
+    ///
+    /// The coffee machine using the card reader
     ///
     /// # Examples
     ///
-    /// ```no_run
+    /// ```
+    /// use rusty_coffeemachine::payment::SimulatedCardReader;
     /// use rusty_coffeemachine::CoffeeMachine;
-    /// let mut machine = CoffeeMachine::new().unwrap();
-    /// machine.run().unwrap();
+    /// let machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_card_reader(SimulatedCardReader::with_balance(1000));
     /// ```
-    pub fn run(&mut self) -> Result<(), std::io::Error> {
-        clean_terminal()?;
-        self.start_up()?;
-        std::thread::sleep(std::time::Duration::from_millis(2000));
-        clean_terminal()?;
-        loop {
-            clean_terminal()?;
-            self.print_main_menu()?;
-            let choice = get_input()?
-                .parse::<usize>()
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
-            match choice {
-                1 => {
-                    clean_terminal()?;
-                    self.print_menu()?;
-                    let choice = get_input()?
-                        .parse::<usize>()
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
-                    clean_terminal()?;
-                    self.make_coffee(choice)?;
-                    std::thread::sleep(std::time::Duration::from_millis(2000));
-                    clean_terminal()?;
-                }
-                2 => {
-                    clean_terminal()?;
-                    self.print_ingredients()?;
-                    std::thread::sleep(std::time::Duration::from_millis(2000));
-                    clean_terminal()?;
-                }
-                3 => {
-                    clean_terminal()?;
-                    self.print_garbage()?;
-                    std::thread::sleep(std::time::Duration::from_millis(2000));
-                    clean_terminal()?;
-                }
-                4 => {
-                    clean_terminal()?;
-                    println!("Servicing...");
-                    self.take_service()?;
-                    self.draw_progress(200)?;
-                    println!("Service done.");
-                    std::thread::sleep(std::time::Duration::from_millis(2000));
-                    clean_terminal()?;
-                }
-                5 => {
-                    clean_terminal()?;
-                    println!("Shutting down...");
-                    self.draw_progress(50)?;
-                    clean_terminal()?;
-                    break;
-                }
-                _ => {
-                    println!("Invalid choice");
-                }
-            }
+    pub fn with_card_reader(mut self, card_reader: impl CardReader + 'static) -> Self {
+        self.card_reader = Box::new(card_reader);
+        self
+    }
+
+    /// Add a subscriber to the events of the coffee machine
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber` - The subscriber
+    ///
+    /// # Returns
+    ///
+    /// The coffee machine notifying the subscriber
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::events::ChannelSubscriber;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let (subscriber, events) = ChannelSubscriber::new();
+    /// let machine = CoffeeMachine::new().unwrap().with_subscriber(subscriber);
+    /// ```
+    pub fn with_subscriber(mut self, subscriber: impl Subscriber + 'static) -> Self {
+        self.subscribe(subscriber);
+        self
+    }
+
+    /// Add a subscriber to the events of a coffee machine that is already in use
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber` - The subscriber
+    pub fn subscribe(&mut self, subscriber: impl Subscriber + 'static) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// Notify the subscribers of an event, dropping the ones that disconnected
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What happened
+    fn emit(&mut self, kind: EventKind) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let event = Event {
+            kind,
+            at: self.clock.now(),
+        };
+        for subscriber in &mut self.subscribers {
+            subscriber.notify(&event);
         }
+        self.subscribers
+            .retain(|subscriber| subscriber.is_connected());
+    }
 
-        Ok(())
+    /// Record a change in the journal, if the coffee machine has one
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - The change
+    fn record_change(&mut self, change: Change) {
+        if let Some(journal) = &mut self.journal {
+            journal.record(self.clock.now(), change);
+        }
     }
 
-    /// Start up the coffee machine
+    /// Get the levels and the state as recorded in the journal
+    fn journal_state(&self) -> JournalState {
+        JournalState {
+            tanks: self.tanks.clone(),
+            garbage_container: self.garbage_container.clone(),
+            state: self.state.clone(),
+        }
+    }
+
+    /// Use other hardware than the simulated one
     ///
-    /// This function prints the starts up the coffee machine to the terminal.
+    /// # Arguments
+    ///
+    /// * `hardware` - The devices the coffee machine brews with
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The coffee machine using the hardware
     ///
-    /// # Errors
+    /// # Examples
     ///
-    /// This function will return an error if writing to the terminal fails
-    fn start_up(&self) -> Result<(), std::io::Error> {
-        println!("Welcome to the coffee machine");
-        println!("Starting machine...");
-        self.draw_progress(50)?;
-        println!("Machine ready.");
+    /// ```
+    /// use rusty_coffeemachine::hardware::SimulatedHardware;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_hardware(SimulatedHardware::new().hardware());
+    /// ```
+    pub fn with_hardware(mut self, hardware: Hardware) -> Self {
+        self.hardware = hardware;
+        self
+    }
 
-        Ok(())
+    /// Use other reciepes than the built-in ones
+    ///
+    /// # Arguments
+    ///
+    /// * `reciepes` - The reciepes offered by the coffee machine
+    ///
+    /// # Returns
+    ///
+    /// The coffee machine offering the reciepes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::recipe_book::{parse, RecipeFormat};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let reciepes = parse(
+    ///     "[[recipes]]\nname = \"Ristretto\"\ningredients = { water = 15.0, coffee = 30.0 }",
+    ///     RecipeFormat::Toml,
+    /// )
+    /// .unwrap();
+    /// let machine = CoffeeMachine::new().unwrap().with_recipes(reciepes);
+    /// assert_eq!(machine.reciepes.len(), 1);
+    /// ```
+    pub fn with_recipes(mut self, reciepes: Vec<Reciepes>) -> Self {
+        self.reciepes = reciepes;
+        self
     }
 
-    /// Draw a progress bar
+    /// Persist the coffee machine in a state file
     ///
-    /// This function draws a progress bar to the terminal
+    /// If the state file exists, the coffee machine is restored from it. After every brew and
+    /// service the state file is written again.
     ///
     /// # Arguments
     ///
-    /// * `duration` - The duration of the progress bar
+    /// * `path` - The path of the state file
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The coffee machine persisted in the state file
     ///
     /// # Errors
     ///
-    /// This function will return an error if writing to the terminal fails
-    fn draw_progress(&self, duration: u64) -> Result<(), std::io::Error> {
-        let mut progress_bar = ProgressBar::new(100.0);
-        for i in 0..=100 {
-            let _ = progress_bar.set_progress(i as f32);
-            progress_bar.draw()?;
-            std::thread::sleep(std::time::Duration::from_millis(duration));
+    /// This function will return an error if the state file exists but can't be read or is corrupt
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_state_file("state.json".as_ref())
+    ///     .unwrap();
+    /// ```
+    pub fn with_state_file(mut self, path: &Path) -> Result<Self, MachineError> {
+        if path.exists() {
+            self.restore(SavedState::load(path)?);
         }
-        println!();
+        self.state_file = Some(path.to_path_buf());
 
-        Ok(())
+        Ok(self)
     }
 
-    /// Print the main menu
+    /// Record the orders in a ledger file
     ///
-    /// This function prints the main menu to the terminal
+    /// The entries already in the ledger file are loaded, every further order is appended to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the ledger file
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The coffee machine recording its orders in the ledger file
     ///
     /// # Errors
     ///
-    /// This function will return an error if writing to the terminal fails
-    fn print_main_menu(&self) -> Result<(), std::io::Error> {
-        print_line()?;
-        println!("Choose your choice");
-        print_line()?;
-        println!("1. Make coffee");
-        println!("2. Check ingredients");
-        println!("3. Check garbage");
-        println!("4. Service");
-        println!("5. Exit");
-        print_line()?;
+    /// This function will return an error if the ledger file exists but can't be read or is corrupt
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_ledger_file("ledger.jsonl".as_ref())
+    ///     .unwrap();
+    /// ```
+    pub fn with_ledger_file(mut self, path: &Path) -> Result<Self, MachineError> {
+        self.ledger = Ledger::open(path)?;
 
-        Ok(())
+        Ok(self)
     }
 
-    /// Print the coffee menu
+    /// Record every change of the levels and the state in a journal
     ///
-    /// This function prints the coffee menu to the terminal
+    /// If the journal has entries already, the levels are restored by replaying them, otherwise
+    /// it starts with a snapshot of the current levels. Set the clock before, the entries are
+    /// recorded with its time.
+    ///
+    /// # Arguments
+    ///
+    /// * `journal` - The journal
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The coffee machine recording its changes in the journal
     ///
-    /// # Errors
+    /// # Examples
     ///
-    /// This function will return an error if writing to the terminal fails
-    fn print_menu(&self) -> Result<(), std::io::Error> {
-        print_line()?;
-        println!("Choose a coffee:");
-        print_line()?;
-        for (i, reciepe) in self.reciepes.iter().enumerate() {
-            println!("{}. {}", i + 1, reciepe.name);
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::journal::Journal;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_clock(VirtualClock::new())
+    ///     .with_journal(Journal::new());
+    /// machine.power_on().unwrap();
+    /// machine.brew("Latte").unwrap();
+    /// let journal = machine.journal.as_ref().unwrap();
+    /// assert_eq!(journal.replay().unwrap().tanks.milk.level, 50.0);
+    /// ```
+    pub fn with_journal(mut self, mut journal: Journal) -> Self {
+        let now = self.clock.now();
+        match journal.replay() {
+            Some(replayed) => {
+                self.tanks = replayed.tanks;
+                self.garbage_container = replayed.garbage_container;
+                if replayed.state != self.state {
+                    let state = self.state.clone();
+                    journal.record(now, Change::StateChanged { state });
+                }
+            }
+            None => journal.record(now, Change::Snapshot(self.journal_state())),
         }
-        print_line()?;
-
-        Ok(())
+        self.journal = Some(journal);
+        self
     }
 
-    /// Print the ingredients
+    /// Record every change of the levels and the state in a journal file
     ///
-    /// This function prints the ingredients to the terminal
+    /// See [`CoffeeMachine::with_journal`], the levels in the journal file take precedence over
+    /// the ones in the state file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the journal file
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The coffee machine recording its changes in the journal file
     ///
     /// # Errors
     ///
-    /// This function will return an error if writing to the terminal fails
-    fn print_ingredients(&self) -> Result<(), std::io::Error> {
-        print_line()?;
-        println!("Ingredients:");
-        println!("Water: {}", self.ingredients_container.water);
-        println!("Coffee: {}", self.ingredients_container.coffee);
-        println!("Milk: {}", self.ingredients_container.milk);
-        println!("Sugar: {}", self.ingredients_container.sugar);
-        println!("Cacao: {}", self.ingredients_container.cacao);
-        print_line()?;
-
-        Ok(())
+    /// This function will return an error if the journal file exists but can't be read or is
+    /// corrupt
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_journal_file("journal.jsonl".as_ref())
+    ///     .unwrap();
+    /// ```
+    pub fn with_journal_file(self, path: &Path) -> Result<Self, MachineError> {
+        Ok(self.with_journal(Journal::open(path)?))
     }
 
-    /// Print the garbage
+    /// Use other low-stock thresholds
     ///
-    /// This function prints the garbage to the terminal
+    /// # Arguments
+    ///
+    /// * `thresholds` - The levels below which the ingredients are reported as running low
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The coffee machine using the thresholds
     ///
-    /// # Errors
+    /// # Examples
     ///
-    /// This function will return an error if writing to the terminal fails
-    fn print_garbage(&self) -> Result<(), std::io::Error> {
-        print_line()?;
-        println!("Garbage:");
-        println!("Coffee grounds: {}", self.garbage_container.coffee_grounds);
-        print_line()?;
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::containers::Ingredient;
+    /// use rusty_coffeemachine::stock::StockThresholds;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let thresholds = StockThresholds::default()
+    ///     .with_threshold(Ingredient::Milk, 60.0, 20.0)
+    ///     .unwrap();
+    /// let mut machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_clock(VirtualClock::new())
+    ///     .with_thresholds(thresholds);
+    /// machine.power_on().unwrap();
+    /// machine.brew("Latte").unwrap();
+    /// assert_eq!(machine.stock_warnings()[0].ingredient, Ingredient::Milk);
+    /// ```
+    pub fn with_thresholds(mut self, thresholds: StockThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
 
-        Ok(())
+    /// Use other maintenance intervals
+    ///
+    /// # Arguments
+    ///
+    /// * `maintenance` - The water hardness and the maintenance intervals
+    ///
+    /// # Returns
+    ///
+    /// The coffee machine using the maintenance intervals
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::maintenance::{MaintenanceConfig, WaterHardness};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new().unwrap().with_maintenance(MaintenanceConfig {
+    ///     hardness: WaterHardness::VeryHard,
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(machine.maintenance.descale_interval(), 20.0);
+    /// ```
+    pub fn with_maintenance(mut self, maintenance: MaintenanceConfig) -> Self {
+        self.maintenance = maintenance;
+        self
     }
 
-    /// Make a coffee
+    /// Get the part of the coffee machine that survives a restart
+    ///
+    /// # Returns
     ///
-    /// This function makes a coffee
+    /// The state to save
+    pub fn saved_state(&self) -> SavedState {
+        SavedState {
+            tanks: self.tanks.clone(),
+            garbage_container: self.garbage_container.clone(),
+            counters: self.counters.clone(),
+            reciepes: self.reciepes.clone(),
+            coins: self.coins.clone(),
+            thresholds: self.thresholds.clone(),
+            maintenance: self.maintenance.clone(),
+            maintenance_counters: self.maintenance_counters.clone(),
+            queue: self.queue.clone(),
+        }
+    }
+
+    /// Restore the coffee machine from a saved state
     ///
     /// # Arguments
     ///
-    /// * `choice` - The choice of coffee to make
+    /// * `state` - The saved state
+    pub fn restore(&mut self, state: SavedState) {
+        self.tanks = state.tanks;
+        self.garbage_container = state.garbage_container;
+        self.counters = state.counters;
+        self.reciepes = state.reciepes;
+        self.coins = state.coins;
+        self.thresholds = state.thresholds;
+        self.maintenance = state.maintenance;
+        self.maintenance_counters = state.maintenance_counters;
+        self.queue = state.queue;
+        self.queue.interrupt();
+        self.record_change(Change::Snapshot(self.journal_state()));
+    }
+
+    /// Write the state file, if the coffee machine has one
+    ///
+    /// Journal entries that could not be written yet are written as well.
     ///
     /// # Returns
     ///
@@ -276,84 +522,127 @@ impl CoffeeMachine {
     ///
     /// # Errors
     ///
-    /// This function will return an error if making the coffee fails
-    fn make_coffee(&mut self, choice: usize) -> Result<(), std::io::Error> {
-        let reciepe = &self.reciepes.clone()[choice - 1];
-        if self.check_ingredients(&reciepe.ingredients)? {
-            self.use_ingredients(&reciepe.ingredients)?;
-            println!("Make your {}", reciepe.name);
-            self.draw_progress(100)?;
-            println!("{} ready to go.", reciepe.name);
-        } else {
-            println!("Not enough ingredients");
+    /// This function will return an error if writing the state file fails
+    pub fn save(&mut self) -> Result<(), MachineError> {
+        if let Some(journal) = &mut self.journal {
+            journal.flush()?;
+        }
+        match &self.state_file {
+            Some(path) => self.saved_state().save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the state file again if saving it after an operation failed
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing the state file fails
+    pub fn flush_state(&mut self) -> Result<(), MachineError> {
+        if self.unsaved {
+            self.save()?;
+            self.unsaved = false;
         }
 
         Ok(())
     }
 
-    /// Check if there are enough ingredients
+    /// Save the state file after an operation that is done either way
     ///
-    /// This function checks if there are enough ingredients to make a coffee
+    /// A failure doesn't undo the operation, the state file is written again by the next save and
+    /// the failure is reported by [`CoffeeMachine::flush_state`].
+    fn save_after(&mut self) {
+        self.unsaved = self.save().is_err();
+    }
+
+    /// Get the clock of the coffee machine
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Get the current state of the coffee machine
+    pub fn state(&self) -> &MachineState {
+        &self.state
+    }
+
+    /// Change the state of the coffee machine
     ///
     /// # Arguments
     ///
-    /// * `ingredients` - The ingredients needed to make a coffee
+    /// * `operation` - The operation changing the state
     ///
     /// # Returns
     ///
-    /// A boolean indicating if there are enough ingredients
+    /// An empty result
     ///
     /// # Errors
     ///
-    /// This function will return an error if checking the ingredients fails
-    fn check_ingredients(
-        &self,
-        ingredients: &IngredientsContainer,
-    ) -> Result<bool, std::io::Error> {
-        let result = self.ingredients_container.water >= ingredients.water
-            && self.ingredients_container.coffee >= ingredients.coffee
-            && self.ingredients_container.milk >= ingredients.milk
-            && self.ingredients_container.sugar >= ingredients.sugar
-            && self.ingredients_container.cacao >= ingredients.cacao;
+    /// This function will return an error if the operation is not allowed in the current state
+    fn transition(&mut self, operation: Operation) -> Result<(), MachineError> {
+        let state = self.state.transition(&operation)?;
+        if state != self.state {
+            let from = std::mem::replace(&mut self.state, state);
+            self.record_change(Change::StateChanged {
+                state: self.state.clone(),
+            });
+            self.emit(EventKind::StateChanged {
+                from,
+                to: self.state.clone(),
+            });
+        }
 
-        Ok(result)
+        Ok(())
     }
 
-    /// Use ingredients
+    /// Perform an action framed by a start and a finish operation
     ///
-    /// This function uses ingredients to make a coffee
+    /// The finish operation is performed even if the action fails, so a failed action doesn't
+    /// leave the coffee machine stuck in the intermediate state. A hardware fault puts the coffee
+    /// machine into the error state instead, until it is reset. Successful actions are saved to
+    /// the state file, a failing save doesn't undo them.
     ///
     /// # Arguments
     ///
-    /// * `ingredients` - The ingredients to use
+    /// * `start` - The operation performed before the action
+    /// * `finish` - The operation performed after the action
+    /// * `action` - The action to perform
     ///
     /// # Returns
     ///
-    /// An empty result
+    /// The result of the action
     ///
     /// # Errors
     ///
-    /// This function will return an error if using the ingredients fails
-    fn use_ingredients(
+    /// This function will return an error if the start operation is not allowed or the action
+    /// fails
+    fn perform<T>(
         &mut self,
-        ingredients: &IngredientsContainer,
-    ) -> Result<(), std::io::Error> {
-        self.ingredients_container.water -= ingredients.water;
-        self.ingredients_container.coffee -= ingredients.coffee;
-        self.ingredients_container.milk -= ingredients.milk;
-        self.ingredients_container.sugar -= ingredients.sugar;
-        self.ingredients_container.cacao -= ingredients.cacao;
-
-        if ingredients.coffee > 0.0 {
-            self.garbage_container.coffee_grounds += ingredients.coffee;
+        start: Operation,
+        finish: Operation,
+        action: impl FnOnce(&mut Self) -> Result<T, MachineError>,
+    ) -> Result<T, MachineError> {
+        self.transition(start)?;
+        let result = action(self);
+        match &result {
+            Err(MachineError::HardwareFault(reason)) => {
+                self.transition(Operation::Fail(reason.clone()))?
+            }
+            _ => self.transition(finish)?,
         }
-
-        Ok(())
+        if result.is_ok() {
+            self.save_after();
+        }
+        result
     }
 
-    /// Take service
+    /// Power on the coffee machine
     ///
-    /// This function takes the service of the coffee machine, refilling the ingredients and emptying the garbage
+    /// This function heats up the boiler to the brewing temperature without writing to the
+    /// terminal, waiting on the clock of the coffee machine.
     ///
     /// # Returns
     ///
@@ -361,32 +650,2517 @@ impl CoffeeMachine {
     ///
     /// # Errors
     ///
-    /// This function will return an error if taking the service fails
-    fn take_service(&mut self) -> Result<(), std::io::Error> {
-        self.ingredients_container.water = 100.0;
-        self.ingredients_container.coffee = 100.0;
-        self.ingredients_container.milk = 100.0;
-        self.ingredients_container.sugar = 100.0;
-        self.ingredients_container.cacao = 100.0;
-        self.garbage_container.coffee_grounds = 0.0;
-
-        Ok(())
+    /// This function will return an error if the coffee machine is not switched off or the boiler
+    /// fails
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::state::MachineState;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let clock = VirtualClock::new();
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(clock.clone());
+    /// machine.power_on().unwrap();
+    /// assert_eq!(*machine.state(), MachineState::Idle);
+    /// assert!(machine.temperature().unwrap() >= 92.5);
+    /// assert_eq!(clock.elapsed().as_secs(), 20);
+    /// ```
+    pub fn power_on(&mut self) -> Result<(), MachineError> {
+        self.transition(Operation::PowerOn)?;
+        self.heat_up(|machine| machine.wait_for_temperature(BREW_TEMPERATURE))?;
+        self.transition(Operation::Ready)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn test_coffee_machine_new() {
-        let machine = CoffeeMachine::new().unwrap();
-        assert_eq!(machine.ingredients_container.water, 100.0);
-        assert_eq!(machine.ingredients_container.coffee, 100.0);
-        assert_eq!(machine.ingredients_container.milk, 100.0);
-        assert_eq!(machine.ingredients_container.sugar, 100.0);
-        assert_eq!(machine.ingredients_container.cacao, 100.0);
-        assert_eq!(machine.garbage_container.coffee_grounds, 0.0);
+    /// Switch the boiler on and wait for it to heat up
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - The action waiting for the boiler
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the boiler fails, which puts the coffee machine into
+    /// the error state
+    fn heat_up(
+        &mut self,
+        wait: impl FnOnce(&mut Self) -> Result<(), MachineError>,
+    ) -> Result<(), MachineError> {
+        let now = self.clock.now();
+        let heated = self
+            .hardware
+            .boiler
+            .switch_on(now)
+            .and_then(|()| wait(self));
+        match heated {
+            Err(MachineError::HardwareFault(reason)) => {
+                self.transition(Operation::Fail(reason.clone()))?;
+                Err(MachineError::HardwareFault(reason))
+            }
+            heated => heated,
+        }
+    }
+
+    /// Get the water temperature of the boiler
+    ///
+    /// # Returns
+    ///
+    /// The temperature in °C
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the boiler can't be read
+    pub fn temperature(&self) -> Result<f32, MachineError> {
+        self.hardware.boiler.temperature(self.clock.now())
+    }
+
+    /// Get the temperature the thermostat of the boiler holds
+    ///
+    /// # Returns
+    ///
+    /// The temperature in °C
+    pub fn target_temperature(&self) -> f32 {
+        self.hardware.boiler.target()
+    }
+
+    /// Check if the boiler is heated up
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating if the heater is on and the boiler is at its target temperature
+    pub fn is_heated_up(&self) -> bool {
+        self.hardware.boiler.is_on()
+            && self.temperature().is_ok_and(|temperature| {
+                temperature >= self.target_temperature() - TEMPERATURE_TOLERANCE
+            })
+    }
+
+    /// Wait on the clock until the boiler reaches a temperature
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The temperature in °C, which the thermostat holds from now on
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the boiler can't be read or doesn't reach the
+    /// temperature in time
+    fn wait_for_temperature(&mut self, target: f32) -> Result<(), MachineError> {
+        let started = self.clock.now();
+        self.hardware.boiler.set_target(target, started);
+        while self.temperature()? < target - TEMPERATURE_TOLERANCE {
+            let waited = self.clock.now().duration_since(started).unwrap_or_default();
+            if waited >= HEAT_UP_TIMEOUT {
+                return Err(MachineError::HardwareFault(format!(
+                    "The boiler doesn't reach {} °C",
+                    target
+                )));
+            }
+            self.clock.sleep(POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Show the boiler heating up to a temperature
+    ///
+    /// This function only draws the progress while the boiler heats up, failures are left to the
+    /// operation needing the temperature.
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    /// * `target` - The temperature in °C, which the thermostat holds from now on
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn heat_from_console(
+        &mut self,
+        console: &mut dyn Console,
+        target: f32,
+    ) -> Result<(), MachineError> {
+        let started = self.clock.now();
+        self.hardware.boiler.set_target(target, started);
+        let Ok(start) = self.temperature() else {
+            return Ok(());
+        };
+        if start >= target - TEMPERATURE_TOLERANCE {
+            return Ok(());
+        }
+        console.write_line(&format!("Heating up to {:.1} °C...", target))?;
+        let mut progress_bar = ProgressBar::new(100.0);
+        while let Ok(temperature) = self.temperature() {
+            let progress = (temperature - start) / (target - start) * 100.0;
+            let _ = progress_bar.set_progress(progress.clamp(0.0, 100.0));
+            console.draw_progress(&progress_bar)?;
+            let waited = self.clock.now().duration_since(started).unwrap_or_default();
+            if temperature >= target - TEMPERATURE_TOLERANCE || waited >= HEAT_UP_TIMEOUT {
+                break;
+            }
+            self.clock.sleep(POLL_INTERVAL);
+        }
+        console.write_line("")
+    }
+
+    /// Power off the coffee machine
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is busy
+    pub fn power_off(&mut self) -> Result<(), MachineError> {
+        self.transition(Operation::PowerOff)?;
+        self.hardware.boiler.switch_off(self.clock.now());
+
+        Ok(())
+    }
+
+    /// Reset the coffee machine after an error
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not in the error state
+    pub fn reset(&mut self) -> Result<(), MachineError> {
+        self.transition(Operation::Reset)
+    }
+
+    /// Record the time an operation took
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The name of the operation
+    /// * `started` - The time the operation started
+    fn record_timing(&mut self, operation: &str, started: SystemTime) {
+        let elapsed = self.clock.now().duration_since(started).unwrap_or_default();
+        self.timings.push(OperationTiming {
+            operation: operation.to_string(),
+            elapsed,
+        });
+    }
+
+    /// Run the coffee machine
+    ///
+    /// This function runs the coffee machine and allows the user to interact with it
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if running the coffee machine fails
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::console::StdConsole;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap();
+    /// machine.run(&mut StdConsole).unwrap();
+    /// ```
+    pub fn run(&mut self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.clear()?;
+        self.start_up(console)?;
+        self.clock.sleep(Duration::from_millis(2000));
+        console.clear()?;
+        loop {
+            console.clear()?;
+            self.print_main_menu(console)?;
+            let choice = console.read_line()?.parse::<usize>().unwrap_or(0);
+            match choice {
+                1 => {
+                    console.clear()?;
+                    self.print_menu(console)?;
+                    let choice = console.read_line()?.parse::<usize>().unwrap_or(0);
+                    console.clear()?;
+                    match self.make_coffee(console, choice) {
+                        Err(MachineError::Io(e)) => return Err(MachineError::Io(e)),
+                        Err(e) => console.write_line(&e.to_string())?,
+                        Ok(()) => {}
+                    }
+                    self.clock.sleep(Duration::from_millis(2000));
+                    console.clear()?;
+                }
+                2 => {
+                    console.clear()?;
+                    self.print_ingredients(console)?;
+                    self.clock.sleep(Duration::from_millis(2000));
+                    console.clear()?;
+                }
+                3 => {
+                    console.clear()?;
+                    self.print_garbage(console)?;
+                    self.clock.sleep(Duration::from_millis(2000));
+                    console.clear()?;
+                }
+                4 => {
+                    console.clear()?;
+                    self.service_menu(console)?;
+                    console.clear()?;
+                }
+                5 => {
+                    console.clear()?;
+                    self.print_forecast(console)?;
+                    self.clock.sleep(Duration::from_millis(2000));
+                    console.clear()?;
+                }
+                6 => {
+                    console.clear()?;
+                    self.queue_menu(console)?;
+                    console.clear()?;
+                }
+                7 => {
+                    console.clear()?;
+                    let started = self.clock.now();
+                    console.write_line("Shutting down...")?;
+                    self.power_off()?;
+                    self.draw_progress(console, 50)?;
+                    self.record_timing("Shut down", started);
+                    console.clear()?;
+                    break;
+                }
+                _ => {
+                    console.write_line("Invalid choice")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Start up the coffee machine
+    ///
+    /// This function prints the starts up the coffee machine to the terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    pub(crate) fn start_up(&mut self, console: &mut dyn Console) -> Result<(), MachineError> {
+        let started = self.clock.now();
+        console.write_line("Welcome to the coffee machine")?;
+        console.write_line("Starting machine...")?;
+        self.transition(Operation::PowerOn)?;
+        self.heat_up(|machine| {
+            machine.heat_from_console(console, BREW_TEMPERATURE)?;
+            machine.wait_for_temperature(BREW_TEMPERATURE)
+        })?;
+        self.transition(Operation::Ready)?;
+        if self.maintenance.rinse_at_startup {
+            self.maintain_from_console(console, MaintenanceTask::Rinse)?;
+        }
+        console.write_line("Machine ready.")?;
+        self.record_timing("Start up", started);
+
+        Ok(())
+    }
+
+    /// Draw a progress bar
+    ///
+    /// This function draws a progress bar to the terminal
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    /// * `duration` - The duration of the progress bar
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn draw_progress(&self, console: &mut dyn Console, duration: u64) -> Result<(), MachineError> {
+        let mut progress_bar = ProgressBar::new(100.0);
+        for i in 0..=100 {
+            let _ = progress_bar.set_progress(i as f32);
+            console.draw_progress(&progress_bar)?;
+            self.clock.sleep(Duration::from_millis(duration));
+        }
+        console.write_line("")?;
+
+        Ok(())
+    }
+
+    /// Draw on a console while it can be written to
+    ///
+    /// A console that can't be written to is dropped, so a broken terminal stops the drawing
+    /// without stopping the brewing.
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to draw on, if any
+    /// * `drawing` - Draws on the console
+    fn draw(
+        console: &mut Option<&mut dyn Console>,
+        drawing: impl FnOnce(&mut dyn Console) -> Result<(), MachineError>,
+    ) {
+        if let Some(screen) = console.as_deref_mut() {
+            if drawing(screen).is_err() {
+                *console = None;
+            }
+        }
+    }
+
+    /// Print the main menu
+    ///
+    /// This function prints the main menu to the terminal
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn print_main_menu(&self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.print_line()?;
+        console.write_line(&format!("State: {}", self.state))?;
+        if let Ok(temperature) = self.temperature() {
+            console.write_line(&format!("Boiler: {:.1} °C", temperature))?;
+        }
+        for warning in self.stock_warnings() {
+            console.write_line(&format!("Warning: {}", warning))?;
+        }
+        for task in MaintenanceTask::ALL {
+            match self.maintenance_status(task) {
+                MaintenanceStatus::Ok => {}
+                MaintenanceStatus::Due => {
+                    console.write_line(&format!("Maintenance: {} is due", task))?
+                }
+                MaintenanceStatus::Overdue => console.write_line(&format!(
+                    "Maintenance: {} is overdue, brewing is blocked",
+                    task
+                ))?,
+            }
+        }
+        console.write_line("Choose your choice")?;
+        console.print_line()?;
+        console.write_line("1. Make coffee")?;
+        console.write_line("2. Check ingredients")?;
+        console.write_line("3. Check garbage")?;
+        console.write_line("4. Service")?;
+        console.write_line("5. Forecast")?;
+        console.write_line("6. Order queue")?;
+        console.write_line("7. Exit")?;
+        console.print_line()?;
+
+        Ok(())
+    }
+
+    /// Print the coffee menu
+    ///
+    /// This function prints the coffee menu to the terminal
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn print_menu(&self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.print_line()?;
+        console.write_line("Choose a coffee:")?;
+        console.print_line()?;
+        let unavailable = self.unavailable_recipes();
+        for (i, reciepe) in self.reciepes.iter().enumerate() {
+            match unavailable
+                .iter()
+                .find(|unavailable| unavailable.id == i + 1)
+            {
+                Some(unavailable) => console.write_line(&format!(
+                    "{}. {} - unavailable, out of {}",
+                    i + 1,
+                    reciepe.name,
+                    unavailable.blocking()
+                ))?,
+                None => console.write_line(&format!("{}. {}", i + 1, reciepe.name))?,
+            }
+        }
+        console.print_line()?;
+
+        Ok(())
+    }
+
+    /// Print the service menu
+    ///
+    /// This function prints the service menu to the terminal
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn print_service_menu(&self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.print_line()?;
+        console.write_line("Choose a service:")?;
+        console.print_line()?;
+        console.write_line("1. Refill water")?;
+        console.write_line("2. Add coffee beans")?;
+        console.write_line("3. Top up milk")?;
+        console.write_line("4. Add sugar")?;
+        console.write_line("5. Add cacao")?;
+        console.write_line("6. Empty grounds bin")?;
+        console.write_line("7. Full service")?;
+        console.write_line("8. Descale")?;
+        console.write_line("9. Clean milk system")?;
+        console.write_line("10. Rinse")?;
+        console.write_line("11. Back")?;
+        console.print_line()?;
+
+        Ok(())
+    }
+
+    /// Print the order queue menu
+    ///
+    /// This function prints the waiting orders, the next one first, and the queue options to the
+    /// terminal
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn print_queue_menu(&self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.print_line()?;
+        let pending = self.queue.pending();
+        if pending.is_empty() {
+            console.write_line("The queue is empty")?;
+        }
+        for (position, queued) in pending.iter().enumerate() {
+            console.write_line(&format!("{}. {}", position + 1, queued))?;
+        }
+        console.print_line()?;
+        console.write_line("1. Add an order")?;
+        console.write_line("2. Cancel an order")?;
+        console.write_line("3. Brew the next order")?;
+        console.write_line("4. Brew all orders")?;
+        console.write_line("5. Back")?;
+        console.print_line()?;
+
+        Ok(())
+    }
+
+    /// Print the ingredients
+    ///
+    /// This function prints the ingredients to the terminal
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn print_ingredients(&self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.print_line()?;
+        console.write_line("Ingredients:")?;
+        for ingredient in Ingredient::ALL {
+            let tank = self.tanks.get(ingredient);
+            let mut line = format!("{}: {}", capitalize(&ingredient.to_string()), tank);
+            match self.thresholds.level(ingredient, tank.level) {
+                StockLevel::Ok => {}
+                level => line.push_str(&format!(" ({})", level)),
+            }
+            console.write_line(&line)?;
+        }
+        console.print_line()?;
+
+        Ok(())
+    }
+
+    /// Print the availability forecast
+    ///
+    /// This function prints how many cups of every reciepe can still be made and when the
+    /// ingredients run out at the recent consumption
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn print_forecast(&self, console: &mut dyn Console) -> Result<(), MachineError> {
+        let forecast = self.forecast();
+        console.print_line()?;
+        console.write_line("Cups left:")?;
+        for recipe in &forecast.recipes {
+            console.write_line(&recipe.to_string())?;
+        }
+        console.print_line()?;
+        console.write_line("Ingredients:")?;
+        for ingredient in &forecast.ingredients {
+            console.write_line(&ingredient.to_string())?;
+        }
+        if let Some(first) = forecast.runs_out_first() {
+            console.write_line(&format!(
+                "{} runs out first, refill within {}",
+                capitalize(&first.ingredient.to_string()),
+                format_duration(first.remaining.unwrap_or_default())
+            ))?;
+        }
+        console.print_line()?;
+
+        Ok(())
+    }
+
+    /// Print the garbage
+    ///
+    /// This function prints the garbage to the terminal
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn print_garbage(&self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.print_line()?;
+        console.write_line("Garbage:")?;
+        console.write_line(&format!(
+            "Coffee grounds: {}",
+            self.garbage_container.coffee_grounds
+        ))?;
+        console.print_line()?;
+
+        Ok(())
+    }
+
+    /// Make a coffee
+    ///
+    /// This function sells a coffee: it checks the ingredients, heats the boiler, takes the payment
+    /// and brews the coffee. If brewing fails, the payment is refunded.
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    /// * `choice` - The choice of coffee to make
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there are not enough ingredients, the payment fails
+    /// or reading from or writing to the terminal fails
+    fn make_coffee(
+        &mut self,
+        console: &mut dyn Console,
+        choice: usize,
+    ) -> Result<(), MachineError> {
+        let mut order = Order::new(choice);
+        let mut heating = Duration::ZERO;
+        let taken = self.take_order(console, &mut order).and_then(|reciepe| {
+            // Heat before taking the payment, a failing terminal must not leave it taken
+            let started = self.clock.now();
+            self.heat_from_console(console, reciepe.target_temperature())?;
+            heating = self.clock.now().duration_since(started).unwrap_or_default();
+            let payment = self.take_payment(console, &reciepe)?;
+            Ok((reciepe, payment))
+        });
+        let (reciepe, payment) = match taken {
+            Ok(taken) => taken,
+            Err(MachineError::Io(e)) => return Err(MachineError::Io(e)),
+            Err(e) => {
+                self.record_order(&order, None, Err(&e));
+                return Err(e);
+            }
+        };
+        let refund = match &payment {
+            Payment::Cash(inserted) => format!("Returned {}", inserted),
+            Payment::Card => format!("Refunded {} to your card", format_price(reciepe.price)),
+        };
+
+        // Time the heating and the brewing, not the payment
+        let started = self.clock.now() - heating;
+        let sale = self.sell(order, payment, Some(&mut *console));
+        let sale = match sale {
+            Ok(sale) => sale,
+            Err(FailedSale {
+                error: MachineError::Io(e),
+                ..
+            }) => return Err(MachineError::Io(e)),
+            Err(FailedSale {
+                error,
+                refund: None,
+            }) => {
+                console.write_line(&refund)?;
+                return Err(error);
+            }
+            Err(FailedSale {
+                error,
+                refund: Some(e),
+            }) => {
+                console.write_line(&format!("The refund failed: {}", e))?;
+                return Err(error);
+            }
+        };
+        console.write_line(&format!("{} ready to go.", sale.beverage.name))?;
+        if !sale.change.is_empty() {
+            console.write_line(&format!(
+                "Your change: {} ({})",
+                format_price(sale.change.total()),
+                sale.change
+            ))?;
+        }
+        self.record_timing(&sale.beverage.name, started);
+        if let Err(e) = self.ledger.flush() {
+            console.write_line(&format!(
+                "The sale could not be written to the ledger: {}",
+                e
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Take an order from the user
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    /// * `order` - The order of the chosen reciepe, which is customized by the user
+    ///
+    /// # Returns
+    ///
+    /// The customized reciepe
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the reciepe is unknown, the customization is invalid
+    /// or there are not enough ingredients
+    fn take_order(
+        &mut self,
+        console: &mut dyn Console,
+        order: &mut Order,
+    ) -> Result<Reciepes, MachineError> {
+        Reciepes::find(&self.reciepes, &order.recipe)?;
+        self.check_maintenance()?;
+        order.customization = self.customize_from_console(console)?;
+        let reciepe = self.customize(order)?;
+        let shortfalls = self.shortfalls(&reciepe.ingredients);
+        if !shortfalls.is_empty() {
+            return Err(MachineError::InsufficientIngredients(shortfalls));
+        }
+
+        Ok(reciepe)
+    }
+
+    /// Let the user choose the size, the strength and the extras
+    ///
+    /// An empty input keeps the default.
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    ///
+    /// # Returns
+    ///
+    /// The customization chosen by the user
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a choice is invalid or reading from or writing to
+    /// the terminal fails
+    fn customize_from_console(
+        &mut self,
+        console: &mut dyn Console,
+    ) -> Result<Customization, MachineError> {
+        let mut customization = Customization::default();
+        console.write_line("Size: 1. Small, 2. Medium, 3. Large (default medium)")?;
+        if let Some(choice) = Self::read_option(console, Size::ALL.len())? {
+            customization.size = Size::ALL[choice - 1];
+        }
+        console.write_line("Strength: 1. Weak, 2. Normal, 3. Strong (default normal)")?;
+        if let Some(choice) = Self::read_option(console, Strength::ALL.len())? {
+            customization.strength = Strength::ALL[choice - 1];
+        }
+        console.write_line(&format!(
+            "Extras for {} each: 1. None, 2. Extra sugar, 3. Extra milk, 4. Both (default none)",
+            format_price(EXTRA_PRICE)
+        ))?;
+        if let Some(choice) = Self::read_option(console, 4)? {
+            customization.extra_sugar = choice == 2 || choice == 4;
+            customization.extra_milk = choice == 3 || choice == 4;
+        }
+
+        Ok(customization)
+    }
+
+    /// Read the choice of an option
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from
+    /// * `options` - The number of options
+    ///
+    /// # Returns
+    ///
+    /// The chosen option starting at 1, or `None` for an empty input
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the choice is not one of the options or reading
+    /// from the terminal fails
+    fn read_option(
+        console: &mut dyn Console,
+        options: usize,
+    ) -> Result<Option<usize>, MachineError> {
+        let input = console.read_line()?;
+        if input.is_empty() {
+            return Ok(None);
+        }
+        match input.parse::<usize>() {
+            Ok(choice) if (1..=options).contains(&choice) => Ok(Some(choice)),
+            _ => Err(MachineError::InvalidInput(format!("No option {}", input))),
+        }
+    }
+
+    /// Take the payment for a reciepe from the user
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    /// * `reciepe` - The reciepe to pay for
+    ///
+    /// # Returns
+    ///
+    /// The payment offered by the user
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the user chooses no valid payment method, cancels the
+    /// payment or reading from or writing to the terminal fails
+    fn take_payment(
+        &mut self,
+        console: &mut dyn Console,
+        reciepe: &Reciepes,
+    ) -> Result<Payment, MachineError> {
+        if reciepe.price == 0 {
+            return Ok(Payment::Cash(CoinInventory::new()));
+        }
+        console.write_line(&format!(
+            "{} costs {}",
+            reciepe.name,
+            format_price(reciepe.price)
+        ))?;
+        console.write_line("1. Pay with cash")?;
+        console.write_line("2. Pay by card")?;
+        let choice = console.read_line()?;
+        match choice.parse::<usize>().unwrap_or(0) {
+            1 => self.insert_coins(console, reciepe.price).map(Payment::Cash),
+            2 => Ok(Payment::Card),
+            _ => Err(MachineError::InvalidInput(format!(
+                "No payment method {}",
+                choice
+            ))),
+        }
+    }
+
+    /// Let the user insert notes and coins until the price is paid
+    ///
+    /// Notes and coins that are not accepted are returned right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    /// * `price` - The price in cents
+    ///
+    /// # Returns
+    ///
+    /// The inserted notes and coins
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the user cancels the payment or reading from or
+    /// writing to the terminal fails
+    fn insert_coins(
+        &mut self,
+        console: &mut dyn Console,
+        price: u32,
+    ) -> Result<CoinInventory, MachineError> {
+        let mut inserted = CoinInventory::new();
+        while inserted.total() < price {
+            console.write_line(&format!(
+                "Insert a coin or note ({} of {} paid, empty to cancel)",
+                format_price(inserted.total()),
+                format_price(price)
+            ))?;
+            let input = console.read_line()?;
+            if input.is_empty() {
+                if !inserted.is_empty() {
+                    console.write_line(&format!("Returned {}", inserted))?;
+                }
+                return Err(MachineError::PaymentCancelled);
+            }
+            if let Err(e) = parse_price(&input).and_then(|coin| inserted.add(coin, 1)) {
+                console.write_line(&e.to_string())?;
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    /// Run the service menu
+    ///
+    /// This function lets the user choose and perform a service operation
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading from or writing to the terminal fails
+    fn service_menu(&mut self, console: &mut dyn Console) -> Result<(), MachineError> {
+        self.print_service_menu(console)?;
+        let choice = console.read_line()?.parse::<usize>().unwrap_or(0);
+        if choice == 11 {
+            return Ok(());
+        }
+        console.clear()?;
+
+        let logged = self.service_log.len();
+        let result = match choice {
+            1..=5 => self.refill_from_console(console, Ingredient::ALL[choice - 1]),
+            6 => self.empty_grounds_bin().map(|_| ()),
+            7 => {
+                let started = self.clock.now();
+                console.write_line("Servicing...")?;
+                self.perform(
+                    Operation::StartService,
+                    Operation::FinishService,
+                    |machine| {
+                        machine.service_all();
+                        machine.draw_progress(console, 200)
+                    },
+                )?;
+                console.write_line("Service done.")?;
+                self.record_timing("Service", started);
+                Ok(())
+            }
+            8..=10 => {
+                let task = MaintenanceTask::ALL[choice - 8];
+                let started = self.clock.now();
+                self.maintain_from_console(console, task)?;
+                self.record_timing(&capitalize(&task.to_string()), started);
+                Ok(())
+            }
+            _ => Err(MachineError::InvalidInput(format!("No service {}", choice))),
+        };
+        match result {
+            Err(MachineError::Io(e)) => return Err(MachineError::Io(e)),
+            Err(e) => console.write_line(&e.to_string())?,
+            Ok(()) => {
+                for record in &self.service_log[logged..] {
+                    console.write_line(&record.to_string())?;
+                }
+            }
+        }
+        self.clock.sleep(Duration::from_millis(2000));
+
+        Ok(())
+    }
+
+    /// Run the order queue menu
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading from or writing to the terminal fails
+    fn queue_menu(&mut self, console: &mut dyn Console) -> Result<(), MachineError> {
+        self.print_queue_menu(console)?;
+        let choice = console.read_line()?.parse::<usize>().unwrap_or(0);
+        if choice == 5 {
+            return Ok(());
+        }
+        console.clear()?;
+
+        let result = match choice {
+            1 => self.enqueue_from_console(console),
+            2 => self.cancel_from_console(console),
+            3 => self.brew_queue_from_console(console, 1),
+            4 => self.brew_queue_from_console(console, usize::MAX),
+            _ => Err(MachineError::InvalidInput(format!("No option {}", choice))),
+        };
+        match result {
+            Err(MachineError::Io(e)) => return Err(MachineError::Io(e)),
+            Err(e) => console.write_line(&e.to_string())?,
+            Ok(()) => {}
+        }
+        self.clock.sleep(Duration::from_millis(2000));
+
+        Ok(())
+    }
+
+    /// Let the user submit an order to the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a choice is invalid, the order can't be queued or
+    /// reading from or writing to the terminal fails
+    fn enqueue_from_console(&mut self, console: &mut dyn Console) -> Result<(), MachineError> {
+        self.print_menu(console)?;
+        let choice = console.read_line()?.parse::<usize>().unwrap_or(0);
+        let mut order = Order::new(choice);
+        Reciepes::find(&self.reciepes, &order.recipe)?;
+        order.customization = self.customize_from_console(console)?;
+        console.write_line("Priority: 1. Normal, 2. High (default normal)")?;
+        let priority = match Self::read_option(console, Priority::ALL.len())? {
+            Some(choice) => Priority::ALL[choice - 1],
+            None => Priority::Normal,
+        };
+        let id = self.enqueue(order, priority)?;
+        if let Some(queued) = self.queue.get(id) {
+            console.write_line(&format!(
+                "Order #{} queued: {}, position {}",
+                id,
+                queued.name,
+                self.queue.position(id).unwrap_or_default()
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Let the user cancel a queued order
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the order can't be cancelled or reading from or
+    /// writing to the terminal fails
+    fn cancel_from_console(&mut self, console: &mut dyn Console) -> Result<(), MachineError> {
+        console.write_line("Order number:")?;
+        let input = console.read_line()?;
+        let id = input
+            .trim_start_matches('#')
+            .parse::<OrderId>()
+            .map_err(|_| MachineError::InvalidInput(format!("No order number: {}", input)))?;
+        self.cancel_order(id)?;
+        console.write_line(&format!("Order #{} cancelled.", id))
+    }
+
+    /// Brew queued orders, announcing every order and its outcome
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    /// * `count` - The maximum number of orders to brew
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the terminal fails
+    fn brew_queue_from_console(
+        &mut self,
+        console: &mut dyn Console,
+        count: usize,
+    ) -> Result<(), MachineError> {
+        if self.queue.pending().is_empty() {
+            return console.write_line("The queue is empty");
+        }
+        for _ in 0..count {
+            let Some(next) = self.queue.pending().first().map(|queued| queued.id) else {
+                break;
+            };
+            if let Some(queued) = self.queue.get(next) {
+                console.write_line(&format!("Brewing #{} {}...", queued.id, queued.name))?;
+            }
+            let started = self.clock.now();
+            self.process_next();
+            if let Some(queued) = self.queue.get(next) {
+                console.write_line(&queued.to_string())?;
+                self.record_timing(&queued.name.clone(), started);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Refill an ingredient with an amount entered by the user
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to read the user input from and write the output to
+    /// * `ingredient` - The ingredient to refill
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the amount is invalid or doesn't fit into the tank
+    fn refill_from_console(
+        &mut self,
+        console: &mut dyn Console,
+        ingredient: Ingredient,
+    ) -> Result<(), MachineError> {
+        let tank = self.tanks.get(ingredient);
+        console.write_line(&format!(
+            "How much {} to add? ({} {} free)",
+            ingredient,
+            tank.free_space(),
+            tank.unit
+        ))?;
+        let amount = console
+            .read_line()?
+            .parse::<f32>()
+            .map_err(|e| MachineError::InvalidInput(e.to_string()))?;
+        self.refill(ingredient, amount)?;
+
+        Ok(())
+    }
+
+    /// Brew a beverage
+    ///
+    /// This function checks and uses the ingredients of a reciepe and fills the garbage container,
+    /// without writing to the terminal or waiting.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The menu number (starting at 1) or the name of the reciepe, or a customized order
+    ///
+    /// # Returns
+    ///
+    /// The brewed beverage
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not ready, the reciepe is
+    /// unknown, there are not enough ingredients or the grounds bin has to be emptied first
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let beverage = machine.brew("Latte").unwrap();
+    /// assert_eq!(beverage.ingredients.milk, 50.0);
+    /// ```
+    pub fn brew(&mut self, order: impl Into<Order>) -> Result<Beverage, MachineError> {
+        let order = order.into();
+        let brewed = self.perform(Operation::StartBrew, Operation::FinishBrew, |machine| {
+            machine.prepare_beverage(&order, None)
+        });
+        self.record_order(&order, None, brewed.as_ref());
+        brewed
+    }
+
+    /// Brew a beverage, showing the heating and the brewing program on a console
+    ///
+    /// This function heats the boiler to the temperature of the reciepe and brews it like
+    /// [`CoffeeMachine::brew`], drawing the progress of every step of its program.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The menu number (starting at 1) or the name of the reciepe, or a customized order
+    /// * `console` - The console to write the progress to
+    ///
+    /// # Returns
+    ///
+    /// The brewed beverage
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if brewing fails or the heating can't be written to the
+    /// terminal. A terminal failing while the beverage is brewed only stops the drawing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::console::ScriptedConsole;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let mut console = ScriptedConsole::new(&[]);
+    /// let beverage = machine.brew_with_progress("Espresso", &mut console).unwrap();
+    /// assert_eq!(beverage.name, "Espresso");
+    /// assert!(console.transcript().contains("100%"));
+    /// ```
+    pub fn brew_with_progress(
+        &mut self,
+        order: impl Into<Order>,
+        console: &mut dyn Console,
+    ) -> Result<Beverage, MachineError> {
+        let order = order.into();
+        let started = self.clock.now();
+        if let Ok(reciepe) = self.customize(&order) {
+            self.heat_from_console(console, reciepe.target_temperature())?;
+        }
+        let brewed = self.perform(Operation::StartBrew, Operation::FinishBrew, |machine| {
+            machine.prepare_beverage(&order, Some(console))
+        });
+        self.record_order(&order, None, brewed.as_ref());
+        if let Ok(beverage) = &brewed {
+            self.record_timing(&beverage.name, started);
+        }
+        brewed
+    }
+
+    /// Sell a beverage, showing the heating and the brewing program on a console
+    ///
+    /// This function heats the boiler to the temperature of the reciepe and sells the beverage
+    /// like [`CoffeeMachine::buy`], drawing the progress of every step of its program.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The menu number (starting at 1) or the name of the reciepe, or a customized order
+    /// * `payment` - The payment for the beverage
+    /// * `console` - The console to write the progress to
+    ///
+    /// # Returns
+    ///
+    /// The sale with the brewed beverage and the change
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the payment fails, brewing fails or the heating can't
+    /// be written to the terminal. A terminal failing while the beverage is brewed only stops the
+    /// drawing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::console::ScriptedConsole;
+    /// use rusty_coffeemachine::payment::Payment;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let mut console = ScriptedConsole::new(&[]);
+    /// let sale = machine
+    ///     .buy_with_progress("Espresso", Payment::Card, &mut console)
+    ///     .unwrap();
+    /// assert_eq!(sale.price, 180);
+    /// assert!(console.transcript().contains("100%"));
+    /// ```
+    pub fn buy_with_progress(
+        &mut self,
+        order: impl Into<Order>,
+        payment: Payment,
+        console: &mut dyn Console,
+    ) -> Result<Sale, MachineError> {
+        let order = order.into();
+        let started = self.clock.now();
+        if let Ok(reciepe) = self.customize(&order) {
+            self.heat_from_console(console, reciepe.target_temperature())?;
+        }
+        let sale = self
+            .sell(order, payment, Some(console))
+            .map_err(|failed| failed.error)?;
+        self.record_timing(&sale.beverage.name, started);
+
+        Ok(sale)
+    }
+
+    /// Submit an order to the queue
+    ///
+    /// The ingredients of the order are reserved until it is brewed or cancelled, so later orders
+    /// and direct brews can't use them up. The order is charged to the card when it is brewed.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The menu number (starting at 1) or the name of the reciepe, or a customized order
+    /// * `priority` - The priority of the order, higher priorities are brewed first
+    ///
+    /// # Returns
+    ///
+    /// The number of the order
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the reciepe is unknown or there are not enough
+    /// ingredients left besides the ones reserved for the orders in the queue
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::queue::Priority;
+    /// use rusty_coffeemachine::{CoffeeMachine, MachineError};
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let latte = machine.enqueue("Latte", Priority::Normal).unwrap();
+    /// let espresso = machine.enqueue("Espresso", Priority::High).unwrap();
+    /// // 100 g of coffee are enough for three orders of 30 g
+    /// machine.enqueue("Espresso", Priority::Normal).unwrap();
+    /// assert!(matches!(
+    ///     machine.enqueue("Mocha", Priority::Normal),
+    ///     Err(MachineError::InsufficientIngredients(_))
+    /// ));
+    /// assert_eq!(machine.process_next(), Some(espresso));
+    /// assert_eq!(machine.process_next(), Some(latte));
+    /// ```
+    pub fn enqueue(
+        &mut self,
+        order: impl Into<Order>,
+        priority: Priority,
+    ) -> Result<OrderId, MachineError> {
+        let order = order.into();
+        let reciepe = self.customize(&order)?;
+        let shortfalls = self.shortfalls(&reciepe.ingredients);
+        if !shortfalls.is_empty() {
+            return Err(MachineError::InsufficientIngredients(shortfalls));
+        }
+        let id = self.queue.submit(
+            reciepe.name.clone(),
+            order,
+            priority,
+            reciepe.ingredients,
+            self.clock.now(),
+        );
+        self.emit(EventKind::OrderQueued {
+            id,
+            name: reciepe.name,
+        });
+        self.save()?;
+
+        Ok(id)
+    }
+
+    /// Get the status of an order in the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the order
+    ///
+    /// # Returns
+    ///
+    /// The status, or `None` if the queue doesn't know the order
+    pub fn order_status(&self, id: OrderId) -> Option<&OrderStatus> {
+        self.queue.get(id).map(|queued| &queued.status)
+    }
+
+    /// Cancel an order that is still waiting in the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the order
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the order is unknown or not waiting anymore
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::queue::{OrderStatus, Priority};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap();
+    /// let id = machine.enqueue("Latte", Priority::Normal).unwrap();
+    /// machine.cancel_order(id).unwrap();
+    /// assert_eq!(machine.order_status(id), Some(&OrderStatus::Cancelled));
+    /// assert!(machine.cancel_order(id).is_err());
+    /// ```
+    pub fn cancel_order(&mut self, id: OrderId) -> Result<(), MachineError> {
+        self.queue.cancel(id)?;
+        self.emit(EventKind::OrderCancelled(id));
+        self.save()
+    }
+
+    /// Brew the next order in the queue
+    ///
+    /// The order is charged to the card when it is brewed. The outcome is recorded in the queue, a
+    /// failed order doesn't stop the queue.
+    ///
+    /// # Returns
+    ///
+    /// The number of the brewed order, or `None` if the queue is empty
+    pub fn process_next(&mut self) -> Option<OrderId> {
+        let (id, order) = self.queue.start_next()?;
+        self.brew_queued(id, order);
+
+        Some(id)
+    }
+
+    /// Sell an order the queue has started, charging it to the card, and record the outcome
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the order
+    /// * `order` - The order
+    pub(crate) fn brew_queued(&mut self, id: OrderId, order: Order) {
+        let sold = self.buy(order, Payment::Card);
+        self.queue
+            .finish(id, sold.as_ref().map(|sale| &sale.beverage));
+        // The order is done either way, a failing state file must not stop the queue
+        self.save_after();
+    }
+
+    /// Customize a reciepe for an order
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order
+    ///
+    /// # Returns
+    ///
+    /// The reciepe with the ingredients and the price of the order
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the reciepe is unknown
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::customization::{Order, Size};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new().unwrap();
+    /// let reciepe = machine.customize(&Order::new("Latte").with_size(Size::Large)).unwrap();
+    /// assert_eq!(reciepe.ingredients.milk, 75.0);
+    /// assert_eq!(reciepe.price, 450);
+    /// assert!(machine.check_ingredients(&reciepe.ingredients).unwrap());
+    /// ```
+    pub fn customize(&self, order: &Order) -> Result<Reciepes, MachineError> {
+        let reciepe = Reciepes::find(&self.reciepes, &order.recipe)?;
+
+        Ok(order.customization.customize(reciepe))
+    }
+
+    /// Sell a beverage
+    ///
+    /// This function takes the payment, brews the beverage and gives the change, without writing
+    /// to the terminal or waiting. Cash is only kept if the beverage is brewed, a card payment is
+    /// refunded if brewing fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The menu number (starting at 1) or the name of the reciepe, or a customized order
+    /// * `payment` - The payment for the beverage
+    ///
+    /// # Returns
+    ///
+    /// The sale with the brewed beverage and the change
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the payment is too low, no exact change can be given,
+    /// the card is declined or brewing fails
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::payment::{CoinInventory, Payment};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let coins = CoinInventory::from_coins(&[200, 200]).unwrap();
+    /// let sale = machine.buy("Latte", Payment::Cash(coins)).unwrap();
+    /// assert_eq!(sale.change.total(), 100);
+    /// ```
+    pub fn buy(&mut self, order: impl Into<Order>, payment: Payment) -> Result<Sale, MachineError> {
+        self.sell(order.into(), payment, None)
+            .map_err(|failed| failed.error)
+    }
+
+    /// Sell a beverage, drawing the progress of its brewing program on a console
+    ///
+    /// Every sale is recorded in the ledger, whether it succeeds or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order
+    /// * `payment` - The payment for the beverage
+    /// * `console` - The console to draw the progress of the brewing program on, if any
+    ///
+    /// # Returns
+    ///
+    /// The sale with the brewed beverage and the change
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the payment fails or brewing fails,
+    /// together with the failure of the refund if the card could not be refunded
+    fn sell(
+        &mut self,
+        order: Order,
+        payment: Payment,
+        console: Option<&mut dyn Console>,
+    ) -> Result<Sale, FailedSale> {
+        let method = payment.method();
+        let sale = self.complete_sale(&order, payment, console);
+        self.record_order(
+            &order,
+            Some(method),
+            sale.as_ref().map(|sale| &sale.beverage),
+        );
+        sale
+    }
+
+    /// Take the payment, brew the beverage and give the change
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order
+    /// * `payment` - The payment for the beverage
+    /// * `console` - The console to draw the progress of the brewing program on, if any
+    ///
+    /// # Returns
+    ///
+    /// The sale with the brewed beverage and the change
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the payment fails or brewing fails,
+    /// together with the failure of the refund if the card could not be refunded
+    fn complete_sale(
+        &mut self,
+        order: &Order,
+        payment: Payment,
+        console: Option<&mut dyn Console>,
+    ) -> Result<Sale, FailedSale> {
+        let reciepe = self.customize(order)?;
+        let price = reciepe.price;
+        let method = payment.method();
+        let mut till = self.coins.clone();
+        let change = match &payment {
+            Payment::Cash(inserted) => {
+                let paid = inserted.total();
+                if paid < price {
+                    return Err(MachineError::InsufficientPayment { price, paid }.into());
+                }
+                till.merge(inserted);
+                let change = till
+                    .make_change(paid - price)
+                    .ok_or(MachineError::NoChange {
+                        change: paid - price,
+                    })?;
+                till.remove(&change)?;
+                change
+            }
+            Payment::Card => {
+                self.card_reader.charge(price)?;
+                CoinInventory::new()
+            }
+        };
+
+        let brewed = self.perform(Operation::StartBrew, Operation::FinishBrew, |machine| {
+            let beverage = machine.prepare_beverage(order, console)?;
+            machine.coins = till;
+            Ok(beverage)
+        });
+        match brewed {
+            Ok(beverage) => Ok(Sale {
+                beverage,
+                price,
+                method,
+                change,
+            }),
+            Err(error) => {
+                let refund = match method {
+                    PaymentMethod::Card => self.card_reader.refund(price).err().map(Box::new),
+                    PaymentMethod::Cash => None,
+                };
+                Err(FailedSale { error, refund })
+            }
+        }
+    }
+
+    /// Record the outcome of an order in the ledger
+    ///
+    /// The outcome stands even if the ledger file can't be written, the entry is written later and
+    /// the failure is reported by [`Ledger::flush`].
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order
+    /// * `payment` - How the order was paid, if it got that far
+    /// * `outcome` - The brewed beverage or the reason the order failed
+    fn record_order(
+        &mut self,
+        order: &Order,
+        payment: Option<PaymentMethod>,
+        outcome: Result<&Beverage, &impl fmt::Display>,
+    ) {
+        let known = self.customize(order).ok();
+        let price = known.as_ref().map_or(0, |reciepe| reciepe.price);
+        let entry = match outcome {
+            Ok(beverage) => LedgerEntry {
+                recipe: beverage.name.clone(),
+                ingredients: beverage.ingredients.clone(),
+                price,
+                payment,
+                failure: None,
+                timestamp: unix_seconds(beverage.brewed_at),
+            },
+            Err(e) => LedgerEntry {
+                recipe: known.map_or_else(|| order.recipe.to_string(), |reciepe| reciepe.name),
+                ingredients: IngredientsContainer::default(),
+                price,
+                payment,
+                failure: Some(e.to_string()),
+                timestamp: unix_seconds(self.clock.now()),
+            },
+        };
+
+        self.ledger.record(entry);
+    }
+
+    /// Get how urgently a maintenance program is needed
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The maintenance program
+    ///
+    /// # Returns
+    ///
+    /// The status of the maintenance program
+    pub fn maintenance_status(&self, task: MaintenanceTask) -> MaintenanceStatus {
+        self.maintenance_counters.status(&self.maintenance, task)
+    }
+
+    /// Check that no maintenance program is overdue
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error listing the overdue maintenance programs
+    fn check_maintenance(&self) -> Result<(), MachineError> {
+        let overdue: Vec<MaintenanceTask> = MaintenanceTask::ALL
+            .into_iter()
+            .filter(|&task| self.maintenance_status(task) == MaintenanceStatus::Overdue)
+            .collect();
+        if overdue.is_empty() {
+            Ok(())
+        } else {
+            Err(MachineError::MaintenanceRequired(overdue))
+        }
+    }
+
+    /// Run a maintenance program
+    ///
+    /// This function runs the program without writing to the terminal or waiting and resets its
+    /// counter.
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The maintenance program
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not idle
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::maintenance::{MaintenanceConfig, MaintenanceTask};
+    /// use rusty_coffeemachine::MachineError;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_clock(VirtualClock::new())
+    ///     .with_maintenance(MaintenanceConfig {
+    ///         descale_litres: Some(0.05),
+    ///         ..Default::default()
+    ///     });
+    /// machine.power_on().unwrap();
+    /// machine.brew("Americano").unwrap();
+    /// assert!(matches!(
+    ///     machine.brew("Espresso"),
+    ///     Err(MachineError::MaintenanceRequired(_))
+    /// ));
+    /// machine.maintain(MaintenanceTask::Descale).unwrap();
+    /// machine.brew("Espresso").unwrap();
+    /// ```
+    pub fn maintain(&mut self, task: MaintenanceTask) -> Result<(), MachineError> {
+        self.run_maintenance(task, |_| Ok(()))
+    }
+
+    /// Run a maintenance program, showing the progress of its phases
+    ///
+    /// # Arguments
+    ///
+    /// * `console` - The console to write to
+    /// * `task` - The maintenance program
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not idle or writing to the
+    /// terminal fails
+    fn maintain_from_console(
+        &mut self,
+        console: &mut dyn Console,
+        task: MaintenanceTask,
+    ) -> Result<(), MachineError> {
+        console.write_line(&format!("{}...", capitalize(&task.to_string())))?;
+        self.run_maintenance(task, |machine| {
+            let phases = task.phases();
+            for (number, (phase, duration)) in phases.iter().enumerate() {
+                console.write_line(&format!("Phase {}/{}: {}", number + 1, phases.len(), phase))?;
+                machine.draw_progress(console, duration.as_millis() as u64 / 100)?;
+            }
+            Ok(())
+        })?;
+        console.write_line(&format!("{} done.", capitalize(&task.to_string())))
+    }
+
+    /// Run a maintenance program, running an action while it runs
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The maintenance program
+    /// * `action` - The action to run while the program runs
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not idle or the action fails
+    fn run_maintenance(
+        &mut self,
+        task: MaintenanceTask,
+        action: impl FnOnce(&mut Self) -> Result<(), MachineError>,
+    ) -> Result<(), MachineError> {
+        let (start, finish) = match task {
+            MaintenanceTask::Descale => (Operation::StartDescaling, Operation::FinishDescaling),
+            _ => (Operation::StartCleaning, Operation::FinishCleaning),
+        };
+        self.perform(start, finish, |machine| {
+            action(machine)?;
+            machine.maintenance_counters.reset(task);
+            machine.counters.maintenance += 1;
+            machine.emit(EventKind::MaintenancePerformed(task));
+            Ok(())
+        })
+    }
+
+    /// Prepare a beverage
+    ///
+    /// This function checks and uses the customized ingredients of a reciepe, the coffee machine
+    /// has to be brewing.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order
+    /// * `console` - The console to draw the progress of the brewing program on, if any
+    ///
+    /// # Returns
+    ///
+    /// The prepared beverage
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the reciepe is unknown, there are not enough
+    /// ingredients or the grounds bin has to be emptied first
+    fn prepare_beverage(
+        &mut self,
+        order: &Order,
+        console: Option<&mut dyn Console>,
+    ) -> Result<Beverage, MachineError> {
+        let reciepe = self.customize(order)?;
+        self.check_maintenance()?;
+        self.measure_levels()?;
+        let shortfalls = self.shortfalls(&reciepe.ingredients);
+        if !shortfalls.is_empty() {
+            return Err(MachineError::InsufficientIngredients(shortfalls));
+        }
+        self.emit(EventKind::BrewStarted {
+            recipe: reciepe.name.clone(),
+        });
+        let brewed = self
+            .wait_for_temperature(reciepe.target_temperature())
+            .and_then(|()| {
+                let brewed = self.run_program(&reciepe, console);
+                // Let the boiler cool down again after steaming
+                self.hardware
+                    .boiler
+                    .set_target(BREW_TEMPERATURE, self.clock.now());
+                brewed
+            });
+        if let Err(e) = &brewed {
+            self.emit(EventKind::BrewFailed {
+                recipe: reciepe.name.clone(),
+                reason: e.to_string(),
+            });
+        }
+        brewed?;
+        self.counters.beverages_brewed += 1;
+        if reciepe.ingredients.coffee > 0.0 {
+            self.counters.shots += 1;
+        }
+        if reciepe.ingredients.milk > 0.0 {
+            self.counters.milk_drinks += 1;
+        }
+        self.counters.litres_pumped += reciepe.ingredients.water / 1000.0;
+        self.maintenance_counters.record_brew(&reciepe.ingredients);
+
+        let beverage = Beverage::new(reciepe.name, reciepe.ingredients, self.clock.now());
+        self.emit(EventKind::BrewFinished {
+            beverage: beverage.clone(),
+        });
+
+        Ok(beverage)
+    }
+
+    /// Update the ingredient levels with the levels measured by the sensors
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a sensor fails
+    fn measure_levels(&mut self) -> Result<(), MachineError> {
+        for ingredient in Ingredient::ALL {
+            let tank = self.tanks.get_mut(ingredient);
+            let level = self.hardware.sensors.measure(ingredient, tank.level)?;
+            if level != tank.level {
+                tank.level = level;
+                self.record_change(Change::LevelMeasured { ingredient, level });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Brew a reciepe by driving the hardware through its brewing program
+    ///
+    /// Reciepes without a brewing program get one derived from their ingredients. The ingredients
+    /// are used step by step, so a failing device leaves the ingredients used so far used. On a
+    /// console every step is announced before it is driven and followed by a progress bar lasting
+    /// as long as the step.
+    ///
+    /// # Arguments
+    ///
+    /// * `reciepe` - The customized reciepe
+    /// * `console` - The console to draw the progress on, if any
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee grounds don't fit into the garbage
+    /// container or a device fails
+    fn run_program(
+        &mut self,
+        reciepe: &Reciepes,
+        mut console: Option<&mut dyn Console>,
+    ) -> Result<(), MachineError> {
+        let free = self.garbage_container.coffee_grounds.free_space();
+        if reciepe.ingredients.coffee > free {
+            self.emit(EventKind::GarbageFull(
+                self.garbage_container.coffee_grounds.clone(),
+            ));
+            return Err(MachineError::GarbageFull {
+                required: reciepe.ingredients.coffee,
+                free,
+            });
+        }
+        let steps = if reciepe.steps.is_empty() {
+            program::steps_for(&reciepe.ingredients)
+        } else {
+            reciepe.steps.clone()
+        };
+        Self::draw(&mut console, |screen| {
+            screen.write_line(&format!("Make your {}", reciepe.name))
+        });
+        for (number, step) in steps.iter().enumerate() {
+            Self::draw(&mut console, |screen| {
+                screen.write_line(&format!("Step {}/{}: {}", number + 1, steps.len(), step))
+            });
+            self.drive(step)?;
+            self.use_ingredients(&reciepe.name, &program::totals(std::slice::from_ref(step)))?;
+            Self::draw(&mut console, |screen| {
+                self.draw_progress(screen, step.duration().as_millis() as u64 / 100)
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Drive the device performing a step of a brewing program
+    ///
+    /// # Arguments
+    ///
+    /// * `step` - The step
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the device fails
+    fn drive(&mut self, step: &Step) -> Result<(), MachineError> {
+        let hardware = &mut self.hardware;
+        match step.kind {
+            StepKind::Grind => hardware.grinder.grind(step.amount),
+            StepKind::PreInfuse | StepKind::Extract | StepKind::AddWater => {
+                hardware.pump.pump(step.amount)
+            }
+            StepKind::SteamMilk => hardware.frother.steam(step.amount),
+            StepKind::Froth => hardware.frother.froth(step.amount),
+            StepKind::AddSugar => hardware.dispenser.dispense(Ingredient::Sugar, step.amount),
+            StepKind::AddCacao => hardware.dispenser.dispense(Ingredient::Cacao, step.amount),
+            StepKind::Stir => Ok(()),
+        }
+    }
+
+    /// Check if there are enough ingredients
+    ///
+    /// This function checks if there are enough ingredients to make a coffee
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredients` - The ingredients needed to make a coffee
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating if there are enough ingredients
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if checking the ingredients fails
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new().unwrap();
+    /// let available = machine.check_ingredients(&machine.reciepes[0].ingredients);
+    /// ```
+    pub fn check_ingredients(
+        &self,
+        ingredients: &IngredientsContainer,
+    ) -> Result<bool, MachineError> {
+        Ok(self.shortfalls(ingredients).is_empty())
+    }
+
+    /// Get the shortfalls for a set of ingredients
+    ///
+    /// This function compares the needed ingredients with the ingredients in the machine that are
+    /// not reserved for queued orders
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredients` - The ingredients needed to make a coffee
+    ///
+    /// # Returns
+    ///
+    /// A vector with one entry per ingredient that is not available in the needed amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new().unwrap();
+    /// let shortfalls = machine.shortfalls(&machine.reciepes[0].ingredients);
+    /// assert!(shortfalls.is_empty());
+    /// ```
+    pub fn shortfalls(&self, ingredients: &IngredientsContainer) -> Vec<Shortfall> {
+        let levels = self.available_levels();
+        Ingredient::ALL
+            .iter()
+            .filter_map(|&ingredient| {
+                let required = ingredients.get(ingredient);
+                let available = levels.get(ingredient);
+                if available < required {
+                    Some(Shortfall {
+                        ingredient,
+                        required,
+                        available,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Get the ingredient levels left besides the ones reserved for the orders in the queue
+    fn available_levels(&self) -> IngredientsContainer {
+        let reserved = self.queue.reserved();
+        let mut levels = self.tanks.levels();
+        for ingredient in Ingredient::ALL {
+            let level = levels.get_mut(ingredient);
+            *level = (*level - reserved.get(ingredient)).max(0.0);
+        }
+        levels
+    }
+
+    /// Get the ingredients that are running low
+    ///
+    /// # Returns
+    ///
+    /// A warning for every ingredient below its warning threshold, critical ones first
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::stock::StockLevel;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap();
+    /// machine.tanks.water.level = 5.0;
+    /// let warnings = machine.stock_warnings();
+    /// assert_eq!(warnings.len(), 1);
+    /// assert_eq!(warnings[0].level, StockLevel::Critical);
+    /// ```
+    pub fn stock_warnings(&self) -> Vec<StockWarning> {
+        let mut warnings: Vec<StockWarning> = Ingredient::ALL
+            .into_iter()
+            .filter_map(|ingredient| {
+                let tank = self.tanks.get(ingredient);
+                match self.thresholds.level(ingredient, tank.level) {
+                    StockLevel::Ok => None,
+                    level => Some(StockWarning {
+                        ingredient,
+                        level,
+                        tank: tank.clone(),
+                    }),
+                }
+            })
+            .collect();
+        warnings.sort_by_key(|warning| std::cmp::Reverse(warning.level));
+        warnings
+    }
+
+    /// Get the availability forecast
+    ///
+    /// The forecast tells how many cups of every reciepe can still be made and, based on the sales
+    /// of the last week in the ledger, when every ingredient runs out. The ingredients reserved
+    /// for the orders in the queue are counted as used.
+    ///
+    /// # Returns
+    ///
+    /// The forecast
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::containers::Ingredient;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// machine.brew("Latte").unwrap();
+    /// let forecast = machine.forecast();
+    /// assert_eq!(forecast.recipes[3].cups, Some(1));
+    /// assert_eq!(forecast.recipes[3].limiting, Some(Ingredient::Milk));
+    /// assert_eq!(forecast.runs_out_first().unwrap().ingredient, Ingredient::Milk);
+    /// ```
+    pub fn forecast(&self) -> Forecast {
+        Forecast::new(
+            &self.available_levels(),
+            &self.reciepes,
+            &self.ledger,
+            self.clock.now(),
+        )
+    }
+
+    /// Get the reciepes that can't be brewed with the ingredients left
+    ///
+    /// The reciepes are checked without customization.
+    ///
+    /// # Returns
+    ///
+    /// The unavailable reciepes in menu order, with the ingredients blocking them
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::Ingredient;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap();
+    /// machine.tanks.milk.level = 40.0;
+    /// let unavailable = machine.unavailable_recipes();
+    /// assert_eq!(unavailable.len(), 1);
+    /// assert_eq!(unavailable[0].name, "Latte");
+    /// assert_eq!(unavailable[0].shortfalls[0].ingredient, Ingredient::Milk);
+    /// ```
+    pub fn unavailable_recipes(&self) -> Vec<UnavailableRecipe> {
+        self.reciepes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, reciepe)| {
+                let shortfalls = self.shortfalls(&reciepe.ingredients);
+                (!shortfalls.is_empty()).then(|| UnavailableRecipe {
+                    id: i + 1,
+                    name: reciepe.name.clone(),
+                    shortfalls,
+                })
+            })
+            .collect()
+    }
+
+    /// Use ingredients
+    ///
+    /// This function uses ingredients to make a coffee
+    ///
+    /// # Arguments
+    ///
+    /// * `recipe` - The name of the reciepe the ingredients are used for
+    /// * `ingredients` - The ingredients to use
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee grounds don't fit into the garbage container
+    fn use_ingredients(
+        &mut self,
+        recipe: &str,
+        ingredients: &IngredientsContainer,
+    ) -> Result<(), MachineError> {
+        // The levels are checked before brewing, this keeps a tank from ever going below empty
+        let shortfalls: Vec<Shortfall> = Ingredient::ALL
+            .iter()
+            .filter_map(|&ingredient| {
+                let required = ingredients.get(ingredient);
+                let available = self.tanks.get(ingredient).level;
+                (available < required).then_some(Shortfall {
+                    ingredient,
+                    required,
+                    available,
+                })
+            })
+            .collect();
+        if !shortfalls.is_empty() {
+            return Err(MachineError::InsufficientIngredients(shortfalls));
+        }
+        if ingredients.coffee > 0.0 {
+            self.garbage_container
+                .coffee_grounds
+                .refill(ingredients.coffee)
+                .map_err(|_| MachineError::GarbageFull {
+                    required: ingredients.coffee,
+                    free: self.garbage_container.coffee_grounds.free_space(),
+                })?;
+        }
+
+        for ingredient in Ingredient::ALL {
+            let tank = self.tanks.get_mut(ingredient);
+            let before = self.thresholds.level(ingredient, tank.level);
+            tank.take(ingredients.get(ingredient));
+            let level = self.thresholds.level(ingredient, tank.level);
+            if level > before {
+                let tank = tank.clone();
+                self.emit(EventKind::IngredientLow(StockWarning {
+                    ingredient,
+                    level,
+                    tank,
+                }));
+            }
+        }
+        self.record_change(Change::IngredientsUsed {
+            recipe: recipe.to_string(),
+            ingredients: ingredients.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Take service
+    ///
+    /// This function takes the service of the coffee machine, refilling the ingredients and emptying the garbage
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not idle
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// machine.brew("Espresso").unwrap();
+    /// machine.take_service().unwrap();
+    /// assert_eq!(machine.tanks.water.level, 100.0);
+    /// ```
+    pub fn take_service(&mut self) -> Result<(), MachineError> {
+        self.perform(
+            Operation::StartService,
+            Operation::FinishService,
+            |machine| {
+                machine.service_all();
+                Ok(())
+            },
+        )
+    }
+
+    /// Refill all ingredients and empty the grounds bin, the coffee machine has to be servicing
+    fn service_all(&mut self) {
+        for ingredient in Ingredient::ALL {
+            let tank = self.tanks.get_mut(ingredient);
+            let added = tank.fill_up();
+            let unit = tank.unit;
+            if added > 0.0 {
+                self.log_service(ServiceAction::Refill(ingredient), added, unit);
+            }
+        }
+        if self.garbage_container.coffee_grounds.level > 0.0 {
+            self.drain_grounds_bin();
+        }
+        self.counters.services += 1;
+    }
+
+    /// Refill an ingredient
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredient` - The ingredient to refill
+    /// * `amount` - The amount to add
+    ///
+    /// # Returns
+    ///
+    /// The new level of the ingredient
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not idle or the amount is
+    /// negative or doesn't fit into the tank
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::containers::Ingredient;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// machine.brew("Latte").unwrap();
+    /// assert_eq!(machine.refill(Ingredient::Milk, 20.0).unwrap(), 70.0);
+    /// ```
+    pub fn refill(&mut self, ingredient: Ingredient, amount: f32) -> Result<f32, MachineError> {
+        self.perform(
+            Operation::StartService,
+            Operation::FinishService,
+            |machine| {
+                let tank = machine.tanks.get_mut(ingredient);
+                let level = tank.refill(amount)?;
+                let unit = tank.unit;
+                machine.log_service(ServiceAction::Refill(ingredient), amount, unit);
+
+                Ok(level)
+            },
+        )
+    }
+
+    /// Refill the water tank
+    ///
+    /// # Arguments
+    ///
+    /// * `ml` - The amount of water to add
+    ///
+    /// # Returns
+    ///
+    /// The new level of the water tank
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the water doesn't fit into the tank
+    pub fn refill_water(&mut self, ml: f32) -> Result<f32, MachineError> {
+        self.refill(Ingredient::Water, ml)
+    }
+
+    /// Add coffee beans
+    ///
+    /// # Arguments
+    ///
+    /// * `grams` - The amount of coffee beans to add
+    ///
+    /// # Returns
+    ///
+    /// The new level of the coffee bean container
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the beans don't fit into the container
+    pub fn add_coffee_beans(&mut self, grams: f32) -> Result<f32, MachineError> {
+        self.refill(Ingredient::Coffee, grams)
+    }
+
+    /// Top up the milk tank
+    ///
+    /// # Arguments
+    ///
+    /// * `ml` - The amount of milk to add
+    ///
+    /// # Returns
+    ///
+    /// The new level of the milk tank
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the milk doesn't fit into the tank
+    pub fn top_up_milk(&mut self, ml: f32) -> Result<f32, MachineError> {
+        self.refill(Ingredient::Milk, ml)
+    }
+
+    /// Add sugar
+    ///
+    /// # Arguments
+    ///
+    /// * `grams` - The amount of sugar to add
+    ///
+    /// # Returns
+    ///
+    /// The new level of the sugar container
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sugar doesn't fit into the container
+    pub fn add_sugar(&mut self, grams: f32) -> Result<f32, MachineError> {
+        self.refill(Ingredient::Sugar, grams)
+    }
+
+    /// Add cacao
+    ///
+    /// # Arguments
+    ///
+    /// * `grams` - The amount of cacao to add
+    ///
+    /// # Returns
+    ///
+    /// The new level of the cacao container
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the cacao doesn't fit into the container
+    pub fn add_cacao(&mut self, grams: f32) -> Result<f32, MachineError> {
+        self.refill(Ingredient::Cacao, grams)
+    }
+
+    /// Empty the grounds bin
+    ///
+    /// # Returns
+    ///
+    /// The amount of coffee grounds removed
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coffee machine is not idle
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// machine.brew("Espresso").unwrap();
+    /// assert_eq!(machine.empty_grounds_bin().unwrap(), 30.0);
+    /// ```
+    pub fn empty_grounds_bin(&mut self) -> Result<f32, MachineError> {
+        self.perform(
+            Operation::StartService,
+            Operation::FinishService,
+            |machine| Ok(machine.drain_grounds_bin()),
+        )
+    }
+
+    /// Empty the grounds bin, the coffee machine has to be servicing
+    ///
+    /// # Returns
+    ///
+    /// The amount of coffee grounds removed
+    fn drain_grounds_bin(&mut self) -> f32 {
+        let bin = &mut self.garbage_container.coffee_grounds;
+        let removed = bin.drain();
+        let unit = bin.unit;
+        self.log_service(ServiceAction::EmptyGroundsBin, removed, unit);
+        removed
+    }
+
+    /// Add a service operation to the service log
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The performed action
+    /// * `amount` - The amount added or removed
+    /// * `unit` - The unit of the amount
+    fn log_service(&mut self, action: ServiceAction, amount: f32, unit: Unit) {
+        let record = ServiceRecord {
+            action,
+            amount,
+            unit,
+            performed_at: self.clock.now(),
+        };
+        self.record_change(match action {
+            ServiceAction::Refill(ingredient) => Change::Refilled { ingredient, amount },
+            ServiceAction::EmptyGroundsBin => Change::GroundsBinEmptied { amount },
+        });
+        self.service_log.push(record.clone());
+        self.emit(EventKind::ServicePerformed(record));
+    }
+}
+
+/// A sale that failed, with the failure of the refund if the card could not be refunded
+#[derive(Debug)]
+struct FailedSale {
+    error: MachineError,
+    refund: Option<Box<MachineError>>,
+}
+
+impl From<MachineError> for FailedSale {
+    fn from(error: MachineError) -> Self {
+        FailedSale {
+            error,
+            refund: None,
+        }
+    }
+}
+
+impl fmt::Display for FailedSale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(refund) = &self.refund {
+            write!(f, " (refund failed: {})", refund)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::console::ScriptedConsole;
+    use crate::hardware::{Device, SimulatedHardware};
+    use crate::persistence::temp_path;
+
+    #[test]
+    fn test_coffee_machine_new() {
+        let machine = CoffeeMachine::new().unwrap();
+        assert_eq!(machine.tanks.water.level, 100.0);
+        assert_eq!(machine.tanks.coffee.level, 100.0);
+        assert_eq!(machine.tanks.milk.level, 100.0);
+        assert_eq!(machine.tanks.sugar.level, 100.0);
+        assert_eq!(machine.tanks.cacao.level, 100.0);
+        assert_eq!(machine.garbage_container.coffee_grounds.level, 0.0);
         assert_eq!(machine.reciepes.len(), 5);
     }
 
@@ -404,10 +3178,7 @@ mod tests {
             },
         )
         .unwrap();
-        assert_eq!(
-            machine.check_ingredients(&reciepe.ingredients).unwrap(),
-            true
-        );
+        assert!(machine.check_ingredients(&reciepe.ingredients).unwrap());
     }
 
     #[test]
@@ -424,31 +3195,36 @@ mod tests {
             },
         )
         .unwrap();
-        machine.use_ingredients(&reciepe.ingredients).unwrap();
-        assert_eq!(machine.ingredients_container.water, 70.0);
-        assert_eq!(machine.ingredients_container.coffee, 70.0);
-        assert_eq!(machine.ingredients_container.milk, 100.0);
-        assert_eq!(machine.ingredients_container.sugar, 100.0);
-        assert_eq!(machine.ingredients_container.cacao, 100.0);
-        assert_eq!(machine.garbage_container.coffee_grounds, 30.0);
+        machine
+            .use_ingredients(&reciepe.name, &reciepe.ingredients)
+            .unwrap();
+        assert_eq!(machine.tanks.water.level, 70.0);
+        assert_eq!(machine.tanks.coffee.level, 70.0);
+        assert_eq!(machine.tanks.milk.level, 100.0);
+        assert_eq!(machine.tanks.sugar.level, 100.0);
+        assert_eq!(machine.tanks.cacao.level, 100.0);
+        assert_eq!(machine.garbage_container.coffee_grounds.level, 30.0);
     }
 
     #[test]
     fn test_coffee_machine_take_service() {
-        let mut machine = CoffeeMachine::new().unwrap();
-        machine.ingredients_container.water = 50.0;
-        machine.ingredients_container.coffee = 100.0;
-        machine.ingredients_container.milk = 50.0;
-        machine.ingredients_container.sugar = 100.0;
-        machine.ingredients_container.cacao = 100.0;
-        machine.garbage_container.coffee_grounds = 30.0;
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.tanks.water.level = 50.0;
+        machine.tanks.coffee.level = 100.0;
+        machine.tanks.milk.level = 50.0;
+        machine.tanks.sugar.level = 100.0;
+        machine.tanks.cacao.level = 100.0;
+        machine.garbage_container.coffee_grounds.level = 30.0;
         machine.take_service().unwrap();
-        assert_eq!(machine.ingredients_container.water, 100.0);
-        assert_eq!(machine.ingredients_container.coffee, 100.0);
-        assert_eq!(machine.ingredients_container.milk, 100.0);
-        assert_eq!(machine.ingredients_container.sugar, 100.0);
-        assert_eq!(machine.ingredients_container.cacao, 100.0);
-        assert_eq!(machine.garbage_container.coffee_grounds, 0.0);
+        assert_eq!(machine.tanks.water.level, 100.0);
+        assert_eq!(machine.tanks.coffee.level, 100.0);
+        assert_eq!(machine.tanks.milk.level, 100.0);
+        assert_eq!(machine.tanks.sugar.level, 100.0);
+        assert_eq!(machine.tanks.cacao.level, 100.0);
+        assert_eq!(machine.garbage_container.coffee_grounds.level, 0.0);
     }
 
     #[test]
@@ -465,9 +3241,938 @@ mod tests {
             },
         )
         .unwrap();
+        assert!(!machine.check_ingredients(&reciepe.ingredients).unwrap());
+    }
+
+    #[test]
+    fn test_make_coffee_insufficient_ingredients() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.tanks.water.level = 10.0;
+        let mut console = ScriptedConsole::new(&["", "", ""]);
+        match machine.make_coffee(&mut console, 1) {
+            Err(MachineError::InsufficientIngredients(shortfalls)) => {
+                assert_eq!(shortfalls.len(), 1);
+                assert_eq!(shortfalls[0].ingredient, Ingredient::Water);
+                assert_eq!(shortfalls[0].missing(), 20.0);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(machine.tanks.coffee.level, 100.0);
+    }
+
+    #[test]
+    fn test_brew_by_name_and_id() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        let beverage = machine.brew("cappuccino").unwrap();
+        assert_eq!(beverage.name, "Cappuccino");
+        assert_eq!(beverage.ingredients.milk, 30.0);
+        let beverage = machine.brew(1).unwrap();
+        assert_eq!(beverage.name, "Espresso");
+        assert_eq!(machine.tanks.water.level, 40.0);
+        assert_eq!(machine.tanks.coffee.level, 40.0);
+        assert_eq!(machine.tanks.milk.level, 70.0);
+        assert_eq!(machine.garbage_container.coffee_grounds.level, 60.0);
+    }
+
+    #[test]
+    fn test_brew_unknown_recipe() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        assert!(matches!(
+            machine.brew(0),
+            Err(MachineError::UnknownRecipe(_))
+        ));
+        assert!(matches!(
+            machine.brew(42),
+            Err(MachineError::UnknownRecipe(_))
+        ));
+        assert!(matches!(
+            machine.brew("Tea"),
+            Err(MachineError::UnknownRecipe(_))
+        ));
+        assert_eq!(machine.tanks.water.level, 100.0);
+    }
+
+    #[test]
+    fn test_run_session() {
+        let clock = VirtualClock::new();
+        let mut machine = CoffeeMachine::new().unwrap().with_clock(clock.clone());
+        let mut console =
+            ScriptedConsole::new(&["1", "4", "", "", "", "1", "2", "2", "2", "3", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.starts_with("Welcome to the coffee machine\nStarting machine...\n"));
+        assert!(transcript.contains("Starting machine...\nHeating up to 93.0 °C...\n"));
+        // The boiler is heated before the payment is taken
+        let heating = transcript.find("Heating up to 125.0 °C...\n").unwrap();
+        assert!(heating < transcript.find("Latte costs 3.00\n").unwrap());
+        assert!(transcript.contains(concat!(
+            "Make your Latte\n",
+            "Step 1/4: Grind 30 g coffee\n",
+        )));
+        assert!(transcript.contains("Step 4/4: Steam 50 ml milk\n"));
+        assert!(transcript.contains("Latte ready to go.\nYour change: 1.00 (1x 1.00)\n"));
+        assert!(transcript.contains("Water: 70/100 ml\nCoffee: 70/100 g\nMilk: 50/100 ml\n"));
+        assert!(transcript.contains("Coffee grounds: 30/100 g\n"));
+        assert!(transcript.contains("Shutting down...\n"));
+        assert!(console.clears() > 0);
+        let operations: Vec<_> = machine
+            .timings
+            .iter()
+            .map(|timing| (timing.operation.as_str(), timing.elapsed))
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                ("Start up", Duration::from_millis(25450)),
+                ("Latte", Duration::from_millis(23740)),
+                ("Shut down", Duration::from_millis(5050)),
+            ]
+        );
+        assert_eq!(clock.elapsed(), Duration::from_millis(62240));
+    }
+
+    #[test]
+    fn test_run_session_warns_about_low_stock() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.tanks.milk.level = 40.0;
+        machine.tanks.water.level = 5.0;
+        let mut console = ScriptedConsole::new(&["2", "1", "1", "", "", "", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains(concat!(
+            "State: idle\n",
+            "Boiler: 93.0 °C\n",
+            "Warning: water is critically low (5/100 ml)\n",
+        )));
+        assert!(transcript.contains("Water: 5/100 ml (critical)\nCoffee: 100/100 g\n"));
+        assert!(transcript.contains("Milk: 40/100 ml\n"));
+        assert!(transcript.contains("1. Espresso - unavailable, out of water\n"));
+        assert!(transcript.contains("4. Latte - unavailable, out of water, milk\n"));
+    }
+
+    #[test]
+    fn test_run_session_shows_the_forecast() {
+        let clock = VirtualClock::new();
+        let mut machine = CoffeeMachine::new().unwrap().with_clock(clock.clone());
+        machine.power_on().unwrap();
+        machine.brew("Latte").unwrap();
+        clock.advance(Duration::from_secs(2 * 3600));
+        machine.brew("Espresso").unwrap();
+        machine.power_off().unwrap();
+        let mut console = ScriptedConsole::new(&["5", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("5. Forecast\n6. Order queue\n7. Exit\n"));
+        assert!(transcript.contains("Cups left:\nEspresso: 1 cup left, limited by water\n"));
+        assert!(transcript.contains("Latte: 1 cup left, limited by water\n"));
+        assert!(transcript.contains("Water: 40 ml left, empty in about 1 h 20 min at 30.0 ml/h\n"));
+        assert!(transcript.contains("Sugar: 100 g left, not used recently\n"));
+        assert!(transcript.contains("Water runs out first, refill within 1 h 20 min\n"));
+    }
+
+    #[test]
+    fn test_run_session_queues_orders() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let mut console = ScriptedConsole::new(&[
+            "6", "1", "1", "", "", "", "", "6", "1", "4", "", "", "", "2", "6", "1", "3", "", "",
+            "", "", "6", "2", "3", "6", "4", "7",
+        ]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains(
+            "Order #1 queued: Espresso, position 1
+"
+        ));
+        assert!(transcript.contains(
+            "Order #2 queued: Latte, position 1
+"
+        ));
+        assert!(transcript.contains(
+            "1. #2 Latte (high priority): queued
+2. #1 Espresso: queued
+"
+        ));
+        assert!(transcript.contains(
+            "Order #3 cancelled.
+"
+        ));
+        assert!(transcript.contains(
+            "Brewing #2 Latte...
+#2 Latte (high priority): ready
+"
+        ));
+        assert!(transcript.contains(
+            "Brewing #1 Espresso...
+#1 Espresso: ready
+"
+        ));
+        assert_eq!(machine.counters.beverages_brewed, 2);
+    }
+
+    #[test]
+    fn test_queued_orders_reserve_ingredients() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        let first = machine.enqueue("Latte", Priority::Normal).unwrap();
+        let second = machine.enqueue("Latte", Priority::Normal).unwrap();
+        assert!(matches!(
+            machine.brew("Cappuccino"),
+            Err(MachineError::InsufficientIngredients(_))
+        ));
+        assert!(machine
+            .unavailable_recipes()
+            .iter()
+            .any(|unavailable| unavailable.name == "Latte"));
+        assert_eq!(machine.forecast().recipes[3].cups, Some(0));
+
+        assert_eq!(machine.process_next(), Some(first));
+        assert_eq!(machine.process_next(), Some(second));
+        assert_eq!(machine.process_next(), None);
+        assert!(matches!(
+            machine.order_status(second),
+            Some(OrderStatus::Ready(_))
+        ));
+        assert_eq!(machine.tanks.milk.level, 0.0);
+    }
+
+    #[test]
+    fn test_queued_orders_are_charged_to_the_card() {
+        let card = SimulatedCardReader::with_balance(500);
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_card_reader(card.clone());
+        machine.power_on().unwrap();
+        let latte = machine.enqueue("Latte", Priority::Normal).unwrap();
+        let cappuccino = machine.enqueue("Cappuccino", Priority::Normal).unwrap();
+        machine.process_next();
+        machine.process_next();
+        assert_eq!(card.charged(), 300);
+        assert_eq!(
+            machine.ledger.entries()[0].payment,
+            Some(PaymentMethod::Card)
+        );
+        assert!(matches!(
+            machine.order_status(latte),
+            Some(OrderStatus::Ready(_))
+        ));
+        assert!(matches!(
+            machine.order_status(cappuccino),
+            Some(OrderStatus::Failed(reason)) if reason.starts_with("Payment declined")
+        ));
+    }
+
+    #[test]
+    fn test_run_session_reports_errors() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.tanks.milk.level = 0.0;
+        let mut console = ScriptedConsole::new(&["1", "3", "", "", "", "4", "7", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("Not enough ingredients: milk: 30 missing"));
+        assert!(transcript.contains("Service done.\nAdded 100 ml milk\n"));
+        assert_eq!(machine.tanks.milk.level, 100.0);
+        assert_eq!(machine.timings[1].operation, "Service");
+        assert_eq!(machine.timings[1].elapsed, Duration::from_millis(20200));
+    }
+
+    #[test]
+    fn test_run_session_survives_non_numeric_input() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let mut console = ScriptedConsole::new(&["coffee", "1", "latte", "4", "lots", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("Invalid choice\n"));
+        assert!(transcript.contains("Unknown reciepe: #0\n"));
+        assert!(transcript.contains("Invalid input: No service 0\n"));
+        assert_eq!(machine.counters.beverages_brewed, 0);
+        assert_eq!(*machine.state(), MachineState::Off);
+    }
+
+    #[test]
+    fn test_run_session_rejects_unknown_coins_and_cancels() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let mut console = ScriptedConsole::new(&["1", "1", "", "", "", "1", "0.03", "1", "", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("Invalid input: 0.03 is not an accepted coin or note\n"));
+        assert!(transcript.contains("(1.00 of 1.80 paid, empty to cancel)\n"));
+        assert!(transcript.contains("Returned 1x 1.00\nPayment cancelled\n"));
+        assert_eq!(machine.coins, CoinInventory::starting_float());
+        assert_eq!(machine.counters.beverages_brewed, 0);
+    }
+
+    #[test]
+    fn test_buy_with_cash_keeps_the_money() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        let coins = CoinInventory::from_coins(&[500]).unwrap();
+        let sale = machine.buy("Espresso", Payment::Cash(coins)).unwrap();
+        assert_eq!(sale.price, 180);
+        assert_eq!(sale.method, PaymentMethod::Cash);
+        assert_eq!(sale.change.to_string(), "1x 2.00, 1x 1.00, 1x 0.20");
+        assert_eq!(machine.coins.count(500), 1);
+        assert_eq!(
+            machine.coins.total(),
+            CoinInventory::starting_float().total() + 180
+        );
+    }
+
+    #[test]
+    fn test_buy_refuses_without_exact_change() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.coins = CoinInventory::from_coins(&[100, 50]).unwrap();
+        let coins = CoinInventory::from_coins(&[200]).unwrap();
+        assert!(matches!(
+            machine.buy("Espresso", Payment::Cash(coins.clone())),
+            Err(MachineError::NoChange { change: 20 })
+        ));
+        assert!(matches!(
+            machine.buy("Espresso", Payment::Cash(CoinInventory::new())),
+            Err(MachineError::InsufficientPayment {
+                price: 180,
+                paid: 0
+            })
+        ));
+        assert_eq!(machine.coins.total(), 150);
+        assert_eq!(machine.tanks.coffee.level, 100.0);
+
+        let coins = CoinInventory::from_coins(&[200, 20]).unwrap();
+        let sale = machine.buy("Americano", Payment::Cash(coins)).unwrap();
+        assert!(sale.change.is_empty());
+        assert_eq!(machine.coins.total(), 370);
+    }
+
+    #[test]
+    fn test_brewing_drives_the_hardware() {
+        let simulator = SimulatedHardware::new();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_hardware(simulator.hardware());
+        machine.power_on().unwrap();
+        machine.brew("Cappuccino").unwrap();
+        let delivered = simulator.delivered();
+        assert_eq!(delivered.coffee, 30.0);
+        assert_eq!(delivered.water, 30.0);
+        assert_eq!(delivered.milk, 30.0);
+        // 20 s grinding, 15 s pumping, 1.5 s steaming and 3 s frothing
+        assert_eq!(simulator.runtime(), Duration::from_millis(39500));
+        machine.power_off().unwrap();
+        assert!(!simulator.hardware().boiler.is_on());
+    }
+
+    #[test]
+    fn test_hardware_fault_puts_the_machine_into_the_error_state() {
+        let simulator = SimulatedHardware::new();
+        let card = SimulatedCardReader::new();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_hardware(simulator.hardware())
+            .with_card_reader(card.clone());
+        machine.power_on().unwrap();
+        simulator.break_device(Device::Frother);
+        let error = machine.buy("Latte", Payment::Card).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Hardware fault: The milk frother is broken"
+        );
+        assert_eq!(
+            *machine.state(),
+            MachineState::Error("The milk frother is broken".to_string())
+        );
+        assert_eq!(card.charged(), 0);
+        // The coffee was ground and the water pumped before the frother failed
+        assert_eq!(machine.tanks.coffee.level, 70.0);
+        assert_eq!(machine.tanks.milk.level, 100.0);
+        assert!(machine.brew("Espresso").is_err());
+
+        simulator.repair(Device::Frother);
+        machine.reset().unwrap();
+        machine.brew("Latte").unwrap();
+    }
+
+    #[test]
+    fn test_level_sensors_correct_the_levels() {
+        let simulator = SimulatedHardware::new();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_hardware(simulator.hardware());
+        machine.power_on().unwrap();
+        simulator.leak(Ingredient::Milk, 60.0);
+        assert!(matches!(
+            machine.brew("Latte"),
+            Err(MachineError::InsufficientIngredients(_))
+        ));
+        assert_eq!(machine.tanks.milk.level, 40.0);
+    }
+
+    #[test]
+    fn test_failed_brew_refunds_the_payment() {
+        let card = SimulatedCardReader::new();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_card_reader(card.clone());
+        machine.power_on().unwrap();
+        machine.garbage_container.coffee_grounds.level = 90.0;
+        assert!(matches!(
+            machine.buy("Latte", Payment::Card),
+            Err(MachineError::GarbageFull { .. })
+        ));
+        assert_eq!(card.charged(), 0);
+
+        let coins = CoinInventory::from_coins(&[500]).unwrap();
+        assert!(machine.buy("Latte", Payment::Cash(coins)).is_err());
+        assert_eq!(machine.coins, CoinInventory::starting_float());
+
+        machine.empty_grounds_bin().unwrap();
+        machine.buy("Latte", Payment::Card).unwrap();
+        assert_eq!(card.charged(), 300);
+    }
+
+    #[derive(Debug)]
+    struct OfflineCardReader;
+
+    impl CardReader for OfflineCardReader {
+        fn charge(&mut self, _amount: u32) -> Result<(), MachineError> {
+            Ok(())
+        }
+
+        fn refund(&mut self, _amount: u32) -> Result<(), MachineError> {
+            Err(MachineError::PaymentDeclined(
+                "Card reader offline".to_string(),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_progress_is_drawn_while_the_steps_are_driven() {
+        let simulator = SimulatedHardware::new();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_hardware(simulator.hardware());
+        machine.power_on().unwrap();
+        simulator.break_device(Device::Frother);
+        let mut console = ScriptedConsole::new(&[]);
+        assert!(machine
+            .prepare_beverage(&Order::new("Latte"), Some(&mut console))
+            .is_err());
+        let transcript = console.transcript();
+        // The frother fails while the milk is steamed, so its step gets no progress bar
+        assert!(transcript.contains("Step 3/4: Extract with 25 ml water\n"));
+        assert!(transcript.ends_with("100%\nStep 4/4: Steam 50 ml milk\n"));
+    }
+
+    /// A console whose terminal goes away once the brewing program starts
+    #[derive(Debug, Default)]
+    struct BrokenConsole {
+        broken: bool,
+    }
+
+    impl BrokenConsole {
+        fn check(&self) -> Result<(), MachineError> {
+            match self.broken {
+                true => Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl Console for BrokenConsole {
+        fn read_line(&mut self) -> Result<String, MachineError> {
+            self.check()?;
+            Ok(String::new())
+        }
+
+        fn write(&mut self, text: &str) -> Result<(), MachineError> {
+            self.broken |= text.starts_with("Step");
+            self.check()
+        }
+
+        fn clear(&mut self) -> Result<(), MachineError> {
+            self.check()
+        }
+
+        fn draw_progress(&mut self, _progress_bar: &ProgressBar) -> Result<(), MachineError> {
+            self.check()
+        }
+    }
+
+    #[test]
+    fn test_broken_console_doesnt_stop_the_brewing() {
+        let card = SimulatedCardReader::new();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_card_reader(card.clone());
+        machine.power_on().unwrap();
+        let mut console = BrokenConsole::default();
+        let sale = machine
+            .sell(Order::new("Latte"), Payment::Card, Some(&mut console))
+            .unwrap();
+        assert_eq!(sale.beverage.name, "Latte");
+        assert_eq!(card.charged(), 300);
+        assert_eq!(machine.ledger.entries()[0].failure, None);
+    }
+
+    #[test]
+    fn test_failed_refund_keeps_the_brew_error() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_card_reader(OfflineCardReader);
+        machine.power_on().unwrap();
+        machine.garbage_container.coffee_grounds.level = 90.0;
+        assert!(matches!(
+            machine.buy("Latte", Payment::Card),
+            Err(MachineError::GarbageFull { .. })
+        ));
+        let failure = machine.ledger.entries()[0].failure.clone().unwrap();
+        assert!(failure.starts_with("Empty grounds bin"));
+        assert!(failure.ends_with("(refund failed: Payment declined: Card reader offline)"));
+    }
+
+    #[test]
+    fn test_run_session_customizes_the_order() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let mut console =
+            ScriptedConsole::new(&["1", "4", "3", "3", "4", "2", "1", "4", "1", "7", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("Latte costs 4.90\n"));
+        assert!(transcript.contains("Invalid input: No option 7\n"));
+        assert_eq!(machine.tanks.water.level, 55.0);
+        assert_eq!(machine.tanks.coffee.level, 43.75);
+        assert_eq!(machine.tanks.milk.level, 5.0);
+        assert_eq!(machine.tanks.sugar.level, 90.0);
+        assert_eq!(machine.ledger.entries()[0].price, 490);
+    }
+
+    #[test]
+    fn test_customized_order_needs_customized_ingredients() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.tanks.milk.level = 60.0;
+        let order = Order::new("Latte").with_size(Size::Large);
+        let reciepe = machine.customize(&order).unwrap();
+        assert!(!machine.check_ingredients(&reciepe.ingredients).unwrap());
+        match machine.brew(order) {
+            Err(MachineError::InsufficientIngredients(shortfalls)) => {
+                assert_eq!(shortfalls[0].required, 75.0)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let beverage = machine
+            .brew(Order::new("Latte").with_size(Size::Small))
+            .unwrap();
+        assert_eq!(beverage.ingredients.milk, 37.5);
+    }
+
+    #[test]
+    fn test_orders_are_recorded_in_the_ledger() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.tanks.milk.level = 40.0;
+        let mut console = ScriptedConsole::new(&[
+            "1", "4", "", "", "", "1", "5", "", "", "", "2", "1", "9", "7",
+        ]);
+        machine.run(&mut console).unwrap();
+        let entries = machine.ledger.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].recipe, "Latte");
+        assert_eq!(entries[0].payment, None);
+        assert!(entries[0]
+            .failure
+            .as_deref()
+            .unwrap()
+            .starts_with("Not enough ingredients: milk"));
+        assert_eq!(entries[1].recipe, "Mocha");
+        assert_eq!(entries[1].payment, Some(PaymentMethod::Card));
+        assert!(entries[1].succeeded());
+        assert_eq!(entries[1].revenue(), 350);
+        assert_eq!(entries[1].ingredients.cacao, 30.0);
+        assert_eq!(entries[2].recipe, "#9");
+        assert_eq!(entries[2].failure.as_deref(), Some("Unknown reciepe: #9"));
+    }
+
+    #[test]
+    fn test_unwritable_ledger_keeps_the_sale() {
+        // A file in the way of the directory of the ledger file makes the writes fail
+        let blocked = temp_path("machine-ledger-blocked");
+        std::fs::write(&*blocked, "").unwrap();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_ledger_file(&blocked.join("ledger.jsonl"))
+            .unwrap();
+        let mut console = ScriptedConsole::new(&["1", "5", "", "", "", "2", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("Mocha ready to go."));
+        assert!(transcript.contains("The sale could not be written to the ledger"));
+        assert!(machine.ledger.entries()[0].succeeded());
+        assert!(machine.ledger.flush().is_err());
+    }
+
+    #[test]
+    fn test_declined_card_brews_nothing() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_card_reader(SimulatedCardReader::with_balance(100))
+            .with_clock(VirtualClock::new());
+        let mut console = ScriptedConsole::new(&["1", "5", "", "", "", "2", "7"]);
+        machine.run(&mut console).unwrap();
+        assert!(console
+            .transcript()
+            .contains("Payment declined: Only 1.00 left on the card\n"));
+        assert_eq!(machine.counters.beverages_brewed, 0);
+    }
+
+    #[test]
+    fn test_brew_uses_clock() {
+        let clock = VirtualClock::new();
+        clock.advance(Duration::from_secs(60));
+        let mut machine = CoffeeMachine::new().unwrap().with_clock(clock);
+        machine.power_on().unwrap();
+        let beverage = machine.brew("Espresso").unwrap();
+        // Heating up the boiler took 20.4 s
+        assert_eq!(
+            beverage.brewed_at,
+            SystemTime::UNIX_EPOCH + Duration::from_millis(80_400)
+        );
+    }
+
+    #[test]
+    fn test_brew_refuses_when_grounds_bin_is_full() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.garbage_container.coffee_grounds.level = 80.0;
+        match machine.brew("Espresso") {
+            Err(MachineError::GarbageFull { required, free }) => {
+                assert_eq!(required, 30.0);
+                assert_eq!(free, 20.0);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(machine.tanks.water.level, 100.0);
+        assert_eq!(machine.tanks.coffee.level, 100.0);
+        assert_eq!(machine.garbage_container.coffee_grounds.level, 80.0);
+    }
+
+    #[test]
+    fn test_take_service_fills_up_to_capacity() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.tanks.water.capacity = 250.0;
+        machine.take_service().unwrap();
+        assert_eq!(machine.tanks.water.level, 250.0);
+    }
+
+    #[test]
+    fn test_partial_refills_are_logged() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.brew("Mocha").unwrap();
+        assert_eq!(machine.refill_water(20.0).unwrap(), 90.0);
+        assert_eq!(machine.add_coffee_beans(30.0).unwrap(), 100.0);
+        assert_eq!(machine.top_up_milk(10.0).unwrap(), 80.0);
+        assert!(matches!(
+            machine.add_sugar(50.0),
+            Err(MachineError::Overfill { .. })
+        ));
+        assert_eq!(machine.tanks.sugar.level, 70.0);
+        assert_eq!(machine.empty_grounds_bin().unwrap(), 30.0);
+        let log: Vec<_> = machine
+            .service_log
+            .iter()
+            .map(|record| record.to_string())
+            .collect();
+        assert_eq!(
+            log,
+            vec![
+                "Added 20 ml water",
+                "Added 30 g coffee",
+                "Added 10 ml milk",
+                "Removed 30 g coffee grounds",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_service_submenu() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let mut console = ScriptedConsole::new(&[
+            "1", "1", "", "", "", "2", "4", "1", "25", "4", "1", "25", "4", "6", "4", "11", "7",
+        ]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("How much water to add? (30 ml free)\nAdded 25 ml water\n"));
+        assert!(transcript
+            .contains("How much water to add? (5 ml free)\nCannot add 25 ml, only 5 ml free\n"));
+        assert!(transcript.contains("Removed 30 g coffee grounds\n"));
+        assert_eq!(machine.tanks.water.level, 95.0);
+        assert_eq!(machine.garbage_container.coffee_grounds.level, 0.0);
+        assert_eq!(machine.service_log.len(), 2);
+    }
+
+    #[test]
+    fn test_run_session_blocks_brewing_until_descaled() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_maintenance(MaintenanceConfig {
+                descale_litres: Some(0.05),
+                rinse_at_startup: false,
+                ..Default::default()
+            });
+        machine.maintenance_counters.litres_since_descale = 0.06;
+        let mut console = ScriptedConsole::new(&["1", "1", "4", "8", "11", "7"]);
+        machine.run(&mut console).unwrap();
+        let transcript = console.transcript();
+        assert!(transcript.contains("Maintenance: descaling is overdue, brewing is blocked\n"));
+        assert!(transcript.contains("Maintenance required before brewing: descaling\n"));
+        assert!(transcript.contains(concat!(
+            "Descaling...\n",
+            "Phase 1/3: Pump the descaling solution\n",
+        )));
+        assert!(transcript.contains("Descaling done.\n"));
+        assert!(!transcript.contains("Rinsing..."));
+        assert_eq!(machine.maintenance_counters.litres_since_descale, 0.0);
+        assert_eq!(machine.counters.maintenance, 1);
+        assert_eq!(machine.timings[1].operation, "Descaling");
+        assert_eq!(machine.timings[1].elapsed, Duration::from_millis(30300));
+    }
+
+    #[test]
+    fn test_brewing_counts_towards_maintenance() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        machine.brew("Latte").unwrap();
+        machine.brew("Espresso").unwrap();
+        assert_eq!(machine.counters.shots, 2);
+        assert_eq!(machine.counters.milk_drinks, 1);
+        assert!((machine.counters.litres_pumped - 0.06).abs() < 1e-6);
+        assert_eq!(machine.maintenance_counters.milk_drinks_since_clean, 1);
+        machine.maintenance_counters.milk_drinks_since_clean = 20;
+        assert_eq!(
+            machine.maintenance_status(MaintenanceTask::CleanMilkSystem),
+            MaintenanceStatus::Due
+        );
+        machine.maintain(MaintenanceTask::CleanMilkSystem).unwrap();
         assert_eq!(
-            machine.check_ingredients(&reciepe.ingredients).unwrap(),
-            false
+            machine.maintenance_status(MaintenanceTask::CleanMilkSystem),
+            MaintenanceStatus::Ok
         );
+        assert_eq!(*machine.state(), MachineState::Idle);
+    }
+
+    #[test]
+    fn test_brew_requires_idle_machine() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        assert_eq!(*machine.state(), MachineState::Off);
+        assert!(matches!(
+            machine.brew("Espresso"),
+            Err(MachineError::IllegalTransition {
+                state: MachineState::Off,
+                operation: Operation::StartBrew,
+            })
+        ));
+        assert!(matches!(
+            machine.take_service(),
+            Err(MachineError::IllegalTransition { .. })
+        ));
+        machine.power_on().unwrap();
+        assert!(matches!(
+            machine.brew("Tea"),
+            Err(MachineError::UnknownRecipe(_))
+        ));
+        assert_eq!(*machine.state(), MachineState::Idle);
+        machine.brew("Espresso").unwrap();
+        assert_eq!(*machine.state(), MachineState::Idle);
+        machine.power_off().unwrap();
+        assert_eq!(*machine.state(), MachineState::Off);
+    }
+
+    #[test]
+    fn test_main_menu_shows_state() {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        let mut console = ScriptedConsole::new(&["7"]);
+        machine.run(&mut console).unwrap();
+        assert!(console
+            .transcript()
+            .contains("State: idle\nBoiler: 93.0 °C\nChoose your choice\n"));
+        assert_eq!(*machine.state(), MachineState::Off);
+    }
+
+    #[test]
+    fn test_unwritable_state_file_keeps_the_brew() {
+        // A file in the way of the directory of the state file makes the saves fail
+        let blocked = temp_path("machine-state-blocked");
+        std::fs::write(&*blocked, "").unwrap();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_state_file(&blocked.join("state.json"))
+            .unwrap();
+        machine.power_on().unwrap();
+        let beverage = machine.brew("Espresso").unwrap();
+        assert_eq!(beverage.name, "Espresso");
+        assert_eq!(machine.counters.beverages_brewed, 1);
+        assert!(machine.flush_state().is_err());
+
+        std::fs::remove_file(&*blocked).unwrap();
+        std::fs::create_dir(&*blocked).unwrap();
+        machine.flush_state().unwrap();
+        assert!(blocked.join("state.json").exists());
+        std::fs::remove_dir_all(&*blocked).unwrap();
+    }
+
+    #[test]
+    fn test_state_file_survives_restart() {
+        let path = temp_path("restart.json");
+
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_state_file(&path)
+            .unwrap();
+        machine.power_on().unwrap();
+        machine.brew("Latte").unwrap();
+        machine.refill_water(10.0).unwrap();
+        assert!(path.exists());
+
+        let restarted = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_state_file(&path)
+            .unwrap();
+        assert_eq!(restarted.tanks.water.level, 80.0);
+        assert_eq!(restarted.tanks.milk.level, 50.0);
+        assert_eq!(restarted.garbage_container.coffee_grounds.level, 30.0);
+        assert_eq!(restarted.counters.beverages_brewed, 1);
+        assert_eq!(restarted.reciepes, machine.reciepes);
+    }
+
+    #[test]
+    fn test_corrupt_state_file() {
+        let path = temp_path("machine-corrupt.json");
+        std::fs::write(&*path, "not json").unwrap();
+        assert!(matches!(
+            CoffeeMachine::new().unwrap().with_state_file(&path),
+            Err(MachineError::CorruptState { .. })
+        ));
+    }
+
+    #[test]
+    fn test_events() {
+        let (subscriber, events) = crate::events::ChannelSubscriber::new();
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_subscriber(subscriber);
+        machine.power_on().unwrap();
+        events.try_iter().for_each(drop);
+
+        machine.brew("Latte").unwrap();
+        let brewed: Vec<String> = events.try_iter().map(|event| event.to_string()).collect();
+        assert_eq!(
+            brewed,
+            [
+                "State changed from idle to brewing",
+                "Brewing Latte",
+                "Latte ready",
+                "State changed from brewing to idle",
+            ]
+        );
+
+        machine.brew("Latte").unwrap();
+        assert!(events
+            .try_iter()
+            .any(|event| event.to_string() == "milk is critically low (0/100 ml)"));
+        assert!(machine.brew("Latte").is_err());
+        machine.empty_grounds_bin().unwrap();
+        let serviced: Vec<String> = events.try_iter().map(|event| event.to_string()).collect();
+        assert!(serviced.contains(&"Removed 60 g coffee grounds".to_string()));
+        assert!(!serviced.iter().any(|event| event.starts_with("Brewing")));
+
+        drop(events);
+        machine.take_service().unwrap();
+        assert!(machine.subscribers.is_empty());
+    }
+
+    #[test]
+    fn test_journal_file_survives_restart() {
+        let path = temp_path("machine-journal.jsonl");
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_journal_file(&path)
+            .unwrap();
+        machine.power_on().unwrap();
+        machine.brew("Latte").unwrap();
+        machine.empty_grounds_bin().unwrap();
+        machine.brew("Espresso").unwrap();
+
+        let restarted = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new())
+            .with_journal_file(&path)
+            .unwrap();
+        assert_eq!(restarted.tanks, machine.tanks);
+        assert_eq!(restarted.garbage_container.coffee_grounds.level, 30.0);
+        let entries = restarted.journal.as_ref().unwrap().entries();
+        assert!(matches!(
+            entries.last().unwrap().change,
+            Change::StateChanged {
+                state: MachineState::Off
+            }
+        ));
     }
 }
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::io::Write;
+
+use crate::error::MachineError;
+use crate::{clean_terminal, get_input, ProgressBar};
+
+/// The user interface the coffee machine talks to
+pub trait Console {
+    /// Read a line of user input, without the trailing newline
+    fn read_line(&mut self) -> Result<String, MachineError>;
+
+    /// Write text without a trailing newline
+    fn write(&mut self, text: &str) -> Result<(), MachineError>;
+
+    /// Clear the screen
+    fn clear(&mut self) -> Result<(), MachineError>;
+
+    /// Render the current state of a progress bar, replacing the previously rendered one
+    fn draw_progress(&mut self, progress_bar: &ProgressBar) -> Result<(), MachineError>;
+
+    /// Write text followed by a newline
+    fn write_line(&mut self, text: &str) -> Result<(), MachineError> {
+        self.write(text)?;
+        self.write("\n")
+    }
+
+    /// Write a separator line
+    fn print_line(&mut self) -> Result<(), MachineError> {
+        self.write_line(&"~".repeat(crate::LINE_AMOUNT))
+    }
+}
+
+/// A console reading from stdin and writing to stdout
+#[derive(Debug, Default)]
+pub struct StdConsole;
+
+impl Console for StdConsole {
+    fn read_line(&mut self) -> Result<String, MachineError> {
+        get_input()
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), MachineError> {
+        print!("{}", text);
+        std::io::stdout().flush()?;
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), MachineError> {
+        clean_terminal()
+    }
+
+    fn draw_progress(&mut self, progress_bar: &ProgressBar) -> Result<(), MachineError> {
+        progress_bar.draw()
+    }
+}
+
+/// A console replaying scripted input and recording the output
+///
+/// Progress bars overwrite each other like they would on a terminal, so the transcript only
+/// contains the last frame of every progress bar.
+#[derive(Debug, Default)]
+pub struct ScriptedConsole {
+    inputs: VecDeque<String>,
+    transcript: String,
+    progress_start: Option<usize>,
+    clears: usize,
+}
+
+impl ScriptedConsole {
+    /// Create a new scripted console
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The lines returned by `read_line`, in order
+    ///
+    /// # Returns
+    ///
+    /// A new scripted console
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::console::ScriptedConsole;
+    /// let console = ScriptedConsole::new(&["1", "3", "5"]);
+    /// ```
+    pub fn new(inputs: &[&str]) -> Self {
+        ScriptedConsole {
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Get everything written to the console so far
+    pub fn transcript(&self) -> &str {
+        &self.transcript
+    }
+
+    /// Get how often the screen was cleared
+    pub fn clears(&self) -> usize {
+        self.clears
+    }
+}
+
+impl Console for ScriptedConsole {
+    fn read_line(&mut self) -> Result<String, MachineError> {
+        self.inputs.pop_front().ok_or_else(|| {
+            MachineError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "No more scripted input",
+            ))
+        })
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), MachineError> {
+        self.progress_start = None;
+        self.transcript.push_str(text);
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), MachineError> {
+        self.progress_start = None;
+        self.clears += 1;
+
+        Ok(())
+    }
+
+    fn draw_progress(&mut self, progress_bar: &ProgressBar) -> Result<(), MachineError> {
+        let start = match self.progress_start {
+            Some(start) => {
+                self.transcript.truncate(start);
+                start
+            }
+            None => self.transcript.len(),
+        };
+        self.transcript.push_str(&progress_bar.render());
+        self.progress_start = Some(start);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_console_read_line() {
+        let mut console = ScriptedConsole::new(&["1", "2"]);
+        assert_eq!(console.read_line().unwrap(), "1");
+        assert_eq!(console.read_line().unwrap(), "2");
+        assert!(matches!(console.read_line(), Err(MachineError::Io(_))));
+    }
+
+    #[test]
+    fn test_scripted_console_progress_overwrites() {
+        let mut console = ScriptedConsole::new(&[]);
+        let mut progress_bar = ProgressBar::new(100.0);
+        console.write_line("Brewing").unwrap();
+        for i in 0..=100 {
+            progress_bar.set_progress(i as f32).unwrap();
+            console.draw_progress(&progress_bar).unwrap();
+        }
+        console.write_line("").unwrap();
+        assert_eq!(
+            console.transcript(),
+            format!("Brewing\n[{}] 100%\n", "=".repeat(50))
+        );
+    }
+}
@@ -1,4 +1,84 @@
-#[derive(Clone, Debug)]
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::MachineError;
+
+/// The capacity of every ingredient tank of a new coffee machine
+pub const DEFAULT_TANK_CAPACITY: f32 = 100.0;
+
+/// The capacity of the grounds bin of a new coffee machine in g
+pub const DEFAULT_GROUNDS_CAPACITY: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Unit {
+    Millilitre,
+    Gram,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Millilitre => write!(f, "ml"),
+            Unit::Gram => write!(f, "g"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ingredient {
+    Water,
+    Coffee,
+    Milk,
+    Sugar,
+    Cacao,
+}
+
+impl Ingredient {
+    pub const ALL: [Ingredient; 5] = [
+        Ingredient::Water,
+        Ingredient::Coffee,
+        Ingredient::Milk,
+        Ingredient::Sugar,
+        Ingredient::Cacao,
+    ];
+
+    /// Get the unit the ingredient is measured in
+    pub fn unit(&self) -> Unit {
+        match self {
+            Ingredient::Water | Ingredient::Milk => Unit::Millilitre,
+            Ingredient::Coffee | Ingredient::Sugar | Ingredient::Cacao => Unit::Gram,
+        }
+    }
+}
+
+impl fmt::Display for Ingredient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Ingredient::Water => "water",
+            Ingredient::Coffee => "coffee",
+            Ingredient::Milk => "milk",
+            Ingredient::Sugar => "sugar",
+            Ingredient::Cacao => "cacao",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Ingredient {
+    type Err = MachineError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ingredient::ALL
+            .into_iter()
+            .find(|ingredient| ingredient.to_string().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| MachineError::InvalidInput(format!("Unknown ingredient: {}", name)))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct IngredientsContainer {
     pub water: f32,
     pub coffee: f32,
@@ -7,7 +87,393 @@ pub struct IngredientsContainer {
     pub cacao: f32,
 }
 
-#[derive(Debug)]
+impl IngredientsContainer {
+    /// Get the amount of an ingredient
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredient` - The ingredient to look up
+    ///
+    /// # Returns
+    ///
+    /// The amount of the ingredient in the container
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::{Ingredient, IngredientsContainer};
+    /// let container = IngredientsContainer {
+    ///     water: 30.0,
+    ///     coffee: 30.0,
+    ///     milk: 0.0,
+    ///     sugar: 0.0,
+    ///     cacao: 0.0,
+    /// };
+    /// assert_eq!(container.get(Ingredient::Water), 30.0);
+    /// ```
+    pub fn get(&self, ingredient: Ingredient) -> f32 {
+        match ingredient {
+            Ingredient::Water => self.water,
+            Ingredient::Coffee => self.coffee,
+            Ingredient::Milk => self.milk,
+            Ingredient::Sugar => self.sugar,
+            Ingredient::Cacao => self.cacao,
+        }
+    }
+
+    /// Get the amount of an ingredient for changing it
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredient` - The ingredient to look up
+    ///
+    /// # Returns
+    ///
+    /// The amount of the ingredient in the container
+    pub fn get_mut(&mut self, ingredient: Ingredient) -> &mut f32 {
+        match ingredient {
+            Ingredient::Water => &mut self.water,
+            Ingredient::Coffee => &mut self.coffee,
+            Ingredient::Milk => &mut self.milk,
+            Ingredient::Sugar => &mut self.sugar,
+            Ingredient::Cacao => &mut self.cacao,
+        }
+    }
+}
+
+/// A container with a capacity and a current fill level
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Container {
+    pub level: f32,
+    pub capacity: f32,
+    pub unit: Unit,
+}
+
+impl Container {
+    /// Create a new full container
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The capacity of the container
+    /// * `unit` - The unit the content is measured in
+    ///
+    /// # Returns
+    ///
+    /// A new container filled up to its capacity
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::{Container, Unit};
+    /// let container = Container::full(100.0, Unit::Millilitre);
+    /// assert_eq!(container.level, 100.0);
+    /// ```
+    pub fn full(capacity: f32, unit: Unit) -> Self {
+        Container {
+            level: capacity,
+            capacity,
+            unit,
+        }
+    }
+
+    /// Create a new empty container
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The capacity of the container
+    /// * `unit` - The unit the content is measured in
+    ///
+    /// # Returns
+    ///
+    /// A new empty container
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::{Container, Unit};
+    /// let container = Container::empty(100.0, Unit::Gram);
+    /// assert_eq!(container.level, 0.0);
+    /// ```
+    pub fn empty(capacity: f32, unit: Unit) -> Self {
+        Container {
+            level: 0.0,
+            capacity,
+            unit,
+        }
+    }
+
+    /// Get the amount that still fits into the container
+    pub fn free_space(&self) -> f32 {
+        (self.capacity - self.level).max(0.0)
+    }
+
+    /// Check if the given amount fits into the container
+    pub fn fits(&self, amount: f32) -> bool {
+        amount <= self.free_space()
+    }
+
+    /// Refill the container
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount to add
+    ///
+    /// # Returns
+    ///
+    /// The new level of the container
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the amount is negative or doesn't fit into the container
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::{Container, Unit};
+    /// let mut container = Container::empty(100.0, Unit::Millilitre);
+    /// assert_eq!(container.refill(60.0).unwrap(), 60.0);
+    /// assert!(container.refill(60.0).is_err());
+    /// ```
+    pub fn refill(&mut self, amount: f32) -> Result<f32, MachineError> {
+        if !amount.is_finite() || amount < 0.0 {
+            return Err(MachineError::InvalidInput(format!(
+                "Cannot add {} {}",
+                amount, self.unit
+            )));
+        }
+        if !self.fits(amount) {
+            return Err(MachineError::Overfill {
+                requested: amount,
+                free: self.free_space(),
+                unit: self.unit,
+            });
+        }
+        self.level += amount;
+
+        Ok(self.level)
+    }
+
+    /// Refill the container, discarding what doesn't fit
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount to add
+    ///
+    /// # Returns
+    ///
+    /// The amount that was actually added
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::{Container, Unit};
+    /// let mut container = Container::empty(100.0, Unit::Millilitre);
+    /// container.refill(60.0).unwrap();
+    /// assert_eq!(container.refill_clamped(60.0), 40.0);
+    /// assert_eq!(container.level, 100.0);
+    /// ```
+    pub fn refill_clamped(&mut self, amount: f32) -> f32 {
+        let added = amount.max(0.0).min(self.free_space());
+        self.level += added;
+        added
+    }
+
+    /// Fill the container up to its capacity
+    ///
+    /// # Returns
+    ///
+    /// The amount that was added
+    pub fn fill_up(&mut self) -> f32 {
+        self.refill_clamped(self.free_space())
+    }
+
+    /// Take an amount out of the container
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount to take
+    pub fn take(&mut self, amount: f32) {
+        self.level -= amount;
+    }
+
+    /// Empty the container
+    ///
+    /// # Returns
+    ///
+    /// The amount that was removed
+    pub fn drain(&mut self) -> f32 {
+        let removed = self.level;
+        self.level = 0.0;
+        removed
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} {}", self.level, self.capacity, self.unit)
+    }
+}
+
+/// The ingredient tanks of a coffee machine
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IngredientTanks {
+    pub water: Container,
+    pub coffee: Container,
+    pub milk: Container,
+    pub sugar: Container,
+    pub cacao: Container,
+}
+
+impl IngredientTanks {
+    /// Create new full tanks
+    ///
+    /// # Arguments
+    ///
+    /// * `capacities` - The capacity of every tank
+    ///
+    /// # Returns
+    ///
+    /// New tanks filled up to their capacities
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::{IngredientTanks, IngredientsContainer};
+    /// let tanks = IngredientTanks::full(&IngredientsContainer {
+    ///     water: 1000.0,
+    ///     coffee: 250.0,
+    ///     milk: 500.0,
+    ///     sugar: 100.0,
+    ///     cacao: 100.0,
+    /// });
+    /// assert_eq!(tanks.water.level, 1000.0);
+    /// ```
+    pub fn full(capacities: &IngredientsContainer) -> Self {
+        let tank =
+            |ingredient: Ingredient| Container::full(capacities.get(ingredient), ingredient.unit());
+        IngredientTanks {
+            water: tank(Ingredient::Water),
+            coffee: tank(Ingredient::Coffee),
+            milk: tank(Ingredient::Milk),
+            sugar: tank(Ingredient::Sugar),
+            cacao: tank(Ingredient::Cacao),
+        }
+    }
+
+    /// Get the tank of an ingredient
+    pub fn get(&self, ingredient: Ingredient) -> &Container {
+        match ingredient {
+            Ingredient::Water => &self.water,
+            Ingredient::Coffee => &self.coffee,
+            Ingredient::Milk => &self.milk,
+            Ingredient::Sugar => &self.sugar,
+            Ingredient::Cacao => &self.cacao,
+        }
+    }
+
+    /// Get the mutable tank of an ingredient
+    pub fn get_mut(&mut self, ingredient: Ingredient) -> &mut Container {
+        match ingredient {
+            Ingredient::Water => &mut self.water,
+            Ingredient::Coffee => &mut self.coffee,
+            Ingredient::Milk => &mut self.milk,
+            Ingredient::Sugar => &mut self.sugar,
+            Ingredient::Cacao => &mut self.cacao,
+        }
+    }
+
+    /// Get the current levels of all tanks
+    ///
+    /// # Returns
+    ///
+    /// The amount of every ingredient in the tanks
+    pub fn levels(&self) -> IngredientsContainer {
+        IngredientsContainer {
+            water: self.water.level,
+            coffee: self.coffee.level,
+            milk: self.milk.level,
+            sugar: self.sugar.level,
+            cacao: self.cacao.level,
+        }
+    }
+}
+
+impl Default for IngredientTanks {
+    fn default() -> Self {
+        Self::full(&IngredientsContainer {
+            water: DEFAULT_TANK_CAPACITY,
+            coffee: DEFAULT_TANK_CAPACITY,
+            milk: DEFAULT_TANK_CAPACITY,
+            sugar: DEFAULT_TANK_CAPACITY,
+            cacao: DEFAULT_TANK_CAPACITY,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GarbageContainer {
-    pub coffee_grounds: f32,
+    pub coffee_grounds: Container,
+}
+
+impl Default for GarbageContainer {
+    fn default() -> Self {
+        GarbageContainer {
+            coffee_grounds: Container::empty(DEFAULT_GROUNDS_CAPACITY, Unit::Gram),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_refill_rejects_overfill() {
+        let mut container = Container::empty(100.0, Unit::Millilitre);
+        container.refill(80.0).unwrap();
+        match container.refill(30.0) {
+            Err(MachineError::Overfill {
+                requested, free, ..
+            }) => {
+                assert_eq!(requested, 30.0);
+                assert_eq!(free, 20.0);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(container.level, 80.0);
+        assert!(container.refill(-5.0).is_err());
+    }
+
+    #[test]
+    fn test_container_refill_clamped() {
+        let mut container = Container::full(100.0, Unit::Gram);
+        container.take(30.0);
+        assert_eq!(container.refill_clamped(50.0), 30.0);
+        assert_eq!(container.level, 100.0);
+        assert_eq!(container.refill_clamped(-10.0), 0.0);
+    }
+
+    #[test]
+    fn test_container_drain() {
+        let mut container = Container::empty(100.0, Unit::Gram);
+        container.refill(40.0).unwrap();
+        assert_eq!(container.drain(), 40.0);
+        assert_eq!(container.level, 0.0);
+    }
+
+    #[test]
+    fn test_ingredient_from_str() {
+        assert_eq!("Milk".parse::<Ingredient>().unwrap(), Ingredient::Milk);
+        assert_eq!(" cacao ".parse::<Ingredient>().unwrap(), Ingredient::Cacao);
+        assert!("honey".parse::<Ingredient>().is_err());
+    }
+
+    #[test]
+    fn test_tanks_units() {
+        let tanks = IngredientTanks::default();
+        assert_eq!(tanks.water.unit, Unit::Millilitre);
+        assert_eq!(tanks.coffee.unit, Unit::Gram);
+        assert_eq!(tanks.milk.unit, Unit::Millilitre);
+        assert_eq!(tanks.get(Ingredient::Cacao).capacity, DEFAULT_TANK_CAPACITY);
+    }
 }
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// Counters of what the coffee machine did over its lifetime
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Counters {
+    pub beverages_brewed: u64,
+    pub services: u64,
+    /// The beverages brewed with coffee
+    pub shots: u64,
+    /// The beverages brewed with milk
+    pub milk_drinks: u64,
+    /// The litres of water pumped for beverages
+    pub litres_pumped: f32,
+    /// The maintenance programs run, rinsing included
+    pub maintenance: u64,
+}
@@ -0,0 +1,474 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::containers::{Ingredient, IngredientsContainer};
+use crate::error::MachineError;
+use crate::program::{self, Step, StepKind};
+use crate::reciepes::{Reciepes, RecipeRef};
+
+/// The sugar added for extra sugar, in g
+pub const EXTRA_SUGAR: f32 = 10.0;
+
+/// The milk added for extra milk, in ml
+pub const EXTRA_MILK: f32 = 20.0;
+
+/// The surcharge for every extra, in cents
+pub const EXTRA_PRICE: u32 = 20;
+
+/// The duration of the step adding an extra, in seconds
+const EXTRA_SECONDS: f32 = 1.0;
+
+/// The size of a beverage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Size {
+    Small,
+    #[default]
+    Medium,
+    Large,
+}
+
+impl Size {
+    pub const ALL: [Size; 3] = [Size::Small, Size::Medium, Size::Large];
+
+    /// Get the factor the ingredients and the price are scaled with
+    pub fn factor(&self) -> f32 {
+        match self {
+            Size::Small => 0.75,
+            Size::Medium => 1.0,
+            Size::Large => 1.5,
+        }
+    }
+}
+
+/// The strength of a beverage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strength {
+    Weak,
+    #[default]
+    Normal,
+    Strong,
+}
+
+impl Strength {
+    pub const ALL: [Strength; 3] = [Strength::Weak, Strength::Normal, Strength::Strong];
+
+    /// Get the factor the coffee is scaled with
+    pub fn factor(&self) -> f32 {
+        match self {
+            Strength::Weak => 0.75,
+            Strength::Normal => 1.0,
+            Strength::Strong => 1.25,
+        }
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Size::Small => write!(f, "small"),
+            Size::Medium => write!(f, "medium"),
+            Size::Large => write!(f, "large"),
+        }
+    }
+}
+
+impl fmt::Display for Strength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strength::Weak => write!(f, "weak"),
+            Strength::Normal => write!(f, "normal"),
+            Strength::Strong => write!(f, "strong"),
+        }
+    }
+}
+
+impl FromStr for Size {
+    type Err = MachineError;
+
+    fn from_str(size: &str) -> Result<Self, Self::Err> {
+        Size::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_string().eq_ignore_ascii_case(size))
+            .ok_or_else(|| MachineError::InvalidInput(format!("Unknown size: {}", size)))
+    }
+}
+
+impl FromStr for Strength {
+    type Err = MachineError;
+
+    fn from_str(strength: &str) -> Result<Self, Self::Err> {
+        Strength::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_string().eq_ignore_ascii_case(strength))
+            .ok_or_else(|| MachineError::InvalidInput(format!("Unknown strength: {}", strength)))
+    }
+}
+
+/// The changes a customer makes to a reciepe
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Customization {
+    pub size: Size,
+    pub strength: Strength,
+    pub extra_sugar: bool,
+    pub extra_milk: bool,
+}
+
+impl Customization {
+    /// Check if the customization leaves the reciepe unchanged
+    pub fn is_default(&self) -> bool {
+        *self == Customization::default()
+    }
+
+    /// Customize the ingredients of a reciepe
+    ///
+    /// The size scales all ingredients, the strength additionally scales the coffee. Extra sugar
+    /// and extra milk are added on top, whatever the size.
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredients` - The ingredients of the base reciepe
+    ///
+    /// # Returns
+    ///
+    /// The customized ingredients
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::customization::{Customization, Size, Strength};
+    /// use rusty_coffeemachine::IngredientsContainer;
+    /// let customization = Customization {
+    ///     size: Size::Large,
+    ///     strength: Strength::Strong,
+    ///     extra_sugar: true,
+    ///     ..Default::default()
+    /// };
+    /// let ingredients = customization.apply(&IngredientsContainer {
+    ///     water: 30.0,
+    ///     coffee: 20.0,
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(ingredients.water, 45.0);
+    /// assert_eq!(ingredients.coffee, 37.5);
+    /// assert_eq!(ingredients.sugar, 10.0);
+    /// ```
+    pub fn apply(&self, ingredients: &IngredientsContainer) -> IngredientsContainer {
+        let mut customized = IngredientsContainer::default();
+        for ingredient in Ingredient::ALL {
+            *customized.get_mut(ingredient) = ingredients.get(ingredient) * self.size.factor();
+        }
+        customized.coffee *= self.strength.factor();
+        if self.extra_sugar {
+            customized.sugar += EXTRA_SUGAR;
+        }
+        if self.extra_milk {
+            customized.milk += EXTRA_MILK;
+        }
+        customized
+    }
+
+    /// Customize the price of a reciepe
+    ///
+    /// The size scales the price, rounded to 5 cents, and every extra costs a surcharge.
+    ///
+    /// # Arguments
+    ///
+    /// * `price` - The price of the base reciepe in cents
+    ///
+    /// # Returns
+    ///
+    /// The customized price in cents
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::customization::{Customization, Size};
+    /// let customization = Customization {
+    ///     size: Size::Small,
+    ///     extra_milk: true,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(customization.price(300), 245);
+    /// ```
+    pub fn price(&self, price: u32) -> u32 {
+        let scaled = (price as f32 * self.size.factor() / 5.0).round() as u32 * 5;
+        let extras = u32::from(self.extra_sugar) + u32::from(self.extra_milk);
+        scaled + extras * EXTRA_PRICE
+    }
+
+    /// Customize a brewing program
+    ///
+    /// The amounts are scaled like the ingredients, the durations stay the same. The extras are
+    /// added as steps of their own, before a final stir.
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - The steps of the base reciepe
+    ///
+    /// # Returns
+    ///
+    /// The customized steps, empty if the base reciepe has no brewing program
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::customization::{Customization, Size};
+    /// use rusty_coffeemachine::program::{Step, StepKind};
+    /// let customization = Customization {
+    ///     size: Size::Large,
+    ///     extra_sugar: true,
+    ///     ..Default::default()
+    /// };
+    /// let steps = customization.apply_steps(&[
+    ///     Step::new(StepKind::Extract, 30.0, 5.0),
+    ///     Step::new(StepKind::Stir, 0.0, 2.0),
+    /// ]);
+    /// assert_eq!(steps[0].amount, 45.0);
+    /// assert_eq!(steps[1].kind, StepKind::AddSugar);
+    /// assert_eq!(steps[2].kind, StepKind::Stir);
+    /// ```
+    pub fn apply_steps(&self, steps: &[Step]) -> Vec<Step> {
+        if steps.is_empty() {
+            return Vec::new();
+        }
+        let mut customized: Vec<Step> = steps
+            .iter()
+            .map(|step| {
+                let mut amount = step.amount * self.size.factor();
+                if step.kind == StepKind::Grind {
+                    amount *= self.strength.factor();
+                }
+                Step { amount, ..*step }
+            })
+            .collect();
+        let stir = customized.pop_if(|step| step.kind == StepKind::Stir);
+        if self.extra_sugar {
+            customized.push(Step::new(StepKind::AddSugar, EXTRA_SUGAR, EXTRA_SECONDS));
+        }
+        if self.extra_milk {
+            customized.push(Step::new(StepKind::SteamMilk, EXTRA_MILK, EXTRA_SECONDS));
+        }
+        customized.extend(stir);
+        customized
+    }
+
+    /// Customize a reciepe
+    ///
+    /// # Arguments
+    ///
+    /// * `reciepe` - The base reciepe
+    ///
+    /// # Returns
+    ///
+    /// The reciepe with customized ingredients, price and brewing program, keeping its name
+    pub fn customize(&self, reciepe: &Reciepes) -> Reciepes {
+        let steps = self.apply_steps(&reciepe.steps);
+        let ingredients = if steps.is_empty() {
+            self.apply(&reciepe.ingredients)
+        } else {
+            program::totals(&steps)
+        };
+        Reciepes {
+            ingredients,
+            price: self.price(reciepe.price),
+            steps,
+            ..reciepe.clone()
+        }
+    }
+}
+
+impl fmt::Display for Customization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.size, self.strength)?;
+        if self.extra_sugar {
+            write!(f, ", extra sugar")?;
+        }
+        if self.extra_milk {
+            write!(f, ", extra milk")?;
+        }
+        Ok(())
+    }
+}
+
+/// An order of a reciepe with its customization
+///
+/// Everything referencing a reciepe converts into an order without customization, so
+/// `machine.brew("Latte")` orders a medium, normal Latte.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub recipe: RecipeRef,
+    #[serde(flatten)]
+    pub customization: Customization,
+}
+
+impl Order {
+    /// Create an order without customization
+    ///
+    /// # Arguments
+    ///
+    /// * `recipe` - The menu number (starting at 1) or the name of the reciepe
+    ///
+    /// # Returns
+    ///
+    /// The order
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::customization::{Order, Size, Strength};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let order = Order::new("Espresso")
+    ///     .with_size(Size::Small)
+    ///     .with_strength(Strength::Strong);
+    /// let beverage = machine.brew(order).unwrap();
+    /// assert_eq!(beverage.ingredients.water, 22.5);
+    /// ```
+    pub fn new(recipe: impl Into<RecipeRef>) -> Self {
+        Order {
+            recipe: recipe.into(),
+            customization: Customization::default(),
+        }
+    }
+
+    /// Order another size
+    pub fn with_size(mut self, size: Size) -> Self {
+        self.customization.size = size;
+        self
+    }
+
+    /// Order another strength
+    pub fn with_strength(mut self, strength: Strength) -> Self {
+        self.customization.strength = strength;
+        self
+    }
+
+    /// Order extra sugar
+    pub fn with_extra_sugar(mut self) -> Self {
+        self.customization.extra_sugar = true;
+        self
+    }
+
+    /// Order extra milk
+    pub fn with_extra_milk(mut self) -> Self {
+        self.customization.extra_milk = true;
+        self
+    }
+}
+
+impl From<RecipeRef> for Order {
+    fn from(recipe: RecipeRef) -> Self {
+        Order::new(recipe)
+    }
+}
+
+impl From<usize> for Order {
+    fn from(id: usize) -> Self {
+        Order::new(id)
+    }
+}
+
+impl From<&str> for Order {
+    fn from(name: &str) -> Self {
+        Order::new(name)
+    }
+}
+
+impl From<String> for Order {
+    fn from(name: String) -> Self {
+        Order::new(name)
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.customization.is_default() {
+            write!(f, "{}", self.recipe)
+        } else {
+            write!(f, "{} ({})", self.recipe, self.customization)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_customization_changes_nothing() {
+        let reciepe = &Reciepes::get_reciepes().unwrap()[3];
+        let customized = Customization::default().customize(reciepe);
+        assert_eq!(customized, *reciepe);
+    }
+
+    #[test]
+    fn test_weak_small_latte() {
+        let reciepe = &Reciepes::get_reciepes().unwrap()[3];
+        let customization = Customization {
+            size: Size::Small,
+            strength: Strength::Weak,
+            extra_milk: true,
+            ..Default::default()
+        };
+        let customized = customization.customize(reciepe);
+        assert_eq!(customized.name, "Latte");
+        assert_eq!(customized.ingredients.water, 22.5);
+        assert_eq!(customized.ingredients.coffee, 16.875);
+        assert_eq!(customized.ingredients.milk, 57.5);
+        assert_eq!(customized.price, 245);
+        assert_eq!(customization.to_string(), "small, weak, extra milk");
+        assert_eq!(customized.steps.len(), 5);
+        assert_eq!(
+            customized.steps[4],
+            Step::new(StepKind::SteamMilk, 20.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_customize_reciepe_without_steps() {
+        let reciepe = Reciepes::new(
+            "Ristretto".to_string(),
+            IngredientsContainer {
+                water: 15.0,
+                coffee: 30.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let customization = Customization {
+            strength: Strength::Strong,
+            extra_sugar: true,
+            ..Default::default()
+        };
+        let customized = customization.customize(&reciepe);
+        assert!(customized.steps.is_empty());
+        assert_eq!(customized.ingredients.coffee, 37.5);
+        assert_eq!(customized.ingredients.sugar, 10.0);
+    }
+
+    #[test]
+    fn test_parse_size_and_strength() {
+        assert_eq!("Large".parse::<Size>().unwrap(), Size::Large);
+        assert_eq!("strong".parse::<Strength>().unwrap(), Strength::Strong);
+        assert!("huge".parse::<Size>().is_err());
+    }
+
+    #[test]
+    fn test_order_display() {
+        assert_eq!(Order::new("Latte").to_string(), "Latte");
+        assert_eq!(
+            Order::new(2)
+                .with_size(Size::Large)
+                .with_extra_sugar()
+                .to_string(),
+            "#2 (large, normal, extra sugar)"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::containers::{Ingredient, Unit};
+use crate::format_price;
+use crate::maintenance::MaintenanceTask;
+use crate::state::{MachineState, Operation};
+
+/// The amount of a single ingredient that is missing for a reciepe
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shortfall {
+    pub ingredient: Ingredient,
+    pub required: f32,
+    pub available: f32,
+}
+
+impl Shortfall {
+    /// Get the missing amount
+    ///
+    /// # Returns
+    ///
+    /// The difference between the required and the available amount
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::Ingredient;
+    /// use rusty_coffeemachine::error::Shortfall;
+    /// let shortfall = Shortfall {
+    ///     ingredient: Ingredient::Water,
+    ///     required: 30.0,
+    ///     available: 10.0,
+    /// };
+    /// assert_eq!(shortfall.missing(), 20.0);
+    /// ```
+    pub fn missing(&self) -> f32 {
+        self.required - self.available
+    }
+}
+
+impl fmt::Display for Shortfall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} missing (required {}, available {})",
+            self.ingredient,
+            self.missing(),
+            self.required,
+            self.available
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum MachineError {
+    InsufficientIngredients(Vec<Shortfall>),
+    UnknownRecipe(String),
+    GarbageFull {
+        required: f32,
+        free: f32,
+    },
+    Overfill {
+        requested: f32,
+        free: f32,
+        unit: Unit,
+    },
+    IllegalTransition {
+        state: MachineState,
+        operation: Operation,
+    },
+    InvalidInput(String),
+    InsufficientPayment {
+        price: u32,
+        paid: u32,
+    },
+    NoChange {
+        change: u32,
+    },
+    PaymentDeclined(String),
+    PaymentCancelled,
+    MaintenanceRequired(Vec<MaintenanceTask>),
+    HardwareFault(String),
+    UnknownOrder(u64),
+    InvalidRecipeBook {
+        line: Option<usize>,
+        entry: Option<String>,
+        message: String,
+    },
+    CorruptState {
+        path: PathBuf,
+        message: String,
+    },
+    Io(std::io::Error),
+}
+
+impl MachineError {
+    /// Get the exit code of the command line interface for the error
+    ///
+    /// # Returns
+    ///
+    /// A distinct exit code for every kind of failure
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::MachineError;
+    /// let error = MachineError::UnknownRecipe("Tea".to_string());
+    /// assert_eq!(error.exit_code(), 4);
+    /// ```
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MachineError::Io(_) => 1,
+            MachineError::InvalidInput(_) => 2,
+            MachineError::InsufficientIngredients(_) => 3,
+            MachineError::UnknownRecipe(_) => 4,
+            MachineError::GarbageFull { .. } => 5,
+            MachineError::Overfill { .. } => 6,
+            MachineError::IllegalTransition { .. } => 7,
+            MachineError::InvalidRecipeBook { .. } | MachineError::CorruptState { .. } => 8,
+            MachineError::PaymentDeclined(_) | MachineError::PaymentCancelled => 9,
+            MachineError::InsufficientPayment { .. } => 10,
+            MachineError::NoChange { .. } => 11,
+            MachineError::MaintenanceRequired(_) => 12,
+            MachineError::HardwareFault(_) => 13,
+            MachineError::UnknownOrder(_) => 14,
+        }
+    }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::InsufficientIngredients(shortfalls) => {
+                write!(f, "Not enough ingredients")?;
+                for (i, shortfall) in shortfalls.iter().enumerate() {
+                    let separator = if i == 0 { ": " } else { ", " };
+                    write!(f, "{}{}", separator, shortfall)?;
+                }
+                Ok(())
+            }
+            MachineError::UnknownRecipe(name) => write!(f, "Unknown reciepe: {}", name),
+            MachineError::GarbageFull { required, free } => write!(
+                f,
+                "Empty grounds bin: {} g of coffee grounds don't fit, {} g free",
+                required, free
+            ),
+            MachineError::Overfill {
+                requested,
+                free,
+                unit,
+            } => write!(
+                f,
+                "Cannot add {} {}, only {} {} free",
+                requested, unit, free, unit
+            ),
+            MachineError::IllegalTransition { state, operation } => {
+                write!(f, "Cannot {} while the machine is {}", operation, state)
+            }
+            MachineError::InvalidInput(message) => write!(f, "Invalid input: {}", message),
+            MachineError::InsufficientPayment { price, paid } => write!(
+                f,
+                "Please pay {} more: the price is {}, {} paid",
+                format_price(price.saturating_sub(*paid)),
+                format_price(*price),
+                format_price(*paid)
+            ),
+            MachineError::NoChange { change } => write!(
+                f,
+                "Cannot give {} change, please pay the exact amount",
+                format_price(*change)
+            ),
+            MachineError::PaymentDeclined(reason) => write!(f, "Payment declined: {}", reason),
+            MachineError::PaymentCancelled => write!(f, "Payment cancelled"),
+            MachineError::MaintenanceRequired(tasks) => {
+                write!(f, "Maintenance required before brewing")?;
+                for (i, task) in tasks.iter().enumerate() {
+                    let separator = if i == 0 { ": " } else { ", " };
+                    write!(f, "{}{}", separator, task)?;
+                }
+                Ok(())
+            }
+            MachineError::HardwareFault(message) => write!(f, "Hardware fault: {}", message),
+            MachineError::UnknownOrder(id) => write!(f, "Unknown order: #{}", id),
+            MachineError::InvalidRecipeBook {
+                line,
+                entry,
+                message,
+            } => {
+                write!(f, "Invalid reciepe book")?;
+                if let Some(line) = line {
+                    write!(f, " at line {}", line)?;
+                }
+                if let Some(entry) = entry {
+                    write!(f, " in reciepe {}", entry)?;
+                }
+                write!(f, ": {}", message)
+            }
+            MachineError::CorruptState { path, message } => {
+                write!(f, "Corrupt state file {}: {}", path.display(), message)
+            }
+            MachineError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MachineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MachineError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for MachineError {
+    fn from(e: std::io::Error) -> Self {
+        MachineError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortfall_missing() {
+        let shortfall = Shortfall {
+            ingredient: Ingredient::Milk,
+            required: 50.0,
+            available: 20.0,
+        };
+        assert_eq!(shortfall.missing(), 30.0);
+    }
+
+    #[test]
+    fn test_insufficient_ingredients_display() {
+        let error = MachineError::InsufficientIngredients(vec![
+            Shortfall {
+                ingredient: Ingredient::Water,
+                required: 30.0,
+                available: 10.0,
+            },
+            Shortfall {
+                ingredient: Ingredient::Milk,
+                required: 50.0,
+                available: 0.0,
+            },
+        ]);
+        assert_eq!(
+            error.to_string(),
+            "Not enough ingredients: water: 20 missing (required 30, available 10), milk: 50 missing (required 50, available 0)"
+        );
+    }
+
+    #[test]
+    fn test_invalid_recipe_book_display() {
+        let error = MachineError::InvalidRecipeBook {
+            line: Some(12),
+            entry: Some("Latte".to_string()),
+            message: "The name is used more than once".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Invalid reciepe book at line 12 in reciepe Latte: The name is used more than once"
+        );
+    }
+
+    #[test]
+    fn test_insufficient_payment_display() {
+        let underpaid = MachineError::InsufficientPayment {
+            price: 300,
+            paid: 250,
+        };
+        assert_eq!(
+            underpaid.to_string(),
+            "Please pay 0.50 more: the price is 3.00, 2.50 paid"
+        );
+        let overpaid = MachineError::InsufficientPayment {
+            price: 100,
+            paid: 200,
+        };
+        assert_eq!(
+            overpaid.to_string(),
+            "Please pay 0.00 more: the price is 1.00, 2.00 paid"
+        );
+    }
+
+    #[test]
+    fn test_payment_errors_display() {
+        let error = MachineError::NoChange { change: 30 };
+        assert_eq!(
+            error.to_string(),
+            "Cannot give 0.30 change, please pay the exact amount"
+        );
+    }
+
+    #[test]
+    fn test_maintenance_required_display() {
+        let error = MachineError::MaintenanceRequired(vec![
+            MaintenanceTask::Descale,
+            MaintenanceTask::CleanMilkSystem,
+        ]);
+        assert_eq!(
+            error.to_string(),
+            "Maintenance required before brewing: descaling, milk system cleaning"
+        );
+        assert_eq!(error.exit_code(), 12);
+    }
+
+    #[test]
+    fn test_from_io_error() {
+        let error: MachineError =
+            std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "closed").into();
+        assert!(matches!(error, MachineError::Io(_)));
+    }
+}
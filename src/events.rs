@@ -0,0 +1,194 @@
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::SystemTime;
+
+use crate::beverage::Beverage;
+use crate::containers::Container;
+use crate::maintenance::MaintenanceTask;
+use crate::queue::OrderId;
+use crate::service::ServiceRecord;
+use crate::state::MachineState;
+use crate::stock::StockWarning;
+
+/// Something the coffee machine did
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventKind {
+    /// The coffee machine changed its state
+    StateChanged {
+        from: MachineState,
+        to: MachineState,
+    },
+    /// The ingredients were checked and the reciepe is being brewed
+    BrewStarted { recipe: String },
+    /// A beverage is ready
+    BrewFinished { beverage: Beverage },
+    /// Brewing a reciepe failed after it started
+    BrewFailed { recipe: String, reason: String },
+    /// An ingredient dropped to a lower stock level
+    IngredientLow(StockWarning),
+    /// A brew was refused because the grounds bin is too full
+    GarbageFull(Container),
+    /// An ingredient was refilled or the grounds bin emptied
+    ServicePerformed(ServiceRecord),
+    /// A maintenance program finished
+    MaintenancePerformed(MaintenanceTask),
+    /// An order was added to the queue
+    OrderQueued { id: OrderId, name: String },
+    /// A queued order was cancelled
+    OrderCancelled(OrderId),
+}
+
+/// An event emitted by the coffee machine, with the time of its clock
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub at: SystemTime,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            EventKind::StateChanged { from, to } => {
+                write!(f, "State changed from {} to {}", from, to)
+            }
+            EventKind::BrewStarted { recipe } => write!(f, "Brewing {}", recipe),
+            EventKind::BrewFinished { beverage } => write!(f, "{} ready", beverage.name),
+            EventKind::BrewFailed { recipe, reason } => {
+                write!(f, "Brewing {} failed: {}", recipe, reason)
+            }
+            EventKind::IngredientLow(warning) => write!(f, "{}", warning),
+            EventKind::GarbageFull(bin) => write!(f, "The grounds bin is full ({})", bin),
+            EventKind::ServicePerformed(record) => write!(f, "{}", record),
+            EventKind::MaintenancePerformed(task) => write!(f, "Finished {}", task),
+            EventKind::OrderQueued { id, name } => write!(f, "Order #{} queued: {}", id, name),
+            EventKind::OrderCancelled(id) => write!(f, "Order #{} cancelled", id),
+        }
+    }
+}
+
+/// A receiver of the events of a coffee machine
+///
+/// Subscribers are notified while the coffee machine operates, so they should return quickly
+/// and leave slow work like writing to the network to another thread.
+pub trait Subscriber: fmt::Debug + Send {
+    /// Receive an event
+    fn notify(&mut self, event: &Event);
+
+    /// Check if the subscriber still wants events, the coffee machine drops it otherwise
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+/// A subscriber sending the events into a channel
+///
+/// The subscriber disconnects once the receiving end is gone.
+#[derive(Clone, Debug)]
+pub struct ChannelSubscriber {
+    sender: Sender<Event>,
+    connected: bool,
+}
+
+impl ChannelSubscriber {
+    /// Create a subscriber with a new channel
+    ///
+    /// # Returns
+    ///
+    /// The subscriber and the receiving end of its channel
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::events::{ChannelSubscriber, EventKind};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let (subscriber, events) = ChannelSubscriber::new();
+    /// let mut machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_clock(VirtualClock::new())
+    ///     .with_subscriber(subscriber);
+    /// machine.power_on().unwrap();
+    /// machine.brew("Latte").unwrap();
+    /// let brewed: Vec<String> = events
+    ///     .try_iter()
+    ///     .filter(|event| matches!(event.kind, EventKind::BrewFinished { .. }))
+    ///     .map(|event| event.to_string())
+    ///     .collect();
+    /// assert_eq!(brewed, ["Latte ready"]);
+    /// ```
+    pub fn new() -> (Self, Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            ChannelSubscriber {
+                sender,
+                connected: true,
+            },
+            receiver,
+        )
+    }
+}
+
+impl Subscriber for ChannelSubscriber {
+    fn notify(&mut self, event: &Event) {
+        // Nobody is listening anymore
+        self.connected = self.sender.send(event.clone()).is_ok();
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::containers::{Ingredient, Unit};
+    use crate::service::ServiceAction;
+    use crate::stock::StockLevel;
+
+    fn event(kind: EventKind) -> Event {
+        Event {
+            kind,
+            at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_event_display() {
+        let changed = event(EventKind::StateChanged {
+            from: MachineState::Idle,
+            to: MachineState::Brewing,
+        });
+        assert_eq!(changed.to_string(), "State changed from idle to brewing");
+        let low = event(EventKind::IngredientLow(StockWarning {
+            ingredient: Ingredient::Milk,
+            level: StockLevel::Low,
+            tank: Container {
+                capacity: 100.0,
+                level: 25.0,
+                unit: Unit::Millilitre,
+            },
+        }));
+        assert_eq!(low.to_string(), "milk is running low (25/100 ml)");
+        let service = event(EventKind::ServicePerformed(ServiceRecord {
+            action: ServiceAction::EmptyGroundsBin,
+            amount: 30.0,
+            unit: Unit::Gram,
+            performed_at: SystemTime::UNIX_EPOCH,
+        }));
+        assert_eq!(service.to_string(), "Removed 30 g coffee grounds");
+    }
+
+    #[test]
+    fn test_channel_subscriber() {
+        let (mut subscriber, events) = ChannelSubscriber::new();
+        subscriber.notify(&event(EventKind::OrderCancelled(3)));
+        assert_eq!(events.recv().unwrap().to_string(), "Order #3 cancelled");
+
+        assert!(subscriber.is_connected());
+
+        drop(events);
+        subscriber.notify(&event(EventKind::OrderCancelled(4)));
+        assert!(!subscriber.is_connected());
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::capitalize;
+use crate::containers::{Ingredient, IngredientsContainer};
+use crate::ledger::{unix_seconds, Ledger};
+use crate::reciepes::Reciepes;
+
+/// How far back the sales history is used to estimate the consumption
+pub const HISTORY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The shortest span the consumption is averaged over, so a few sales in a row don't suggest the
+/// machine runs dry within minutes
+pub const MIN_SPAN: Duration = Duration::from_secs(60 * 60);
+
+/// How many cups of a reciepe can still be made
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecipeForecast {
+    /// The menu number, starting at 1
+    pub id: usize,
+    pub name: String,
+    /// The number of cups, `None` for reciepes that use no ingredients
+    pub cups: Option<u32>,
+    /// The ingredient limiting the number of cups
+    pub limiting: Option<Ingredient>,
+}
+
+impl fmt::Display for RecipeForecast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.cups, self.limiting) {
+            (Some(1), Some(limiting)) => {
+                write!(f, "{}: 1 cup left, limited by {}", self.name, limiting)
+            }
+            (Some(cups), Some(limiting)) => write!(
+                f,
+                "{}: {} cups left, limited by {}",
+                self.name, cups, limiting
+            ),
+            _ => write!(f, "{}: no ingredients needed", self.name),
+        }
+    }
+}
+
+/// When an ingredient runs out at the recent consumption
+#[derive(Clone, Debug, PartialEq)]
+pub struct IngredientForecast {
+    pub ingredient: Ingredient,
+    /// The amount left
+    pub level: f32,
+    /// The average consumption per hour, `None` without recent sales
+    pub per_hour: Option<f32>,
+    /// The estimated time until the ingredient is exhausted, `None` if it isn't consumed
+    pub remaining: Option<Duration>,
+}
+
+impl fmt::Display for IngredientForecast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.ingredient.unit();
+        write!(
+            f,
+            "{}: {} {} left",
+            capitalize(&self.ingredient.to_string()),
+            self.level,
+            unit
+        )?;
+        match (self.per_hour, self.remaining) {
+            (Some(per_hour), Some(remaining)) => write!(
+                f,
+                ", empty in about {} at {:.1} {}/h",
+                format_duration(remaining),
+                per_hour,
+                unit
+            ),
+            _ => write!(f, ", not used recently"),
+        }
+    }
+}
+
+/// The availability forecast of a coffee machine
+#[derive(Clone, Debug, PartialEq)]
+pub struct Forecast {
+    /// The reciepes in menu order
+    pub recipes: Vec<RecipeForecast>,
+    /// The ingredients in the order of `Ingredient::ALL`
+    pub ingredients: Vec<IngredientForecast>,
+}
+
+impl Forecast {
+    /// Compute the availability forecast
+    ///
+    /// The consumption is averaged over the successful orders of the last week, at least over an
+    /// hour.
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - The amount of every ingredient left
+    /// * `reciepes` - The reciepes on the menu
+    /// * `ledger` - The sales history
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The forecast
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::forecast::Forecast;
+    /// use rusty_coffeemachine::ledger::Ledger;
+    /// use rusty_coffeemachine::{IngredientTanks, Reciepes};
+    /// let levels = IngredientTanks::default().levels();
+    /// let reciepes = Reciepes::get_reciepes().unwrap();
+    /// let forecast = Forecast::new(&levels, &reciepes, &Ledger::new(), std::time::SystemTime::now());
+    /// assert_eq!(forecast.recipes[0].cups, Some(3));
+    /// assert_eq!(forecast.runs_out_first(), None);
+    /// ```
+    pub fn new(
+        levels: &IngredientsContainer,
+        reciepes: &[Reciepes],
+        ledger: &Ledger,
+        now: SystemTime,
+    ) -> Self {
+        let recipes = reciepes
+            .iter()
+            .enumerate()
+            .map(|(i, reciepe)| {
+                let limit = Ingredient::ALL
+                    .into_iter()
+                    .filter(|&ingredient| reciepe.ingredients.get(ingredient) > 0.0)
+                    .map(|ingredient| {
+                        let cups = levels.get(ingredient) / reciepe.ingredients.get(ingredient);
+                        // Tolerate rounding errors, 90 / 30 has to be 3 cups
+                        ((cups + 1e-4).floor().max(0.0) as u32, ingredient)
+                    })
+                    .min_by_key(|(cups, _)| *cups);
+                RecipeForecast {
+                    id: i + 1,
+                    name: reciepe.name.clone(),
+                    cups: limit.map(|(cups, _)| cups),
+                    limiting: limit.map(|(_, ingredient)| ingredient),
+                }
+            })
+            .collect();
+
+        let from = now.checked_sub(HISTORY).unwrap_or(SystemTime::UNIX_EPOCH);
+        let recent = ledger.between(from, now + Duration::from_secs(1));
+        let mut consumed = IngredientsContainer::default();
+        for entry in recent.iter().filter(|entry| entry.succeeded()) {
+            for ingredient in Ingredient::ALL {
+                *consumed.get_mut(ingredient) += entry.ingredients.get(ingredient);
+            }
+        }
+        let span = recent.first().map(|first| {
+            let seconds = unix_seconds(now).saturating_sub(first.timestamp);
+            Duration::from_secs(seconds).max(MIN_SPAN)
+        });
+
+        let ingredients = Ingredient::ALL
+            .into_iter()
+            .map(|ingredient| {
+                let level = levels.get(ingredient);
+                let per_hour =
+                    span.map(|span| consumed.get(ingredient) / span.as_secs_f32() * 3600.0);
+                let remaining = span
+                    .filter(|_| consumed.get(ingredient) > 0.0)
+                    .map(|span| span.mul_f32(level.max(0.0) / consumed.get(ingredient)));
+                IngredientForecast {
+                    ingredient,
+                    level,
+                    per_hour,
+                    remaining,
+                }
+            })
+            .collect();
+
+        Forecast {
+            recipes,
+            ingredients,
+        }
+    }
+
+    /// Get the ingredient that runs out first at the recent consumption
+    ///
+    /// # Returns
+    ///
+    /// The forecast of the ingredient, `None` without recent sales
+    pub fn runs_out_first(&self) -> Option<&IngredientForecast> {
+        self.ingredients
+            .iter()
+            .filter(|forecast| forecast.remaining.is_some())
+            .min_by_key(|forecast| forecast.remaining)
+    }
+}
+
+/// Format a duration in days, hours and minutes
+///
+/// # Arguments
+///
+/// * `duration` - The duration
+///
+/// # Returns
+///
+/// The days and hours, or the hours and minutes for less than a day, e.g. `2 d 5 h` or `3 h 20 min`
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use rusty_coffeemachine::forecast::format_duration;
+/// assert_eq!(format_duration(Duration::from_secs(12_000)), "3 h 20 min");
+/// assert_eq!(format_duration(Duration::from_secs(20)), "0 min");
+/// ```
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{} min", minutes),
+        (0, hours) => format!("{} h {} min", hours, minutes),
+        (days, hours) => format!("{} d {} h", days, hours),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::containers::IngredientTanks;
+    use crate::ledger::LedgerEntry;
+
+    fn sale(recipe: &Reciepes, timestamp: u64) -> LedgerEntry {
+        LedgerEntry {
+            recipe: recipe.name.clone(),
+            ingredients: recipe.ingredients.clone(),
+            price: recipe.price,
+            payment: None,
+            failure: None,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_cups_left() {
+        let reciepes = Reciepes::get_reciepes().unwrap();
+        let mut levels = IngredientTanks::default().levels();
+        levels.milk = 90.0;
+        levels.water = 65.0;
+        let forecast = Forecast::new(&levels, &reciepes, &Ledger::new(), SystemTime::UNIX_EPOCH);
+
+        assert_eq!(forecast.recipes[0].cups, Some(2));
+        assert_eq!(forecast.recipes[0].limiting, Some(Ingredient::Water));
+        assert_eq!(forecast.recipes[3].cups, Some(1));
+        assert_eq!(forecast.recipes[3].limiting, Some(Ingredient::Milk));
+        assert_eq!(
+            forecast.recipes[3].to_string(),
+            "Latte: 1 cup left, limited by milk"
+        );
+        assert!(forecast.ingredients.iter().all(|i| i.remaining.is_none()));
+    }
+
+    #[test]
+    fn test_time_until_empty() {
+        let reciepes = Reciepes::get_reciepes().unwrap();
+        let mut ledger = Ledger::new();
+        // Two lattes within four hours, a week old sale is ignored
+        ledger.record(sale(&reciepes[4], 0));
+        let start = HISTORY.as_secs();
+        ledger.record(sale(&reciepes[3], start + 3600));
+        ledger.record(sale(&reciepes[3], start + 7200));
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(start + 4 * 3600);
+
+        let levels = IngredientTanks::default().levels();
+        let forecast = Forecast::new(&levels, &reciepes, &ledger, now);
+        let milk = &forecast.ingredients[2];
+        assert_eq!(milk.ingredient, Ingredient::Milk);
+        assert!((milk.per_hour.unwrap() - 100.0 / 3.0).abs() < 1e-3);
+        assert_eq!(milk.remaining.map(format_duration).unwrap(), "3 h 0 min");
+        assert_eq!(forecast.ingredients[3].remaining, None);
+        assert_eq!(
+            forecast.runs_out_first().unwrap().ingredient,
+            Ingredient::Milk
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, TryLockError};
+
+use crate::beverage::Beverage;
+use crate::coffeemachine::CoffeeMachine;
+use crate::containers::{GarbageContainer, IngredientTanks, IngredientsContainer};
+use crate::counters::Counters;
+use crate::customization::Order;
+use crate::error::MachineError;
+use crate::queue::{OrderId, OrderQueue, OrderStatus, Priority};
+use crate::state::MachineState;
+use crate::stock::{StockThresholds, UnavailableRecipe};
+
+/// A copy of what a coffee machine looked like after its last operation
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineSnapshot {
+    pub state: MachineState,
+    pub tanks: IngredientTanks,
+    pub garbage_container: GarbageContainer,
+    pub counters: Counters,
+    pub thresholds: StockThresholds,
+    pub queue: OrderQueue,
+    /// The reciepes that can't be brewed with the ingredients left
+    pub unavailable: Vec<UnavailableRecipe>,
+}
+
+impl MachineSnapshot {
+    /// Take a snapshot of a coffee machine
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The coffee machine
+    ///
+    /// # Returns
+    ///
+    /// The snapshot
+    pub fn of(machine: &CoffeeMachine) -> Self {
+        MachineSnapshot {
+            state: machine.state().clone(),
+            tanks: machine.tanks.clone(),
+            garbage_container: machine.garbage_container.clone(),
+            counters: machine.counters.clone(),
+            thresholds: machine.thresholds.clone(),
+            queue: machine.queue.clone(),
+            unavailable: machine.unavailable_recipes(),
+        }
+    }
+}
+
+/// A coffee machine shared between threads
+///
+/// Clones of the handle operate on the same coffee machine, one operation at a time, so an
+/// operation checking the ingredients and using them can't be interleaved with another one.
+/// Reading the snapshot never waits for the coffee machine, not even while it brews.
+#[derive(Clone, Debug)]
+pub struct MachineHandle {
+    machine: Arc<Mutex<CoffeeMachine>>,
+    snapshot: Arc<RwLock<MachineSnapshot>>,
+}
+
+impl MachineHandle {
+    /// Share a coffee machine
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The coffee machine
+    ///
+    /// # Returns
+    ///
+    /// The first handle of the coffee machine
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::handle::MachineHandle;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let handle = MachineHandle::new(CoffeeMachine::new().unwrap());
+    /// assert_eq!(handle.ingredients().water, 100.0);
+    /// ```
+    pub fn new(machine: CoffeeMachine) -> Self {
+        let snapshot = MachineSnapshot::of(&machine);
+        MachineHandle {
+            machine: Arc::new(Mutex::new(machine)),
+            snapshot: Arc::new(RwLock::new(snapshot)),
+        }
+    }
+
+    /// Operate on the coffee machine, waiting for the operation of another handle to finish
+    ///
+    /// The snapshot is updated once the operation is done. A panic during an operation doesn't
+    /// lock the coffee machine for good, the next operation sees it as the panic left it.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation
+    ///
+    /// # Returns
+    ///
+    /// The result of the operation
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::handle::MachineHandle;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// let handle = MachineHandle::new(machine);
+    /// handle.with(|machine| machine.power_on()).unwrap();
+    /// assert_eq!(handle.snapshot().state.to_string(), "idle");
+    /// ```
+    pub fn with<R>(&self, operation: impl FnOnce(&mut CoffeeMachine) -> R) -> R {
+        let mut machine = self.lock();
+        let result = operation(&mut machine);
+        self.refresh(&machine);
+        result
+    }
+
+    /// Operate on the coffee machine unless another handle is operating on it
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation
+    ///
+    /// # Returns
+    ///
+    /// The result of the operation, or `None` if the coffee machine is busy
+    pub fn try_with<R>(&self, operation: impl FnOnce(&mut CoffeeMachine) -> R) -> Option<R> {
+        let mut machine = match self.machine.try_lock() {
+            Ok(machine) => machine,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        let result = operation(&mut machine);
+        self.refresh(&machine);
+        Some(result)
+    }
+
+    /// Get the snapshot taken after the last operation
+    pub fn snapshot(&self) -> MachineSnapshot {
+        self.snapshot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Get the ingredient levels after the last operation
+    pub fn ingredients(&self) -> IngredientsContainer {
+        self.read(|snapshot| snapshot.tanks.levels())
+    }
+
+    /// Get the garbage container after the last operation
+    pub fn garbage(&self) -> GarbageContainer {
+        self.read(|snapshot| snapshot.garbage_container.clone())
+    }
+
+    /// Get the status of an order after the last operation
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the order
+    ///
+    /// # Returns
+    ///
+    /// The status, or `None` if the queue doesn't know the order
+    pub fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.read(|snapshot| snapshot.queue.get(id).map(|queued| queued.status.clone()))
+    }
+
+    /// Brew a beverage, see [`CoffeeMachine::brew`]
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The menu number (starting at 1) or the name of the reciepe, or a customized order
+    ///
+    /// # Returns
+    ///
+    /// The brewed beverage
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if brewing fails
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::handle::MachineHandle;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let handle = MachineHandle::new(machine);
+    /// let other = handle.clone();
+    /// let latte = thread::spawn(move || other.brew("Latte"));
+    /// handle.brew("Espresso").unwrap();
+    /// latte.join().unwrap().unwrap();
+    /// assert_eq!(handle.ingredients().coffee, 40.0);
+    /// ```
+    pub fn brew(&self, order: impl Into<Order>) -> Result<Beverage, MachineError> {
+        let order = order.into();
+        self.with(|machine| machine.brew(order))
+    }
+
+    /// Submit an order to the queue, see [`CoffeeMachine::enqueue`]
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The menu number (starting at 1) or the name of the reciepe, or a customized order
+    /// * `priority` - The priority of the order, higher priorities are brewed first
+    ///
+    /// # Returns
+    ///
+    /// The number of the order
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the reciepe is unknown or there are not enough
+    /// ingredients left besides the ones reserved for the orders in the queue
+    pub fn enqueue(
+        &self,
+        order: impl Into<Order>,
+        priority: Priority,
+    ) -> Result<OrderId, MachineError> {
+        let order = order.into();
+        self.with(|machine| machine.enqueue(order, priority))
+    }
+
+    /// Cancel an order that is still waiting in the queue, see [`CoffeeMachine::cancel_order`]
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the order
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the order is unknown or not waiting anymore
+    pub fn cancel_order(&self, id: OrderId) -> Result<(), MachineError> {
+        self.with(|machine| machine.cancel_order(id))
+    }
+
+    /// Brew the next order in the queue
+    ///
+    /// The order is taken from the queue and brewed in one operation, so no other handle can use
+    /// its reserved ingredients in between. It is marked as brewing in the snapshot before it is
+    /// brewed, so its status can be followed while the coffee machine is busy.
+    ///
+    /// # Returns
+    ///
+    /// The number of the brewed order, or `None` if the queue is empty
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::handle::MachineHandle;
+    /// use rusty_coffeemachine::queue::{OrderStatus, Priority};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let handle = MachineHandle::new(machine);
+    /// let id = handle.enqueue("Latte", Priority::Normal).unwrap();
+    /// assert_eq!(handle.process_next(), Some(id));
+    /// assert!(matches!(handle.order_status(id), Some(OrderStatus::Ready(_))));
+    /// ```
+    pub fn process_next(&self) -> Option<OrderId> {
+        let mut machine = self.lock();
+        let (id, order) = machine.queue.start_next()?;
+        self.refresh(&machine);
+        machine.brew_queued(id, order);
+        self.refresh(&machine);
+
+        Some(id)
+    }
+
+    /// Get the coffee machine back once all other handles are dropped
+    ///
+    /// # Returns
+    ///
+    /// The coffee machine, or `None` if another handle still shares it
+    pub fn into_inner(self) -> Option<CoffeeMachine> {
+        let machine = Arc::try_unwrap(self.machine).ok()?;
+        Some(machine.into_inner().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Wait for the coffee machine, recovering it from a panicked operation
+    fn lock(&self) -> MutexGuard<'_, CoffeeMachine> {
+        self.machine.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Read from the snapshot
+    fn read<R>(&self, read: impl FnOnce(&MachineSnapshot) -> R) -> R {
+        read(&self.snapshot.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Replace the snapshot, the caller holds the coffee machine
+    fn refresh(&self, machine: &CoffeeMachine) {
+        *self
+            .snapshot
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = MachineSnapshot::of(machine);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use std::thread;
+
+    fn handle() -> MachineHandle {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        MachineHandle::new(machine)
+    }
+
+    #[test]
+    fn test_concurrent_brews_never_overdraw() {
+        let handle = handle();
+        let brews: Vec<_> = (0..8)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || handle.brew("Espresso").is_ok())
+            })
+            .collect();
+        let brewed = brews
+            .into_iter()
+            .map(|brew| brew.join().unwrap())
+            .filter(|&ok| ok)
+            .count();
+
+        // 100 g of coffee are enough for three espressos of 30 g
+        assert_eq!(brewed, 3);
+        let ingredients = handle.ingredients();
+        assert_eq!(ingredients.coffee, 10.0);
+        assert!(ingredients.water >= 0.0);
+        assert_eq!(handle.garbage().coffee_grounds.level, 90.0);
+        assert_eq!(handle.snapshot().counters.beverages_brewed, 3);
+    }
+
+    #[test]
+    fn test_brews_never_take_reserved_ingredients() {
+        let handle = handle();
+        // Two lattes reserve all of the milk
+        let orders = [
+            handle.enqueue("Latte", Priority::Normal).unwrap(),
+            handle.enqueue("Latte", Priority::Normal).unwrap(),
+        ];
+        let brews: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || handle.brew("Latte").is_ok())
+            })
+            .collect();
+        while handle.process_next().is_some() {}
+        for brew in brews {
+            assert!(!brew.join().unwrap());
+        }
+        for id in orders {
+            assert!(matches!(
+                handle.order_status(id),
+                Some(OrderStatus::Ready(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_snapshot_while_busy() {
+        let handle = handle();
+        handle.brew("Latte").unwrap();
+        let busy = handle.clone();
+        handle.with(|_| {
+            assert!(busy.try_with(|machine| machine.brew("Latte")).is_none());
+            assert_eq!(busy.ingredients().milk, 50.0);
+        });
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let handle = handle();
+        let other = handle.clone();
+        let handle = match handle.into_inner() {
+            Some(_) => panic!("the coffee machine is still shared"),
+            None => other,
+        };
+        assert!(handle.into_inner().is_some());
+    }
+}
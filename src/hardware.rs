@@ -0,0 +1,452 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::boiler::ThermalModel;
+use crate::containers::{Ingredient, IngredientsContainer};
+use crate::error::MachineError;
+
+/// The water flow of the simulated pump in ml per second
+pub const PUMP_FLOW_RATE: f32 = 2.0;
+
+/// The coffee ground by the simulated grinder in g per second
+pub const GRIND_RATE: f32 = 1.5;
+
+/// The milk steamed or frothed by the simulated frother in ml per second
+pub const FROTH_RATE: f32 = 10.0;
+
+/// The sugar or cacao released by the simulated dispenser in g per second
+pub const DISPENSE_RATE: f32 = 15.0;
+
+/// The pump pushing water from the tank through the boiler
+pub trait Pump: fmt::Debug + Send {
+    /// Pump an amount of water in ml
+    fn pump(&mut self, ml: f32) -> Result<(), MachineError>;
+}
+
+/// The boiler heating the water
+///
+/// The current time is passed in, so simulated boilers follow the clock of the coffee machine.
+pub trait Boiler: fmt::Debug + Send {
+    /// Switch the heater on
+    fn switch_on(&mut self, now: SystemTime) -> Result<(), MachineError>;
+
+    /// Switch the heater off
+    fn switch_off(&mut self, now: SystemTime);
+
+    /// Check if the heater is on
+    fn is_on(&self) -> bool;
+
+    /// Set the temperature the thermostat holds in °C
+    fn set_target(&mut self, celsius: f32, now: SystemTime);
+
+    /// Get the temperature the thermostat holds in °C
+    fn target(&self) -> f32;
+
+    /// Measure the water temperature in °C
+    fn temperature(&self, now: SystemTime) -> Result<f32, MachineError>;
+}
+
+/// The grinder grinding coffee beans into the brewing unit
+pub trait Grinder: fmt::Debug + Send {
+    /// Grind an amount of coffee in g
+    fn grind(&mut self, grams: f32) -> Result<(), MachineError>;
+}
+
+/// The milk frother, steaming milk or frothing it into foam
+pub trait Frother: fmt::Debug + Send {
+    /// Steam an amount of milk in ml
+    fn steam(&mut self, ml: f32) -> Result<(), MachineError>;
+
+    /// Froth an amount of milk in ml
+    fn froth(&mut self, ml: f32) -> Result<(), MachineError>;
+}
+
+/// The dispenser releasing sugar and cacao
+pub trait Dispenser: fmt::Debug + Send {
+    /// Dispense an amount of sugar or cacao in g
+    fn dispense(&mut self, ingredient: Ingredient, grams: f32) -> Result<(), MachineError>;
+}
+
+/// The level sensors of the ingredient tanks
+pub trait LevelSensor: fmt::Debug + Send {
+    /// Measure the level of an ingredient
+    ///
+    /// The expected level is the level the coffee machine keeps track of. Sensors that can't
+    /// measure an ingredient return it unchanged.
+    fn measure(&mut self, ingredient: Ingredient, expected: f32) -> Result<f32, MachineError>;
+}
+
+/// The hardware the coffee machine brews with
+#[derive(Debug)]
+pub struct Hardware {
+    pub pump: Box<dyn Pump>,
+    pub boiler: Box<dyn Boiler>,
+    pub grinder: Box<dyn Grinder>,
+    pub frother: Box<dyn Frother>,
+    pub dispenser: Box<dyn Dispenser>,
+    pub sensors: Box<dyn LevelSensor>,
+}
+
+impl Default for Hardware {
+    fn default() -> Self {
+        SimulatedHardware::new().hardware()
+    }
+}
+
+/// A device of the coffee machine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Device {
+    Pump,
+    Boiler,
+    Grinder,
+    Frother,
+    Dispenser,
+    LevelSensor,
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Device::Pump => write!(f, "pump"),
+            Device::Boiler => write!(f, "boiler"),
+            Device::Grinder => write!(f, "grinder"),
+            Device::Frother => write!(f, "milk frother"),
+            Device::Dispenser => write!(f, "dispenser"),
+            Device::LevelSensor => write!(f, "level sensor"),
+        }
+    }
+}
+
+/// Hardware simulating all devices with realistic flow rates
+///
+/// Clones share the same devices, so a test can keep a clone to inspect what the coffee machine
+/// did, break a device or let a tank leak.
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedHardware {
+    devices: Arc<Mutex<SimulatedDevices>>,
+}
+
+#[derive(Debug, Default)]
+struct SimulatedDevices {
+    boiler: ThermalModel,
+    /// The time the boiler was last updated
+    updated: Option<SystemTime>,
+    delivered: IngredientsContainer,
+    runtime: Duration,
+    faults: Vec<Device>,
+    leaks: IngredientsContainer,
+}
+
+impl SimulatedHardware {
+    /// Create simulated hardware with all devices working
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create simulated hardware with another boiler
+    ///
+    /// # Arguments
+    ///
+    /// * `boiler` - The thermal model of the boiler
+    ///
+    /// # Returns
+    ///
+    /// The simulated hardware
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::boiler::{ThermalModel, BREW_TEMPERATURE};
+    /// use rusty_coffeemachine::hardware::SimulatedHardware;
+    /// let preheated = SimulatedHardware::with_boiler(ThermalModel {
+    ///     temperature: BREW_TEMPERATURE,
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn with_boiler(boiler: ThermalModel) -> Self {
+        let hardware = Self::default();
+        hardware.lock().boiler = boiler;
+        hardware
+    }
+
+    /// Get the hardware for a coffee machine, driving the simulated devices
+    ///
+    /// # Returns
+    ///
+    /// The hardware
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::hardware::SimulatedHardware;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let simulator = SimulatedHardware::new();
+    /// let mut machine = CoffeeMachine::new()
+    ///     .unwrap()
+    ///     .with_clock(VirtualClock::new())
+    ///     .with_hardware(simulator.hardware());
+    /// machine.power_on().unwrap();
+    /// machine.brew("Espresso").unwrap();
+    /// assert_eq!(simulator.delivered().water, 30.0);
+    /// assert_eq!(simulator.runtime().as_secs(), 35);
+    /// ```
+    pub fn hardware(&self) -> Hardware {
+        Hardware {
+            pump: Box::new(self.clone()),
+            boiler: Box::new(self.clone()),
+            grinder: Box::new(self.clone()),
+            frother: Box::new(self.clone()),
+            dispenser: Box::new(self.clone()),
+            sensors: Box::new(self.clone()),
+        }
+    }
+
+    /// Get the amounts delivered by the devices so far
+    pub fn delivered(&self) -> IngredientsContainer {
+        self.lock().delivered.clone()
+    }
+
+    /// Get the thermal model of the boiler as of its last update
+    pub fn boiler(&self) -> ThermalModel {
+        self.lock().boiler.clone()
+    }
+
+    /// Get the time the devices were running so far at their flow rates
+    pub fn runtime(&self) -> Duration {
+        self.lock().runtime
+    }
+
+    /// Break a device, so it fails until it is repaired
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The device to break
+    pub fn break_device(&self, device: Device) {
+        let mut devices = self.lock();
+        if !devices.faults.contains(&device) {
+            devices.faults.push(device);
+        }
+    }
+
+    /// Repair a broken device
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The device to repair
+    pub fn repair(&self, device: Device) {
+        self.lock().faults.retain(|&broken| broken != device);
+    }
+
+    /// Let an amount of an ingredient leak from its tank
+    ///
+    /// The level sensors report the loss the next time they measure the ingredient.
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredient` - The ingredient leaking
+    /// * `amount` - The amount lost
+    pub fn leak(&self, ingredient: Ingredient, amount: f32) {
+        *self.lock().leaks.get_mut(ingredient) += amount;
+    }
+
+    /// Run a device at its rate, failing if it is broken
+    fn run(
+        &self,
+        device: Device,
+        ingredient: Ingredient,
+        amount: f32,
+        rate: f32,
+    ) -> Result<(), MachineError> {
+        let mut devices = self.lock();
+        devices.check(device)?;
+        *devices.delivered.get_mut(ingredient) += amount;
+        devices.runtime += Duration::from_secs_f32(amount.max(0.0) / rate);
+
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SimulatedDevices> {
+        self.devices.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SimulatedDevices {
+    /// Let the boiler follow the time passed since its last update
+    fn update(&mut self, now: SystemTime) {
+        if let Some(updated) = self.updated {
+            self.boiler
+                .advance(now.duration_since(updated).unwrap_or_default());
+        }
+        self.updated = Some(now);
+    }
+
+    /// Fail if the device is broken
+    fn check(&self, device: Device) -> Result<(), MachineError> {
+        if self.faults.contains(&device) {
+            return Err(MachineError::HardwareFault(format!(
+                "The {} is broken",
+                device
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Pump for SimulatedHardware {
+    fn pump(&mut self, ml: f32) -> Result<(), MachineError> {
+        if !self.lock().boiler.heating {
+            return Err(MachineError::HardwareFault(
+                "The boiler is cold, the pump can't deliver hot water".to_string(),
+            ));
+        }
+        self.run(Device::Pump, Ingredient::Water, ml, PUMP_FLOW_RATE)
+    }
+}
+
+impl Boiler for SimulatedHardware {
+    fn switch_on(&mut self, now: SystemTime) -> Result<(), MachineError> {
+        let mut devices = self.lock();
+        devices.check(Device::Boiler)?;
+        devices.update(now);
+        devices.boiler.heating = true;
+
+        Ok(())
+    }
+
+    fn switch_off(&mut self, now: SystemTime) {
+        let mut devices = self.lock();
+        devices.update(now);
+        devices.boiler.heating = false;
+    }
+
+    fn is_on(&self) -> bool {
+        self.lock().boiler.heating
+    }
+
+    fn set_target(&mut self, celsius: f32, now: SystemTime) {
+        let mut devices = self.lock();
+        devices.update(now);
+        devices.boiler.target = celsius;
+    }
+
+    fn target(&self) -> f32 {
+        self.lock().boiler.target
+    }
+
+    fn temperature(&self, now: SystemTime) -> Result<f32, MachineError> {
+        let mut devices = self.lock();
+        devices.check(Device::Boiler)?;
+        devices.update(now);
+
+        Ok(devices.boiler.temperature)
+    }
+}
+
+impl Grinder for SimulatedHardware {
+    fn grind(&mut self, grams: f32) -> Result<(), MachineError> {
+        self.run(Device::Grinder, Ingredient::Coffee, grams, GRIND_RATE)
+    }
+}
+
+impl Frother for SimulatedHardware {
+    fn steam(&mut self, ml: f32) -> Result<(), MachineError> {
+        self.run(Device::Frother, Ingredient::Milk, ml, FROTH_RATE)
+    }
+
+    fn froth(&mut self, ml: f32) -> Result<(), MachineError> {
+        // Frothing takes twice as long as steaming to build up the foam
+        self.run(Device::Frother, Ingredient::Milk, ml, FROTH_RATE / 2.0)
+    }
+}
+
+impl Dispenser for SimulatedHardware {
+    fn dispense(&mut self, ingredient: Ingredient, grams: f32) -> Result<(), MachineError> {
+        match ingredient {
+            Ingredient::Sugar | Ingredient::Cacao => {
+                self.run(Device::Dispenser, ingredient, grams, DISPENSE_RATE)
+            }
+            _ => Err(MachineError::HardwareFault(format!(
+                "The dispenser can't dispense {}",
+                ingredient
+            ))),
+        }
+    }
+}
+
+impl LevelSensor for SimulatedHardware {
+    fn measure(&mut self, ingredient: Ingredient, expected: f32) -> Result<f32, MachineError> {
+        let mut devices = self.lock();
+        devices.check(Device::LevelSensor)?;
+        let leaked = std::mem::take(devices.leaks.get_mut(ingredient));
+
+        Ok((expected - leaked).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulated_flow_rates() {
+        let simulator = SimulatedHardware::new();
+        let mut hardware = simulator.hardware();
+        assert!(hardware.pump.pump(30.0).is_err());
+        hardware.boiler.switch_on(SystemTime::UNIX_EPOCH).unwrap();
+        hardware.pump.pump(30.0).unwrap();
+        hardware.grinder.grind(15.0).unwrap();
+        hardware.frother.froth(20.0).unwrap();
+        hardware
+            .dispenser
+            .dispense(Ingredient::Sugar, 30.0)
+            .unwrap();
+        assert!(hardware.dispenser.dispense(Ingredient::Milk, 30.0).is_err());
+        assert_eq!(simulator.runtime(), Duration::from_secs(15 + 10 + 4 + 2));
+        assert_eq!(simulator.delivered().milk, 20.0);
+    }
+
+    #[test]
+    fn test_boiler_follows_the_clock() {
+        let simulator = SimulatedHardware::new();
+        let mut boiler = simulator.hardware().boiler;
+        let start = SystemTime::UNIX_EPOCH;
+        let later = |seconds| start + Duration::from_secs(seconds);
+        assert_eq!(boiler.temperature(start).unwrap(), 20.0);
+        boiler.switch_on(start).unwrap();
+        assert!(boiler.temperature(later(10)).unwrap() > 50.0);
+        assert_eq!(boiler.temperature(later(30)).unwrap(), 93.0);
+        boiler.switch_off(later(30));
+        assert!(boiler.temperature(later(230)).unwrap() < 50.0);
+        assert!(!simulator.boiler().heating);
+    }
+
+    #[test]
+    fn test_broken_devices_and_leaks() {
+        let simulator = SimulatedHardware::new();
+        let mut hardware = simulator.hardware();
+        simulator.break_device(Device::Grinder);
+        assert_eq!(
+            hardware.grinder.grind(10.0).unwrap_err().to_string(),
+            "Hardware fault: The grinder is broken"
+        );
+        simulator.repair(Device::Grinder);
+        hardware.grinder.grind(10.0).unwrap();
+
+        simulator.leak(Ingredient::Water, 20.0);
+        assert_eq!(
+            hardware.sensors.measure(Ingredient::Water, 50.0).unwrap(),
+            30.0
+        );
+        assert_eq!(
+            hardware.sensors.measure(Ingredient::Water, 30.0).unwrap(),
+            30.0
+        );
+        assert_eq!(
+            hardware.sensors.measure(Ingredient::Milk, 5.0).unwrap(),
+            5.0
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use std::fmt;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::containers::{GarbageContainer, Ingredient, IngredientTanks, IngredientsContainer};
+use crate::error::MachineError;
+use crate::ledger::unix_seconds;
+use crate::persistence::JsonLines;
+use crate::report::format_timestamp;
+use crate::state::MachineState;
+
+/// The levels and the state of a coffee machine, as recorded in the journal
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalState {
+    pub tanks: IngredientTanks,
+    pub garbage_container: GarbageContainer,
+    pub state: MachineState,
+}
+
+impl JournalState {
+    /// Apply a change
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - The change
+    fn apply(&mut self, change: &Change) {
+        match change {
+            Change::Snapshot(state) => *self = state.clone(),
+            Change::StateChanged { state } => self.state = state.clone(),
+            Change::IngredientsUsed { ingredients, .. } => {
+                for ingredient in Ingredient::ALL {
+                    self.tanks.get_mut(ingredient).level -= ingredients.get(ingredient);
+                }
+                self.garbage_container.coffee_grounds.level += ingredients.coffee;
+            }
+            Change::Refilled { ingredient, amount } => {
+                self.tanks.get_mut(*ingredient).level += amount;
+            }
+            Change::GroundsBinEmptied { amount } => {
+                let bin = &mut self.garbage_container.coffee_grounds;
+                bin.level = (bin.level - amount).max(0.0);
+            }
+            Change::LevelMeasured { ingredient, level } => {
+                self.tanks.get_mut(*ingredient).level = *level;
+            }
+        }
+    }
+}
+
+/// A change of a coffee machine
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    /// All levels and the state, replacing everything recorded before
+    Snapshot(JournalState),
+    /// The coffee machine changed its state
+    StateChanged { state: MachineState },
+    /// Brewing a reciepe used ingredients and filled the grounds bin with the coffee
+    IngredientsUsed {
+        recipe: String,
+        ingredients: IngredientsContainer,
+    },
+    /// An ingredient was refilled
+    Refilled { ingredient: Ingredient, amount: f32 },
+    /// The grounds bin was emptied
+    GroundsBinEmptied { amount: f32 },
+    /// A sensor corrected the level of an ingredient
+    LevelMeasured { ingredient: Ingredient, level: f32 },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Snapshot(state) => {
+                let levels: Vec<String> = Ingredient::ALL
+                    .iter()
+                    .map(|&ingredient| format!("{} {}", ingredient, state.tanks.get(ingredient)))
+                    .collect();
+                write!(
+                    f,
+                    "Snapshot while {}: {}, grounds {}",
+                    state.state,
+                    levels.join(", "),
+                    state.garbage_container.coffee_grounds
+                )
+            }
+            Change::StateChanged { state } => write!(f, "State changed to {}", state),
+            Change::IngredientsUsed {
+                recipe,
+                ingredients,
+            } => {
+                let used: Vec<String> = Ingredient::ALL
+                    .iter()
+                    .filter(|&&ingredient| ingredients.get(ingredient) > 0.0)
+                    .map(|&ingredient| {
+                        format!(
+                            "{} {} {}",
+                            ingredients.get(ingredient),
+                            ingredient.unit(),
+                            ingredient
+                        )
+                    })
+                    .collect();
+                write!(f, "{} used {}", recipe, used.join(", "))
+            }
+            Change::Refilled { ingredient, amount } => {
+                write!(f, "Added {} {} {}", amount, ingredient.unit(), ingredient)
+            }
+            Change::GroundsBinEmptied { amount } => {
+                write!(f, "Removed {} g coffee grounds", amount)
+            }
+            Change::LevelMeasured { ingredient, level } => {
+                write!(f, "Measured {} {} {}", level, ingredient.unit(), ingredient)
+            }
+        }
+    }
+}
+
+/// A change recorded in the journal
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The seconds since the unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}  {}", format_timestamp(self.timestamp), self.change)
+    }
+}
+
+/// The journal of every change of the levels and the state of a coffee machine
+///
+/// The entries are kept in memory and, if the journal has a file, appended to it as JSON lines.
+/// An entry that can't be written is kept and written together with the next one, the error is
+/// reported by [`Journal::flush`].
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+    lines: JsonLines<JournalEntry>,
+}
+
+impl Journal {
+    /// Create an empty journal kept in memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a journal file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the journal file, which is created on the first entry
+    ///
+    /// # Returns
+    ///
+    /// The journal with the entries of the file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or contains an invalid line
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::journal::Journal;
+    /// let journal = Journal::open("journal.jsonl".as_ref()).unwrap();
+    /// ```
+    pub fn open(path: &Path) -> Result<Self, MachineError> {
+        Ok(Journal {
+            lines: JsonLines::open(path)?,
+        })
+    }
+
+    /// Record a change
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - The time of the change
+    /// * `change` - The change
+    pub fn record(&mut self, at: SystemTime, change: Change) {
+        self.lines.push(JournalEntry {
+            timestamp: unix_seconds(at),
+            change,
+        });
+    }
+
+    /// Write the entries that are not in the journal file yet
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if appending to the journal file fails
+    pub fn flush(&mut self) -> Result<(), MachineError> {
+        self.lines.flush()
+    }
+
+    /// Get all entries, oldest first
+    pub fn entries(&self) -> &[JournalEntry] {
+        self.lines.entries()
+    }
+
+    /// Rebuild the levels and the state by replaying all entries
+    ///
+    /// # Returns
+    ///
+    /// The latest state, or `None` if the journal has no snapshot to start from
+    pub fn replay(&self) -> Option<JournalState> {
+        self.replay_entries(self.entries())
+    }
+
+    /// Rebuild the levels and the state at a point in time
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - The point in time, changes in the same second are included
+    ///
+    /// # Returns
+    ///
+    /// The state at that time, or `None` if the journal starts later
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use rusty_coffeemachine::containers::Ingredient;
+    /// use rusty_coffeemachine::journal::{Change, Journal, JournalState};
+    /// use rusty_coffeemachine::state::MachineState;
+    /// use rusty_coffeemachine::{GarbageContainer, IngredientTanks};
+    /// let mut journal = Journal::new();
+    /// let mut tanks = IngredientTanks::default();
+    /// tanks.milk.level = 20.0;
+    /// let state = JournalState {
+    ///     tanks,
+    ///     garbage_container: GarbageContainer::default(),
+    ///     state: MachineState::Idle,
+    /// };
+    /// journal.record(UNIX_EPOCH + Duration::from_secs(60), Change::Snapshot(state));
+    /// let refill = Change::Refilled { ingredient: Ingredient::Milk, amount: 80.0 };
+    /// journal.record(UNIX_EPOCH + Duration::from_secs(120), refill);
+    /// let at = |seconds| journal.state_at(UNIX_EPOCH + Duration::from_secs(seconds));
+    /// assert!(at(0).is_none());
+    /// assert_eq!(at(90).unwrap().tanks.milk.level, 20.0);
+    /// assert_eq!(at(120).unwrap().tanks.milk.level, 100.0);
+    /// ```
+    pub fn state_at(&self, at: SystemTime) -> Option<JournalState> {
+        let at = unix_seconds(at);
+        let entries = self.entries();
+        let until = entries.partition_point(|entry| entry.timestamp <= at);
+        self.replay_entries(&entries[..until])
+    }
+
+    /// Fold the entries before a point in time into a snapshot
+    ///
+    /// The journal file is rewritten with the snapshot and the later entries. The state at any
+    /// time after the snapshot stays the same, but the folded changes are gone from the audit trail.
+    ///
+    /// # Arguments
+    ///
+    /// * `before` - The point in time, the entries before it are folded
+    ///
+    /// # Returns
+    ///
+    /// The number of entries replaced by the snapshot
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if rewriting the journal file fails
+    pub fn compact(&mut self, before: SystemTime) -> Result<usize, MachineError> {
+        let before = unix_seconds(before);
+        let entries = self.entries();
+        let folded = entries.partition_point(|entry| entry.timestamp < before);
+        if folded < 2 {
+            return Ok(0);
+        }
+        let Some(state) = self.replay_entries(&entries[..folded]) else {
+            return Ok(0);
+        };
+        let snapshot = JournalEntry {
+            timestamp: entries[folded - 1].timestamp,
+            change: Change::Snapshot(state),
+        };
+        let mut compacted = vec![snapshot];
+        compacted.extend_from_slice(&entries[folded..]);
+        self.lines.replace(compacted)?;
+
+        Ok(folded - 1)
+    }
+
+    /// Replay entries, starting with the last snapshot
+    fn replay_entries(&self, entries: &[JournalEntry]) -> Option<JournalState> {
+        let start = entries
+            .iter()
+            .rposition(|entry| matches!(entry.change, Change::Snapshot(_)))?;
+        let mut state = match &entries[start].change {
+            Change::Snapshot(state) => state.clone(),
+            _ => unreachable!("the entry is a snapshot"),
+        };
+        for entry in &entries[start + 1..] {
+            state.apply(&entry.change);
+        }
+
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::temp_path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn snapshot() -> Change {
+        Change::Snapshot(JournalState {
+            tanks: IngredientTanks::default(),
+            garbage_container: GarbageContainer::default(),
+            state: MachineState::Off,
+        })
+    }
+
+    fn espresso() -> Change {
+        Change::IngredientsUsed {
+            recipe: "Espresso".to_string(),
+            ingredients: IngredientsContainer {
+                water: 30.0,
+                coffee: 30.0,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut journal = Journal::new();
+        journal.record(at(10), snapshot());
+        journal.record(
+            at(20),
+            Change::StateChanged {
+                state: MachineState::Idle,
+            },
+        );
+        journal.record(at(30), espresso());
+        journal.record(
+            at(40),
+            Change::LevelMeasured {
+                ingredient: Ingredient::Water,
+                level: 65.0,
+            },
+        );
+        journal.record(at(50), Change::GroundsBinEmptied { amount: 30.0 });
+
+        let state = journal.replay().unwrap();
+        assert_eq!(state.state, MachineState::Idle);
+        assert_eq!(state.tanks.water.level, 65.0);
+        assert_eq!(state.tanks.coffee.level, 70.0);
+        assert_eq!(state.garbage_container.coffee_grounds.level, 0.0);
+        let state = journal.state_at(at(45)).unwrap();
+        assert_eq!(state.garbage_container.coffee_grounds.level, 30.0);
+        assert_eq!(
+            journal.entries()[2].to_string(),
+            "1970-01-01 00:00  Espresso used 30 ml water, 30 g coffee"
+        );
+    }
+
+    #[test]
+    fn test_journal_file_compaction() {
+        let path = temp_path("journal-compact.jsonl");
+
+        let mut journal = Journal::open(&path).unwrap();
+        journal.record(at(10), snapshot());
+        journal.record(at(20), espresso());
+        journal.record(at(30), espresso());
+        journal.record(
+            at(40),
+            Change::Refilled {
+                ingredient: Ingredient::Coffee,
+                amount: 60.0,
+            },
+        );
+        journal.flush().unwrap();
+        assert_eq!(Journal::open(&path).unwrap().entries(), journal.entries());
+
+        assert_eq!(journal.compact(at(35)).unwrap(), 2);
+        assert_eq!(journal.compact(at(35)).unwrap(), 0);
+        let reopened = Journal::open(&path).unwrap();
+        assert_eq!(reopened.entries().len(), 2);
+        assert_eq!(reopened.replay(), journal.replay());
+        assert_eq!(reopened.replay().unwrap().tanks.coffee.level, 100.0);
+        assert_eq!(reopened.state_at(at(30)).unwrap().tanks.coffee.level, 40.0);
+        assert!(reopened.state_at(at(20)).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::containers::IngredientsContainer;
+use crate::error::MachineError;
+use crate::payment::PaymentMethod;
+use crate::persistence::JsonLines;
+
+/// The outcome of a single order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub recipe: String,
+    /// The ingredients consumed, nothing for failed orders
+    pub ingredients: IngredientsContainer,
+    /// The price in cents
+    pub price: u32,
+    /// How the order was paid, `None` for free brews and orders failing before the payment
+    pub payment: Option<PaymentMethod>,
+    /// The reason the order failed, `None` for successful orders
+    pub failure: Option<String>,
+    /// The seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl LedgerEntry {
+    /// Check if the order succeeded
+    pub fn succeeded(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// Get the revenue of the order in cents
+    ///
+    /// # Returns
+    ///
+    /// The price if the order succeeded and was paid, otherwise nothing
+    pub fn revenue(&self) -> u32 {
+        match (&self.failure, self.payment) {
+            (None, Some(_)) => self.price,
+            _ => 0,
+        }
+    }
+}
+
+/// The ledger of all orders
+///
+/// The entries are kept in memory and, if the ledger has a file, appended to it as JSON lines.
+/// Entries that couldn't be written are kept and written with the next one.
+#[derive(Clone, Debug, Default)]
+pub struct Ledger {
+    lines: JsonLines<LedgerEntry>,
+}
+
+impl Ledger {
+    /// Create an empty ledger kept in memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a ledger file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the ledger file, which is created on the first entry
+    ///
+    /// # Returns
+    ///
+    /// The ledger with the entries of the file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or contains an invalid line
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::ledger::Ledger;
+    /// let ledger = Ledger::open("ledger.jsonl".as_ref()).unwrap();
+    /// ```
+    pub fn open(path: &Path) -> Result<Self, MachineError> {
+        Ok(Ledger {
+            lines: JsonLines::open(path)?,
+        })
+    }
+
+    /// Record an order
+    ///
+    /// Recording never fails, so a full disk can't undo a sale. A failed write is retried with
+    /// the next entry and reported by [`Ledger::flush`].
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The outcome of the order
+    pub fn record(&mut self, entry: LedgerEntry) {
+        self.lines.push(entry);
+    }
+
+    /// Write the entries that are not in the ledger file yet
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if appending to the ledger file fails
+    pub fn flush(&mut self) -> Result<(), MachineError> {
+        self.lines.flush()
+    }
+
+    /// Get all entries, oldest first
+    pub fn entries(&self) -> &[LedgerEntry] {
+        self.lines.entries()
+    }
+
+    /// Get the entries of a time range
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The start of the range
+    /// * `to` - The end of the range, excluded
+    ///
+    /// # Returns
+    ///
+    /// The entries recorded in the range
+    pub fn between(&self, from: SystemTime, to: SystemTime) -> Vec<&LedgerEntry> {
+        let (from, to) = (unix_seconds(from), unix_seconds(to));
+        self.entries()
+            .iter()
+            .filter(|entry| entry.timestamp >= from && entry.timestamp < to)
+            .collect()
+    }
+}
+
+/// Get the seconds since the unix epoch
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::temp_path;
+    use std::time::Duration;
+
+    fn entry(recipe: &str, timestamp: u64, failure: Option<&str>) -> LedgerEntry {
+        LedgerEntry {
+            recipe: recipe.to_string(),
+            ingredients: IngredientsContainer::default(),
+            price: 180,
+            payment: Some(PaymentMethod::Cash),
+            failure: failure.map(str::to_string),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_ledger_file_survives_reopening() {
+        let path = temp_path("ledger-reopen.jsonl");
+
+        let mut ledger = Ledger::open(&path).unwrap();
+        ledger.record(entry("Espresso", 10, None));
+        ledger.record(entry("Latte", 20, Some("Payment cancelled")));
+        ledger.flush().unwrap();
+
+        let ledger = Ledger::open(&path).unwrap();
+        assert_eq!(ledger.entries().len(), 2);
+        assert_eq!(
+            ledger.entries()[1].failure.as_deref(),
+            Some("Payment cancelled")
+        );
+        assert_eq!(ledger.entries()[0].revenue(), 180);
+        assert_eq!(ledger.entries()[1].revenue(), 0);
+    }
+
+    #[test]
+    fn test_failed_writes_are_retried() {
+        // A file in the way of the directory of the ledger file makes the writes fail
+        let blocked = temp_path("ledger-blocked");
+        std::fs::write(&*blocked, "").unwrap();
+        let path = blocked.join("ledger.jsonl");
+        let mut ledger = Ledger::open(&path).unwrap();
+        ledger.record(entry("Espresso", 10, None));
+        assert_eq!(ledger.entries().len(), 1);
+        assert!(ledger.flush().is_err());
+
+        std::fs::remove_file(&*blocked).unwrap();
+        ledger.record(entry("Latte", 20, None));
+        ledger.flush().unwrap();
+        assert_eq!(Ledger::open(&path).unwrap().entries(), ledger.entries());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_dir(&*blocked).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_ledger_line() {
+        let path = temp_path("ledger-corrupt.jsonl");
+        std::fs::write(&*path, "\n{\"recipe\": \"Latte\"}\n").unwrap();
+        match Ledger::open(&path) {
+            Err(MachineError::CorruptState { message, .. }) => {
+                assert!(message.starts_with("line 2: "))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_between() {
+        let mut ledger = Ledger::new();
+        for timestamp in [10, 20, 30] {
+            ledger.record(entry("Espresso", timestamp, None));
+        }
+        let from = UNIX_EPOCH + Duration::from_secs(20);
+        let to = UNIX_EPOCH + Duration::from_secs(30);
+        let entries = ledger.between(from, to);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].timestamp, 20);
+    }
+}
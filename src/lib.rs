@@ -1,12 +1,42 @@
+pub mod beverage;
+pub mod boiler;
+pub mod cli;
+pub mod clock;
 pub mod coffeemachine;
+pub mod console;
 pub mod containers;
+pub mod counters;
+pub mod customization;
+pub mod error;
+pub mod events;
+pub mod forecast;
+pub mod handle;
+pub mod hardware;
+pub mod journal;
+pub mod ledger;
+pub mod maintenance;
+pub mod payment;
+pub mod persistence;
+pub mod program;
+pub mod queue;
 pub mod reciepes;
+pub mod recipe_book;
+pub mod report;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod service;
+pub mod state;
+pub mod stock;
+#[cfg(feature = "tui")]
+pub mod tui;
 
 use std::io::Write;
 
+pub use beverage::Beverage;
 pub use coffeemachine::CoffeeMachine;
-pub use containers::{GarbageContainer, IngredientsContainer};
-pub use reciepes::Reciepes;
+pub use containers::{GarbageContainer, IngredientTanks, IngredientsContainer};
+pub use error::MachineError;
+pub use reciepes::{Reciepes, RecipeRef};
 
 pub const LINE_AMOUNT: usize = 50;
 
@@ -20,16 +50,18 @@ pub const LINE_AMOUNT: usize = 50;
 ///
 /// # Errors
 ///
-/// This function will return an error if reading from the terminal fails
+/// This function will return an error if reading from the terminal fails or the input is closed
 ///
 /// # Examples
 ///
 /// ```
 /// let input = rusty_coffeemachine::get_input();
 /// ```
-pub fn get_input() -> Result<String, std::io::Error> {
+pub fn get_input() -> Result<String, MachineError> {
     let mut input = String::new();
-    std::io::stdin().read_line(&mut input)?;
+    if std::io::stdin().read_line(&mut input)? == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "No more input").into());
+    }
     Ok(input.trim().to_string())
 }
 
@@ -50,12 +82,79 @@ pub fn get_input() -> Result<String, std::io::Error> {
 /// ```
 /// rusty_coffeemachine::print_line();
 /// ```
-pub fn print_line() -> Result<(), std::io::Error> {
+pub fn print_line() -> Result<(), MachineError> {
     println!("{}", "~".repeat(LINE_AMOUNT));
 
     Ok(())
 }
 
+/// Format a price
+///
+/// # Arguments
+///
+/// * `cents` - The price in cents
+///
+/// # Returns
+///
+/// The price with two decimal places
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(rusty_coffeemachine::format_price(250), "2.50");
+/// ```
+pub fn format_price(cents: u32) -> String {
+    format!("{}.{:02}", cents / 100, cents % 100)
+}
+
+/// Parse a price
+///
+/// # Arguments
+///
+/// * `price` - The price with at most two decimal places, like `2`, `2.5` or `2.50`
+///
+/// # Returns
+///
+/// The price in cents
+///
+/// # Errors
+///
+/// This function will return an error if the price is not a positive amount of money
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(rusty_coffeemachine::parse_price("2.5").unwrap(), 250);
+/// assert!(rusty_coffeemachine::parse_price("2.505").is_err());
+/// ```
+pub fn parse_price(price: &str) -> Result<u32, MachineError> {
+    let invalid = || MachineError::InvalidInput(format!("Invalid amount of money: {}", price));
+    let price = price.trim();
+    let (units, decimals) = price.split_once('.').unwrap_or((price, ""));
+    if units.is_empty()
+        || decimals.len() > 2
+        || !(units.chars().chain(decimals.chars())).all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let units: u32 = units.parse().map_err(|_| invalid())?;
+    let cents: u32 = format!("{:0<2}", decimals).parse().map_err(|_| invalid())?;
+
+    units
+        .checked_mul(100)
+        .and_then(|units| units.checked_add(cents))
+        .ok_or_else(invalid)
+}
+
+/// Capitalize the first letter of a word
+pub(crate) fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
 #[derive(Debug)]
 pub struct ProgressBar {
     pub progress: f32,
@@ -102,9 +201,11 @@ impl ProgressBar {
     /// let mut progress_bar = rusty_coffeemachine::ProgressBar::new(100.0);
     /// progress_bar.set_progress(50.0);
     /// ```
-    pub fn set_progress(&mut self, progress: f32) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn set_progress(&mut self, progress: f32) -> Result<(), MachineError> {
         if progress > self.max {
-            return Err("Progress is greater than max value".into());
+            return Err(MachineError::InvalidInput(
+                "Progress is greater than max value".to_string(),
+            ));
         }
         self.progress = progress;
 
@@ -121,18 +222,34 @@ impl ProgressBar {
     /// let progress_bar = rusty_coffeemachine::ProgressBar::new(100.0);
     /// progress_bar.draw();
     /// ```
-    pub fn draw(&self) -> Result<(), std::io::Error> {
+    pub fn draw(&self) -> Result<(), MachineError> {
+        print!("\r{}", self.render());
+        std::io::stdout().flush()?;
+
+        Ok(())
+    }
+
+    /// Render the progress bar
+    ///
+    /// # Returns
+    ///
+    /// The progress bar as a string
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut progress_bar = rusty_coffeemachine::ProgressBar::new(100.0);
+    /// progress_bar.set_progress(50.0).unwrap();
+    /// assert!(progress_bar.render().ends_with("] 50%"));
+    /// ```
+    pub fn render(&self) -> String {
         let progress = (self.progress / self.max * 100.0) as usize;
-        let bar = format!(
-            "\r[{}{}] {}%",
+        format!(
+            "[{}{}] {}%",
             "=".repeat(progress / 2),
             " ".repeat(50 - progress / 2),
             progress
-        );
-        print!("{}", bar);
-        std::io::stdout().flush()?;
-
-        Ok(())
+        )
     }
 }
 
@@ -153,7 +270,7 @@ impl ProgressBar {
 /// ```
 /// rusty_coffeemachine::clean_terminal();
 /// ```
-pub fn clean_terminal() -> Result<(), std::io::Error> {
+pub fn clean_terminal() -> Result<(), MachineError> {
     print!("\x1B[2J\x1B[1;1H");
     std::io::stdout().flush()?;
 
@@ -182,7 +299,7 @@ mod tests {
     fn test_progress_bar_set_progress_error() {
         let mut progress_bar = ProgressBar::new(100.0);
         let result = progress_bar.set_progress(150.0);
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
     }
 
     #[test]
@@ -1,8 +1,37 @@
-use rusty_coffeemachine::CoffeeMachine;
+use rusty_coffeemachine::cli::{error_json, Cli, USAGE};
+use rusty_coffeemachine::console::StdConsole;
+use rusty_coffeemachine::MachineError;
 
 /// Main function
 ///
-/// This function creates a new coffee machine and runs it
+/// This function runs the coffee machine and reports errors to the terminal. The exit code tells
+/// scripts what went wrong.
+fn main() {
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            std::process::exit(e.exit_code());
+        }
+    };
+
+    if let Err(e) = run(&cli) {
+        if cli.json {
+            println!("{}", error_json(&e));
+        } else {
+            eprintln!("Error: {}", e);
+        }
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// Run the coffee machine
+///
+/// This function restores the coffee machine and executes the command
+///
+/// # Arguments
+///
+/// * `cli` - The parsed command line
 ///
 /// # Returns
 ///
@@ -10,10 +39,19 @@ use rusty_coffeemachine::CoffeeMachine;
 ///
 /// # Errors
 ///
-/// This function will return an error if creating the coffee machine or running it fails
-fn main() -> Result<(), std::io::Error> {
-    let mut machine = CoffeeMachine::new()?;
-    machine.run()?;
-
-    Ok(())
+/// This function will return an error if creating the coffee machine or executing the command fails
+fn run(cli: &Cli) -> Result<(), MachineError> {
+    let mut machine = cli.machine()?;
+    let result = cli.execute(&mut machine, &mut StdConsole);
+    // The orders are done either way, a ledger that can't be written doesn't change their outcome
+    if let Err(e) = machine.ledger.flush() {
+        eprintln!(
+            "Warning: the orders could not be written to the ledger: {}",
+            e
+        );
+    }
+    if let Err(e) = machine.flush_state() {
+        eprintln!("Warning: the state could not be saved: {}", e);
+    }
+    result
 }
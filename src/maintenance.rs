@@ -0,0 +1,329 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::containers::IngredientsContainer;
+use crate::error::MachineError;
+
+/// The default number of milk drinks between two cleanings of the milk system
+pub const DEFAULT_MILK_DRINKS: u32 = 20;
+
+/// How far a counter may pass its interval before brewing is blocked
+pub const OVERDUE_FACTOR: f32 = 1.1;
+
+/// The hardness of the water the coffee machine is filled with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaterHardness {
+    Soft,
+    #[default]
+    Medium,
+    Hard,
+    VeryHard,
+}
+
+impl WaterHardness {
+    pub const ALL: [WaterHardness; 4] = [
+        WaterHardness::Soft,
+        WaterHardness::Medium,
+        WaterHardness::Hard,
+        WaterHardness::VeryHard,
+    ];
+
+    /// Get the litres of water that can be pumped between two descalings
+    pub fn descale_interval(&self) -> f32 {
+        match self {
+            WaterHardness::Soft => 120.0,
+            WaterHardness::Medium => 80.0,
+            WaterHardness::Hard => 40.0,
+            WaterHardness::VeryHard => 20.0,
+        }
+    }
+}
+
+impl fmt::Display for WaterHardness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaterHardness::Soft => write!(f, "soft"),
+            WaterHardness::Medium => write!(f, "medium"),
+            WaterHardness::Hard => write!(f, "hard"),
+            WaterHardness::VeryHard => write!(f, "very hard"),
+        }
+    }
+}
+
+impl FromStr for WaterHardness {
+    type Err = MachineError;
+
+    fn from_str(hardness: &str) -> Result<Self, Self::Err> {
+        let normalized = hardness.trim().replace(['_', '-'], " ");
+        WaterHardness::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_string().eq_ignore_ascii_case(&normalized))
+            .ok_or_else(|| {
+                MachineError::InvalidInput(format!("Unknown water hardness: {}", hardness))
+            })
+    }
+}
+
+/// A maintenance program of the coffee machine
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaintenanceTask {
+    Descale,
+    CleanMilkSystem,
+    Rinse,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 3] = [
+        MaintenanceTask::Descale,
+        MaintenanceTask::CleanMilkSystem,
+        MaintenanceTask::Rinse,
+    ];
+
+    /// Get the phases of the maintenance program
+    ///
+    /// # Returns
+    ///
+    /// The description and the duration of every phase, in order
+    pub fn phases(&self) -> &'static [(&'static str, Duration)] {
+        const DESCALE: [(&str, Duration); 3] = [
+            ("Pump the descaling solution", Duration::from_secs(10)),
+            ("Let the descaler soak", Duration::from_secs(10)),
+            ("Rinse the water circuit", Duration::from_secs(10)),
+        ];
+        const CLEAN_MILK_SYSTEM: [(&str, Duration); 2] = [
+            ("Flush the milk system with cleaner", Duration::from_secs(8)),
+            ("Rinse the milk system", Duration::from_secs(7)),
+        ];
+        const RINSE: [(&str, Duration); 1] = [("Rinse the brewing unit", Duration::from_secs(5))];
+        match self {
+            MaintenanceTask::Descale => &DESCALE,
+            MaintenanceTask::CleanMilkSystem => &CLEAN_MILK_SYSTEM,
+            MaintenanceTask::Rinse => &RINSE,
+        }
+    }
+
+    /// Get the duration of the maintenance program
+    pub fn duration(&self) -> Duration {
+        self.phases().iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+impl fmt::Display for MaintenanceTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaintenanceTask::Descale => write!(f, "descaling"),
+            MaintenanceTask::CleanMilkSystem => write!(f, "milk system cleaning"),
+            MaintenanceTask::Rinse => write!(f, "rinsing"),
+        }
+    }
+}
+
+impl FromStr for MaintenanceTask {
+    type Err = MachineError;
+
+    fn from_str(task: &str) -> Result<Self, Self::Err> {
+        match task.trim().to_lowercase().as_str() {
+            "descale" => Ok(MaintenanceTask::Descale),
+            "clean" | "clean-milk" => Ok(MaintenanceTask::CleanMilkSystem),
+            "rinse" => Ok(MaintenanceTask::Rinse),
+            _ => Err(MachineError::InvalidInput(format!(
+                "Unknown maintenance program: {}",
+                task
+            ))),
+        }
+    }
+}
+
+/// How urgently a maintenance program is needed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MaintenanceStatus {
+    Ok,
+    /// The interval is reached, brewing still works
+    Due,
+    /// The interval is passed by more than the tolerance, brewing is blocked
+    Overdue,
+}
+
+/// The maintenance intervals of the coffee machine
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    pub hardness: WaterHardness,
+    /// The litres between two descalings, overriding the interval of the water hardness
+    pub descale_litres: Option<f32>,
+    /// The milk drinks between two cleanings of the milk system
+    pub milk_drinks: u32,
+    /// Whether the brewing unit is rinsed when the coffee machine starts
+    pub rinse_at_startup: bool,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        MaintenanceConfig {
+            hardness: WaterHardness::default(),
+            descale_litres: None,
+            milk_drinks: DEFAULT_MILK_DRINKS,
+            rinse_at_startup: true,
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    /// Get the litres between two descalings
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::maintenance::{MaintenanceConfig, WaterHardness};
+    /// let config = MaintenanceConfig {
+    ///     hardness: WaterHardness::Hard,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(config.descale_interval(), 40.0);
+    /// ```
+    pub fn descale_interval(&self) -> f32 {
+        self.descale_litres
+            .unwrap_or_else(|| self.hardness.descale_interval())
+    }
+}
+
+/// The usage since the last maintenance
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceCounters {
+    /// The litres of water pumped since the last descaling
+    pub litres_since_descale: f32,
+    /// The milk drinks brewed since the last cleaning of the milk system
+    pub milk_drinks_since_clean: u32,
+}
+
+impl MaintenanceCounters {
+    /// Count a brewed beverage
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredients` - The ingredients used by the beverage
+    pub fn record_brew(&mut self, ingredients: &IngredientsContainer) {
+        self.litres_since_descale += ingredients.water / 1000.0;
+        if ingredients.milk > 0.0 {
+            self.milk_drinks_since_clean += 1;
+        }
+    }
+
+    /// Reset the counter of a maintenance program after it ran
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The maintenance program
+    pub fn reset(&mut self, task: MaintenanceTask) {
+        match task {
+            MaintenanceTask::Descale => self.litres_since_descale = 0.0,
+            MaintenanceTask::CleanMilkSystem => self.milk_drinks_since_clean = 0,
+            MaintenanceTask::Rinse => {}
+        }
+    }
+
+    /// Get how urgently a maintenance program is needed
+    ///
+    /// Rinsing is done at startup and never becomes due.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The maintenance intervals
+    /// * `task` - The maintenance program
+    ///
+    /// # Returns
+    ///
+    /// The status of the maintenance program
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::maintenance::{
+    ///     MaintenanceConfig, MaintenanceCounters, MaintenanceStatus, MaintenanceTask,
+    /// };
+    /// let config = MaintenanceConfig::default();
+    /// let counters = MaintenanceCounters {
+    ///     milk_drinks_since_clean: 21,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(
+    ///     counters.status(&config, MaintenanceTask::CleanMilkSystem),
+    ///     MaintenanceStatus::Due
+    /// );
+    /// assert_eq!(
+    ///     counters.status(&config, MaintenanceTask::Descale),
+    ///     MaintenanceStatus::Ok
+    /// );
+    /// ```
+    pub fn status(&self, config: &MaintenanceConfig, task: MaintenanceTask) -> MaintenanceStatus {
+        let (used, interval) = match task {
+            MaintenanceTask::Descale => (self.litres_since_descale, config.descale_interval()),
+            MaintenanceTask::CleanMilkSystem => (
+                self.milk_drinks_since_clean as f32,
+                config.milk_drinks as f32,
+            ),
+            MaintenanceTask::Rinse => return MaintenanceStatus::Ok,
+        };
+        if used >= interval * OVERDUE_FACTOR {
+            MaintenanceStatus::Overdue
+        } else if used >= interval {
+            MaintenanceStatus::Due
+        } else {
+            MaintenanceStatus::Ok
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descaling_becomes_overdue() {
+        let config = MaintenanceConfig {
+            descale_litres: Some(0.1),
+            ..Default::default()
+        };
+        let mut counters = MaintenanceCounters::default();
+        let espresso = IngredientsContainer {
+            water: 30.0,
+            coffee: 30.0,
+            ..Default::default()
+        };
+        let status =
+            |counters: &MaintenanceCounters| counters.status(&config, MaintenanceTask::Descale);
+
+        for _ in 0..3 {
+            counters.record_brew(&espresso);
+        }
+        assert_eq!(status(&counters), MaintenanceStatus::Ok);
+        counters.record_brew(&espresso);
+        assert_eq!(status(&counters), MaintenanceStatus::Overdue);
+        assert_eq!(counters.milk_drinks_since_clean, 0);
+        counters.reset(MaintenanceTask::Descale);
+        assert_eq!(status(&counters), MaintenanceStatus::Ok);
+    }
+
+    #[test]
+    fn test_parse_hardness_and_task() {
+        assert_eq!(
+            "very_hard".parse::<WaterHardness>().unwrap(),
+            WaterHardness::VeryHard
+        );
+        assert_eq!(
+            "Very hard".parse::<WaterHardness>().unwrap(),
+            WaterHardness::VeryHard
+        );
+        assert!("chalky".parse::<WaterHardness>().is_err());
+        assert_eq!(
+            "clean-milk".parse::<MaintenanceTask>().unwrap(),
+            MaintenanceTask::CleanMilkSystem
+        );
+        assert_eq!(MaintenanceTask::Descale.duration(), Duration::from_secs(30));
+    }
+}
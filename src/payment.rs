@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::beverage::Beverage;
+use crate::error::MachineError;
+use crate::format_price;
+
+/// The notes and coins the coffee machine accepts, in cents
+pub const DENOMINATIONS: [u32; 8] = [1000, 500, 200, 100, 50, 20, 10, 5];
+
+/// The number of every coin a new coffee machine holds to give change
+const FLOAT_COINS: u32 = 10;
+
+/// Notes and coins, counted per denomination
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CoinInventory {
+    counts: BTreeMap<u32, u32>,
+}
+
+impl CoinInventory {
+    /// Create an empty coin inventory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the coin inventory of a new coffee machine
+    ///
+    /// # Returns
+    ///
+    /// A few of every coin, but no notes
+    pub fn starting_float() -> Self {
+        let mut coins = Self::new();
+        for denomination in DENOMINATIONS.into_iter().filter(|&d| d <= 200) {
+            coins.counts.insert(denomination, FLOAT_COINS);
+        }
+        coins
+    }
+
+    /// Create a coin inventory from single notes and coins
+    ///
+    /// # Arguments
+    ///
+    /// * `coins` - The values of the notes and coins in cents
+    ///
+    /// # Returns
+    ///
+    /// The coin inventory holding the notes and coins
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a value is not an accepted denomination
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::payment::CoinInventory;
+    /// let coins = CoinInventory::from_coins(&[200, 50, 50]).unwrap();
+    /// assert_eq!(coins.total(), 300);
+    /// assert_eq!(coins.count(50), 2);
+    /// assert!(CoinInventory::from_coins(&[3]).is_err());
+    /// ```
+    pub fn from_coins(coins: &[u32]) -> Result<Self, MachineError> {
+        let mut inventory = Self::new();
+        for &coin in coins {
+            inventory.add(coin, 1)?;
+        }
+        Ok(inventory)
+    }
+
+    /// Add notes or coins of one denomination
+    ///
+    /// # Arguments
+    ///
+    /// * `denomination` - The value of the note or coin in cents
+    /// * `count` - The number of notes or coins
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the denomination is not accepted
+    pub fn add(&mut self, denomination: u32, count: u32) -> Result<(), MachineError> {
+        if !DENOMINATIONS.contains(&denomination) {
+            return Err(MachineError::InvalidInput(format!(
+                "{} is not an accepted coin or note",
+                format_price(denomination)
+            )));
+        }
+        *self.counts.entry(denomination).or_insert(0) += count;
+
+        Ok(())
+    }
+
+    /// Add all notes and coins of another coin inventory
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The notes and coins to add
+    pub fn merge(&mut self, other: &CoinInventory) {
+        for (denomination, count) in other.iter() {
+            *self.counts.entry(denomination).or_insert(0) += count;
+        }
+    }
+
+    /// Remove the notes and coins of another coin inventory
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The notes and coins to remove
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error without removing anything if a note or coin is missing
+    pub fn remove(&mut self, other: &CoinInventory) -> Result<(), MachineError> {
+        if let Some((denomination, _)) = other
+            .iter()
+            .find(|&(denomination, count)| self.count(denomination) < count)
+        {
+            return Err(MachineError::InvalidInput(format!(
+                "Not enough coins of {}",
+                format_price(denomination)
+            )));
+        }
+        for (denomination, count) in other.iter() {
+            *self.counts.entry(denomination).or_insert(0) -= count;
+        }
+
+        Ok(())
+    }
+
+    /// Get the number of notes or coins of one denomination
+    pub fn count(&self, denomination: u32) -> u32 {
+        self.counts.get(&denomination).copied().unwrap_or(0)
+    }
+
+    /// Get the total value in cents
+    pub fn total(&self) -> u32 {
+        self.iter()
+            .map(|(denomination, count)| denomination * count)
+            .sum()
+    }
+
+    /// Check if the coin inventory holds no notes or coins
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// Iterate over the denominations and their counts, from the largest to the smallest
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.counts
+            .iter()
+            .rev()
+            .filter(|(_, &count)| count > 0)
+            .map(|(&denomination, &count)| (denomination, count))
+    }
+
+    /// Find the notes and coins paying an amount exactly
+    ///
+    /// Only the notes and coins of this inventory are used, so the greedy choice of always taking
+    /// the largest coin isn't good enough. Among the possible combinations the one with the fewest
+    /// notes and coins is chosen.
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount in cents
+    ///
+    /// # Returns
+    ///
+    /// The notes and coins paying the amount, or `None` if the amount can't be paid exactly
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::payment::CoinInventory;
+    /// let coins = CoinInventory::from_coins(&[50, 20, 20, 20]).unwrap();
+    /// let change = coins.make_change(60).unwrap();
+    /// assert_eq!(change.count(20), 3);
+    /// assert!(coins.make_change(30).is_none());
+    /// ```
+    pub fn make_change(&self, amount: u32) -> Option<CoinInventory> {
+        let coins: Vec<(u32, u32)> = self.iter().collect();
+        let size = amount as usize + 1;
+        // fewest[a] is the fewest number of coins paying a with the denominations seen so far,
+        // taken[i][a] the number of coins of denomination i used for it
+        let mut fewest: Vec<Option<u32>> = vec![None; size];
+        fewest[0] = Some(0);
+        let mut taken = vec![vec![0u32; size]; coins.len()];
+        for (i, &(denomination, stock)) in coins.iter().enumerate() {
+            let previous = fewest.clone();
+            for paid in 0..size {
+                for count in 1..=stock {
+                    let value = (count * denomination) as usize;
+                    if value > paid {
+                        break;
+                    }
+                    if let Some(before) = previous[paid - value] {
+                        if fewest[paid].is_none_or(|best| before + count < best) {
+                            fewest[paid] = Some(before + count);
+                            taken[i][paid] = count;
+                        }
+                    }
+                }
+            }
+        }
+        fewest[amount as usize]?;
+
+        let mut change = CoinInventory::new();
+        let mut rest = amount as usize;
+        for (i, &(denomination, _)) in coins.iter().enumerate().rev() {
+            let count = taken[i][rest];
+            if count > 0 {
+                change.counts.insert(denomination, count);
+                rest -= (count * denomination) as usize;
+            }
+        }
+        Some(change)
+    }
+}
+
+impl fmt::Display for CoinInventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "nothing");
+        }
+        for (i, (denomination, count)) in self.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(f, "{}{}x {}", separator, count, format_price(denomination))?;
+        }
+        Ok(())
+    }
+}
+
+/// The ways a beverage can be paid for
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentMethod {
+    Cash,
+    Card,
+}
+
+impl fmt::Display for PaymentMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentMethod::Cash => write!(f, "cash"),
+            PaymentMethod::Card => write!(f, "card"),
+        }
+    }
+}
+
+/// The payment offered for a beverage
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payment {
+    /// The notes and coins inserted into the coffee machine
+    Cash(CoinInventory),
+    /// A charge on the card in the card reader
+    Card,
+}
+
+impl Payment {
+    /// Get the method of the payment
+    pub fn method(&self) -> PaymentMethod {
+        match self {
+            Payment::Cash(_) => PaymentMethod::Cash,
+            Payment::Card => PaymentMethod::Card,
+        }
+    }
+}
+
+/// A beverage that was paid for
+#[derive(Clone, Debug)]
+pub struct Sale {
+    pub beverage: Beverage,
+    pub price: u32,
+    pub method: PaymentMethod,
+    pub change: CoinInventory,
+}
+
+/// A cashless card reader
+pub trait CardReader: fmt::Debug + Send {
+    /// Charge an amount in cents to the card
+    fn charge(&mut self, amount: u32) -> Result<(), MachineError>;
+
+    /// Refund an amount in cents to the card
+    fn refund(&mut self, amount: u32) -> Result<(), MachineError>;
+}
+
+/// A card reader simulating a card with an optional limit
+///
+/// Clones share the same card, so a test can keep a clone to inspect the charges.
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedCardReader {
+    card: Arc<Mutex<SimulatedCard>>,
+}
+
+#[derive(Debug, Default)]
+struct SimulatedCard {
+    balance: Option<u32>,
+    charged: u32,
+}
+
+impl SimulatedCardReader {
+    /// Create a card reader accepting every charge
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a card reader declining charges above a balance
+    ///
+    /// # Arguments
+    ///
+    /// * `balance` - The balance of the card in cents
+    ///
+    /// # Returns
+    ///
+    /// The card reader
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::payment::{CardReader, SimulatedCardReader};
+    /// let mut reader = SimulatedCardReader::with_balance(300);
+    /// reader.charge(280).unwrap();
+    /// assert!(reader.charge(280).is_err());
+    /// assert_eq!(reader.charged(), 280);
+    /// ```
+    pub fn with_balance(balance: u32) -> Self {
+        let reader = Self::default();
+        reader.lock().balance = Some(balance);
+        reader
+    }
+
+    /// Get the total amount charged to the card, minus the refunds
+    pub fn charged(&self) -> u32 {
+        self.lock().charged
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SimulatedCard> {
+        self.card.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CardReader for SimulatedCardReader {
+    fn charge(&mut self, amount: u32) -> Result<(), MachineError> {
+        let mut card = self.lock();
+        if let Some(balance) = card.balance {
+            if amount > balance {
+                return Err(MachineError::PaymentDeclined(format!(
+                    "Only {} left on the card",
+                    format_price(balance)
+                )));
+            }
+            card.balance = Some(balance - amount);
+        }
+        card.charged += amount;
+
+        Ok(())
+    }
+
+    fn refund(&mut self, amount: u32) -> Result<(), MachineError> {
+        let mut card = self.lock();
+        if amount > card.charged {
+            return Err(MachineError::InvalidInput(format!(
+                "Cannot refund {}, only {} was charged",
+                format_price(amount),
+                format_price(card.charged)
+            )));
+        }
+        card.charged -= amount;
+        if let Some(balance) = card.balance.as_mut() {
+            *balance += amount;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_change_prefers_fewest_coins() {
+        let coins = CoinInventory::starting_float();
+        let change = coins.make_change(380).unwrap();
+        assert_eq!(
+            change.to_string(),
+            "1x 2.00, 1x 1.00, 1x 0.50, 1x 0.20, 1x 0.10"
+        );
+        assert_eq!(change.total(), 380);
+    }
+
+    #[test]
+    fn test_make_change_with_limited_stock() {
+        let coins = CoinInventory::from_coins(&[100, 50, 20, 20, 20]).unwrap();
+        assert_eq!(
+            coins.make_change(160).unwrap().to_string(),
+            "1x 1.00, 3x 0.20"
+        );
+        assert!(coins.make_change(130).is_none());
+        assert!(coins.make_change(300).is_none());
+        assert!(coins.make_change(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_remove_is_all_or_nothing() {
+        let mut coins = CoinInventory::from_coins(&[100, 50]).unwrap();
+        let other = CoinInventory::from_coins(&[100, 20]).unwrap();
+        assert!(coins.remove(&other).is_err());
+        assert_eq!(coins.total(), 150);
+        coins
+            .remove(&CoinInventory::from_coins(&[50]).unwrap())
+            .unwrap();
+        assert_eq!(coins.total(), 100);
+    }
+
+    #[test]
+    fn test_simulated_card_refund() {
+        let mut reader = SimulatedCardReader::with_balance(500);
+        let observer = reader.clone();
+        reader.charge(300).unwrap();
+        reader.refund(300).unwrap();
+        assert_eq!(observer.charged(), 0);
+        reader.charge(500).unwrap();
+        assert!(reader.refund(600).is_err());
+    }
+
+    #[test]
+    fn test_coin_inventory_serializes_as_map() {
+        let coins = CoinInventory::from_coins(&[200, 10, 10]).unwrap();
+        let json = serde_json::to_string(&coins).unwrap();
+        assert_eq!(json, r#"{"10":2,"200":1}"#);
+        assert_eq!(serde_json::from_str::<CoinInventory>(&json).unwrap(), coins);
+    }
+}
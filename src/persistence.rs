@@ -0,0 +1,388 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::containers::{GarbageContainer, IngredientTanks};
+use crate::counters::Counters;
+use crate::error::MachineError;
+use crate::maintenance::{MaintenanceConfig, MaintenanceCounters};
+use crate::payment::CoinInventory;
+use crate::queue::OrderQueue;
+use crate::reciepes::Reciepes;
+use crate::stock::StockThresholds;
+
+/// The name of the directory below the data directory holding the state file
+const APP_DIR: &str = "rusty-coffeemachine";
+
+/// The part of the coffee machine that survives a restart
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    pub tanks: IngredientTanks,
+    pub garbage_container: GarbageContainer,
+    pub counters: Counters,
+    pub reciepes: Vec<Reciepes>,
+    #[serde(default = "CoinInventory::starting_float")]
+    pub coins: CoinInventory,
+    #[serde(default)]
+    pub thresholds: StockThresholds,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub maintenance_counters: MaintenanceCounters,
+    #[serde(default)]
+    pub queue: OrderQueue,
+}
+
+impl SavedState {
+    /// Load a saved state from a file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the state file
+    ///
+    /// # Returns
+    ///
+    /// The saved state
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or doesn't contain a valid state
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::persistence::SavedState;
+    /// let state = SavedState::load("state.json".as_ref()).unwrap();
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, MachineError> {
+        let source = std::fs::read_to_string(path)?;
+        serde_json::from_str(&source).map_err(|e| MachineError::CorruptState {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Save the state to a file
+    ///
+    /// The state is written to a temporary file next to the state file first, which then replaces
+    /// the state file, so a crash never leaves a half written state behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the state file
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing the file fails
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let machine = CoffeeMachine::new().unwrap();
+    /// machine.saved_state().save("state.json".as_ref()).unwrap();
+    /// ```
+    pub fn save(&self, path: &Path) -> Result<(), MachineError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| MachineError::InvalidInput(e.to_string()))?;
+
+        write_atomically(path, json.as_bytes())
+    }
+}
+
+/// Replace a file
+///
+/// The contents are written to a temporary file next to the file first, which then replaces the
+/// file, so a crash never leaves a half written file behind. Missing parent directories are created.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file
+/// * `contents` - The new contents of the file
+///
+/// # Returns
+///
+/// An empty result
+///
+/// # Errors
+///
+/// This function will return an error if writing the file fails
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), MachineError> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = std::fs::File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)?;
+
+    Ok(())
+}
+
+/// Entries kept in memory and, if there is a file, appended to it as JSON lines
+///
+/// Entries that couldn't be written are kept and written with the next one.
+#[derive(Clone, Debug)]
+pub(crate) struct JsonLines<T> {
+    entries: Vec<T>,
+    path: Option<PathBuf>,
+    /// The number of entries at the end that are not in the file yet
+    unwritten: usize,
+}
+
+impl<T> Default for JsonLines<T> {
+    fn default() -> Self {
+        JsonLines {
+            entries: Vec::new(),
+            path: None,
+            unwritten: 0,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> JsonLines<T> {
+    /// Open a JSON lines file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file, which is created on the first entry
+    ///
+    /// # Returns
+    ///
+    /// The entries of the file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or contains an invalid line
+    pub(crate) fn open(path: &Path) -> Result<Self, MachineError> {
+        let mut entries = Vec::new();
+        if path.exists() {
+            let file = std::io::BufReader::new(std::fs::File::open(path)?);
+            for (number, line) in file.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                entries.push(serde_json::from_str(&line).map_err(|e| {
+                    MachineError::CorruptState {
+                        path: path.to_path_buf(),
+                        message: format!("line {}: {}", number + 1, e),
+                    }
+                })?);
+            }
+        }
+
+        Ok(JsonLines {
+            entries,
+            path: Some(path.to_path_buf()),
+            unwritten: 0,
+        })
+    }
+
+    /// Add an entry
+    ///
+    /// Adding never fails. A failed write is retried with the next entry and reported by
+    /// [`JsonLines::flush`].
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry
+    pub(crate) fn push(&mut self, entry: T) {
+        self.entries.push(entry);
+        self.unwritten += 1;
+        let _ = self.flush();
+    }
+
+    /// Write the entries that are not in the file yet
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if appending to the file fails
+    pub(crate) fn flush(&mut self) -> Result<(), MachineError> {
+        let Some(path) = &self.path else {
+            self.unwritten = 0;
+            return Ok(());
+        };
+        if self.unwritten == 0 {
+            return Ok(());
+        }
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let lines = to_lines(&self.entries[self.entries.len() - self.unwritten..])?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        file.write_all(lines.as_bytes())?;
+        self.unwritten = 0;
+
+        Ok(())
+    }
+
+    /// Replace all entries, rewriting the file
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The new entries
+    ///
+    /// # Returns
+    ///
+    /// An empty result
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if rewriting the file fails, the entries stay unchanged
+    pub(crate) fn replace(&mut self, entries: Vec<T>) -> Result<(), MachineError> {
+        if let Some(path) = &self.path {
+            write_atomically(path, to_lines(&entries)?.as_bytes())?;
+        }
+        self.entries = entries;
+        self.unwritten = 0;
+
+        Ok(())
+    }
+
+    /// Get all entries, oldest first
+    pub(crate) fn entries(&self) -> &[T] {
+        &self.entries
+    }
+}
+
+/// Serialize entries as JSON lines
+fn to_lines<T: Serialize>(entries: &[T]) -> Result<String, MachineError> {
+    let mut lines = String::new();
+    for entry in entries {
+        let line =
+            serde_json::to_string(entry).map_err(|e| MachineError::Io(std::io::Error::other(e)))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+
+    Ok(lines)
+}
+
+/// Get the default path of the state file
+///
+/// The state file lives in `$XDG_DATA_HOME/rusty-coffeemachine`, falling back to
+/// `$HOME/.local/share/rusty-coffeemachine`.
+///
+/// # Returns
+///
+/// The path of the state file, if a data directory could be found
+///
+/// # Examples
+///
+/// ```
+/// let path = rusty_coffeemachine::persistence::default_state_path();
+/// ```
+pub fn default_state_path() -> Option<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(|home| PathBuf::from(home).join(".local").join("share"))
+        })?;
+
+    Some(data_dir.join(APP_DIR).join("state.json"))
+}
+
+/// A file of a test in the temporary directory of the test process, removed when dropped
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct TempPath(PathBuf);
+
+#[cfg(test)]
+impl std::ops::Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // The test may never have written the file
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Get a fresh path for a file of a test
+///
+/// # Arguments
+///
+/// * `name` - The file name, unique among the tests
+///
+/// # Returns
+///
+/// The path, whose directory exists and whose file doesn't
+#[cfg(test)]
+pub(crate) fn temp_path(name: &str) -> TempPath {
+    let dir = std::env::temp_dir().join(format!("rusty-coffeemachine-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let _ = std::fs::remove_file(&path);
+    TempPath(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path("save_and_load.json");
+        let mut state = SavedState {
+            tanks: IngredientTanks::default(),
+            garbage_container: GarbageContainer::default(),
+            counters: Counters::default(),
+            reciepes: Reciepes::get_reciepes().unwrap(),
+            coins: CoinInventory::starting_float(),
+            thresholds: StockThresholds::default(),
+            maintenance: MaintenanceConfig::default(),
+            maintenance_counters: MaintenanceCounters::default(),
+            queue: OrderQueue::new(),
+        };
+        state.tanks.milk.level = 42.0;
+        state.counters.beverages_brewed = 3;
+        state.save(&path).unwrap();
+
+        assert_eq!(SavedState::load(&path).unwrap(), state);
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        assert!(!PathBuf::from(temporary).exists());
+    }
+
+    #[test]
+    fn test_load_corrupt_file() {
+        let path = temp_path("corrupt.json");
+        std::fs::write(&*path, "{ \"tanks\": ").unwrap();
+        match SavedState::load(&path) {
+            Err(MachineError::CorruptState { path: corrupt, .. }) => assert_eq!(corrupt, *path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::containers::{Ingredient, IngredientsContainer};
+
+/// The kinds of steps a brewing program is made of
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepKind {
+    Grind,
+    PreInfuse,
+    Extract,
+    AddWater,
+    SteamMilk,
+    Froth,
+    AddSugar,
+    AddCacao,
+    Stir,
+}
+
+impl StepKind {
+    /// Get the ingredient the step uses
+    ///
+    /// # Returns
+    ///
+    /// The ingredient, `None` for steps without an ingredient like stirring
+    pub fn ingredient(&self) -> Option<Ingredient> {
+        match self {
+            StepKind::Grind => Some(Ingredient::Coffee),
+            StepKind::PreInfuse | StepKind::Extract | StepKind::AddWater => Some(Ingredient::Water),
+            StepKind::SteamMilk | StepKind::Froth => Some(Ingredient::Milk),
+            StepKind::AddSugar => Some(Ingredient::Sugar),
+            StepKind::AddCacao => Some(Ingredient::Cacao),
+            StepKind::Stir => None,
+        }
+    }
+}
+
+/// A single step of a brewing program
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub kind: StepKind,
+    /// The amount of the ingredient used, in the unit of the ingredient
+    #[serde(default)]
+    pub amount: f32,
+    /// The duration in seconds
+    #[serde(default)]
+    pub seconds: f32,
+}
+
+impl Step {
+    /// Create a new step
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of the step
+    /// * `amount` - The amount of the ingredient used, 0 for steps without an ingredient
+    /// * `seconds` - The duration of the step in seconds
+    ///
+    /// # Returns
+    ///
+    /// A new step
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::program::{Step, StepKind};
+    /// let step = Step::new(StepKind::SteamMilk, 50.0, 4.0);
+    /// assert_eq!(step.to_string(), "Steam 50 ml milk");
+    /// ```
+    pub fn new(kind: StepKind, amount: f32, seconds: f32) -> Self {
+        Step {
+            kind,
+            amount,
+            seconds,
+        }
+    }
+
+    /// Get the duration of the step
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.seconds.max(0.0))
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = |ingredient: Ingredient| {
+            format!("{} {} {}", self.amount, ingredient.unit(), ingredient)
+        };
+        match self.kind {
+            StepKind::Grind => write!(f, "Grind {}", amount(Ingredient::Coffee)),
+            StepKind::PreInfuse => write!(f, "Pre-infuse with {}", amount(Ingredient::Water)),
+            StepKind::Extract => write!(f, "Extract with {}", amount(Ingredient::Water)),
+            StepKind::AddWater => write!(f, "Top up with {}", amount(Ingredient::Water)),
+            StepKind::SteamMilk => write!(f, "Steam {}", amount(Ingredient::Milk)),
+            StepKind::Froth => write!(f, "Froth {}", amount(Ingredient::Milk)),
+            StepKind::AddSugar => write!(f, "Add {}", amount(Ingredient::Sugar)),
+            StepKind::AddCacao => write!(f, "Add {}", amount(Ingredient::Cacao)),
+            StepKind::Stir => write!(f, "Stir"),
+        }
+    }
+}
+
+/// Get the ingredients used by a brewing program
+///
+/// # Arguments
+///
+/// * `steps` - The steps of the program
+///
+/// # Returns
+///
+/// The sum of the ingredients used by all steps
+///
+/// # Examples
+///
+/// ```
+/// use rusty_coffeemachine::program::{totals, Step, StepKind};
+/// let ingredients = totals(&[
+///     Step::new(StepKind::PreInfuse, 5.0, 2.0),
+///     Step::new(StepKind::Extract, 25.0, 5.0),
+/// ]);
+/// assert_eq!(ingredients.water, 30.0);
+/// ```
+pub fn totals(steps: &[Step]) -> IngredientsContainer {
+    let mut totals = IngredientsContainer::default();
+    for step in steps {
+        if let Some(ingredient) = step.kind.ingredient() {
+            *totals.get_mut(ingredient) += step.amount;
+        }
+    }
+    totals
+}
+
+/// Get a brewing program for a reciepe that only lists its ingredients
+///
+/// The program grinds the coffee, extracts the water, steams the milk and adds the sugar and the
+/// cacao, skipping the ingredients the reciepe doesn't use. The steps take no time.
+///
+/// # Arguments
+///
+/// * `ingredients` - The ingredients of the reciepe
+///
+/// # Returns
+///
+/// The steps using the ingredients
+///
+/// # Examples
+///
+/// ```
+/// use rusty_coffeemachine::containers::IngredientsContainer;
+/// use rusty_coffeemachine::program::{steps_for, totals, StepKind};
+/// let ingredients = IngredientsContainer {
+///     water: 30.0,
+///     coffee: 30.0,
+///     ..Default::default()
+/// };
+/// let steps = steps_for(&ingredients);
+/// assert_eq!(steps[0].kind, StepKind::Grind);
+/// assert_eq!(steps.len(), 2);
+/// assert_eq!(totals(&steps), ingredients);
+/// ```
+pub fn steps_for(ingredients: &IngredientsContainer) -> Vec<Step> {
+    [
+        StepKind::Grind,
+        StepKind::Extract,
+        StepKind::SteamMilk,
+        StepKind::AddSugar,
+        StepKind::AddCacao,
+    ]
+    .into_iter()
+    .filter_map(|kind| {
+        let amount = ingredients.get(kind.ingredient()?);
+        (amount > 0.0).then(|| Step::new(kind, amount, 0.0))
+    })
+    .collect()
+}
+
+/// Get the duration of a brewing program
+///
+/// # Arguments
+///
+/// * `steps` - The steps of the program
+///
+/// # Returns
+///
+/// The sum of the durations of all steps
+pub fn duration(steps: &[Step]) -> Duration {
+    steps.iter().map(Step::duration).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals_and_duration() {
+        let steps = [
+            Step::new(StepKind::Grind, 30.0, 3.0),
+            Step::new(StepKind::Extract, 30.0, 5.0),
+            Step::new(StepKind::SteamMilk, 30.0, 3.0),
+            Step::new(StepKind::Froth, 20.0, 2.5),
+            Step::new(StepKind::Stir, 0.0, 1.5),
+        ];
+        let ingredients = totals(&steps);
+        assert_eq!(ingredients.coffee, 30.0);
+        assert_eq!(ingredients.water, 30.0);
+        assert_eq!(ingredients.milk, 50.0);
+        assert_eq!(ingredients.sugar, 0.0);
+        assert_eq!(duration(&steps), Duration::from_secs(15));
+    }
+
+    #[test]
+    fn test_step_display() {
+        assert_eq!(
+            Step::new(StepKind::Grind, 15.5, 2.0).to_string(),
+            "Grind 15.5 g coffee"
+        );
+        assert_eq!(
+            Step::new(StepKind::AddWater, 30.0, 2.0).to_string(),
+            "Top up with 30 ml water"
+        );
+        assert_eq!(Step::new(StepKind::Stir, 0.0, 1.0).to_string(), "Stir");
+    }
+
+    #[test]
+    fn test_parse_step() {
+        let step: Step =
+            toml::from_str("kind = \"pre_infuse\"\namount = 5.0\nseconds = 2").unwrap();
+        assert_eq!(step, Step::new(StepKind::PreInfuse, 5.0, 2.0));
+    }
+}
@@ -0,0 +1,472 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::beverage::Beverage;
+use crate::containers::{Ingredient, IngredientsContainer};
+use crate::customization::Order;
+use crate::error::MachineError;
+use crate::handle::MachineHandle;
+
+/// The number of an order in the queue, counting from 1
+pub type OrderId = u64;
+
+/// The number of finished orders the queue remembers for status polling
+const FINISHED_KEPT: usize = 100;
+
+/// How often the worker looks for new orders while the queue is empty
+const WORKER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The priority of a queued order, higher priorities are brewed first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 2] = [Priority::Normal, Priority::High];
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Normal => write!(f, "normal"),
+            Priority::High => write!(f, "high"),
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = MachineError;
+
+    fn from_str(priority: &str) -> Result<Self, Self::Err> {
+        Priority::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_string().eq_ignore_ascii_case(priority))
+            .ok_or_else(|| MachineError::InvalidInput(format!("Unknown priority: {}", priority)))
+    }
+}
+
+/// The progress of a queued order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Queued,
+    Brewing,
+    Ready(Beverage),
+    Failed(String),
+    Cancelled,
+}
+
+impl OrderStatus {
+    /// Check if the order is done, successfully or not
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, OrderStatus::Queued | OrderStatus::Brewing)
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderStatus::Queued => write!(f, "queued"),
+            OrderStatus::Brewing => write!(f, "brewing"),
+            OrderStatus::Ready(_) => write!(f, "ready"),
+            OrderStatus::Failed(_) => write!(f, "failed"),
+            OrderStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// An order in the queue
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueuedOrder {
+    pub id: OrderId,
+    /// The name of the ordered reciepe
+    pub name: String,
+    pub order: Order,
+    pub priority: Priority,
+    pub status: OrderStatus,
+    /// The ingredients held back for the order while it is queued
+    pub reserved: IngredientsContainer,
+    pub submitted_at: SystemTime,
+    /// When the order finished among the finished orders, counting from 1
+    #[serde(default)]
+    pub finished: Option<u64>,
+}
+
+impl fmt::Display for QueuedOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {}", self.id, self.name)?;
+        if self.priority != Priority::Normal {
+            write!(f, " ({} priority)", self.priority)?;
+        }
+        match &self.status {
+            OrderStatus::Failed(reason) => write!(f, ": failed, {}", reason),
+            status => write!(f, ": {}", status),
+        }
+    }
+}
+
+/// The orders waiting for the coffee machine and the recently finished ones
+///
+/// Orders are brewed by priority and in the order they were submitted within a priority. The
+/// ingredients of queued orders are reserved, so the coffee machine only accepts orders it can
+/// still make after all orders before them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OrderQueue {
+    orders: Vec<QueuedOrder>,
+    last_id: OrderId,
+    #[serde(default)]
+    last_finished: u64,
+}
+
+impl OrderQueue {
+    /// Create an empty order queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get all orders the queue remembers, in the order they were submitted
+    pub fn orders(&self) -> &[QueuedOrder] {
+        &self.orders
+    }
+
+    /// Get an order
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the order
+    ///
+    /// # Returns
+    ///
+    /// The order, or `None` if the queue doesn't know it
+    pub fn get(&self, id: OrderId) -> Option<&QueuedOrder> {
+        self.orders.iter().find(|queued| queued.id == id)
+    }
+
+    /// Get the queued orders
+    ///
+    /// # Returns
+    ///
+    /// The orders waiting to be brewed, the next one first
+    pub fn pending(&self) -> Vec<&QueuedOrder> {
+        let mut pending: Vec<&QueuedOrder> = self
+            .orders
+            .iter()
+            .filter(|queued| matches!(queued.status, OrderStatus::Queued))
+            .collect();
+        // The sort is stable, so orders of the same priority stay in submission order
+        pending.sort_by_key(|queued| std::cmp::Reverse(queued.priority));
+        pending
+    }
+
+    /// Get the position of a queued order
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the order
+    ///
+    /// # Returns
+    ///
+    /// The position starting at 1, or `None` if the order isn't waiting
+    pub fn position(&self, id: OrderId) -> Option<usize> {
+        self.pending()
+            .iter()
+            .position(|queued| queued.id == id)
+            .map(|position| position + 1)
+    }
+
+    /// Get the ingredients reserved for the queued orders
+    ///
+    /// # Returns
+    ///
+    /// The total of the reservations
+    pub fn reserved(&self) -> IngredientsContainer {
+        let mut reserved = IngredientsContainer::default();
+        for queued in self.pending() {
+            for ingredient in Ingredient::ALL {
+                *reserved.get_mut(ingredient) += queued.reserved.get(ingredient);
+            }
+        }
+        reserved
+    }
+
+    /// Add an order to the end of its priority
+    pub(crate) fn submit(
+        &mut self,
+        name: String,
+        order: Order,
+        priority: Priority,
+        reserved: IngredientsContainer,
+        now: SystemTime,
+    ) -> OrderId {
+        self.last_id += 1;
+        self.orders.push(QueuedOrder {
+            id: self.last_id,
+            name,
+            order,
+            priority,
+            status: OrderStatus::Queued,
+            reserved,
+            submitted_at: now,
+            finished: None,
+        });
+        self.last_id
+    }
+
+    /// Take the next order to brew and mark it as brewing
+    pub(crate) fn start_next(&mut self) -> Option<(OrderId, Order)> {
+        let id = self.pending().first()?.id;
+        let queued = self.get_mut(id)?;
+        queued.status = OrderStatus::Brewing;
+        Some((id, queued.order.clone()))
+    }
+
+    /// Record the outcome of a brewed order
+    pub(crate) fn finish(&mut self, id: OrderId, brewed: Result<&Beverage, &MachineError>) {
+        self.set_finished(
+            id,
+            match brewed {
+                Ok(beverage) => OrderStatus::Ready(beverage.clone()),
+                Err(e) => OrderStatus::Failed(e.to_string()),
+            },
+        );
+    }
+
+    /// Cancel a queued order, releasing its reservation
+    pub(crate) fn cancel(&mut self, id: OrderId) -> Result<(), MachineError> {
+        let queued = self.get_mut(id).ok_or(MachineError::UnknownOrder(id))?;
+        if !matches!(queued.status, OrderStatus::Queued) {
+            return Err(MachineError::InvalidInput(format!(
+                "Order #{} is {}, only queued orders can be cancelled",
+                id, queued.status
+            )));
+        }
+        self.set_finished(id, OrderStatus::Cancelled);
+
+        Ok(())
+    }
+
+    /// Fail the orders that were brewing when the coffee machine stopped
+    pub(crate) fn interrupt(&mut self) {
+        let brewing: Vec<OrderId> = self
+            .orders
+            .iter()
+            .filter(|queued| matches!(queued.status, OrderStatus::Brewing))
+            .map(|queued| queued.id)
+            .collect();
+        for id in brewing {
+            self.set_finished(
+                id,
+                OrderStatus::Failed("Interrupted while brewing".to_string()),
+            );
+        }
+    }
+
+    /// Finish an order and forget the orders that finished longest ago
+    fn set_finished(&mut self, id: OrderId, status: OrderStatus) {
+        self.last_finished += 1;
+        let finished = self.last_finished;
+        if let Some(queued) = self.get_mut(id) {
+            queued.status = status;
+            queued.finished = Some(finished);
+        }
+        self.forget_finished();
+    }
+
+    /// Get an order to change it
+    fn get_mut(&mut self, id: OrderId) -> Option<&mut QueuedOrder> {
+        self.orders.iter_mut().find(|queued| queued.id == id)
+    }
+
+    /// Drop the orders that finished longest ago beyond the ones kept for status polling
+    fn forget_finished(&mut self) {
+        let mut finished: Vec<(Option<u64>, OrderId)> = self
+            .orders
+            .iter()
+            .filter(|queued| queued.status.is_finished())
+            .map(|queued| (queued.finished, queued.id))
+            .collect();
+        if finished.len() <= FINISHED_KEPT {
+            return;
+        }
+        // Orders from state files without the finish order sort first and are dropped first
+        finished.sort();
+        let excess = finished.len() - FINISHED_KEPT;
+        let forgotten: Vec<OrderId> = finished[..excess].iter().map(|(_, id)| *id).collect();
+        self.orders.retain(|queued| !forgotten.contains(&queued.id));
+    }
+}
+
+/// A thread brewing the queued orders of a shared coffee machine, one at a time
+///
+/// The worker only holds the coffee machine while it brews, so orders can be submitted and
+/// cancelled in between.
+#[derive(Debug)]
+pub struct Worker {
+    stop: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Worker {
+    /// Start a worker
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The shared coffee machine to brew the queued orders with
+    ///
+    /// # Returns
+    ///
+    /// The running worker
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::handle::MachineHandle;
+    /// use rusty_coffeemachine::queue::{Priority, Worker};
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let id = machine.enqueue("Latte", Priority::Normal).unwrap();
+    /// let machine = MachineHandle::new(machine);
+    /// let worker = Worker::spawn(machine.clone());
+    /// worker.stop();
+    /// let status = machine.order_status(id).unwrap().to_string();
+    /// assert_eq!(status, "ready");
+    /// ```
+    pub fn spawn(machine: MachineHandle) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let thread = thread::spawn(move || loop {
+            if machine.process_next().is_none() {
+                if stopped.load(Ordering::Acquire) {
+                    return;
+                }
+                thread::sleep(WORKER_POLL_INTERVAL);
+            }
+        });
+
+        Worker { stop, thread }
+    }
+
+    /// Stop the worker once the queue is empty
+    ///
+    /// This function waits for the worker to brew the orders still in the queue.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+        // A panicking worker has nothing left to clean up
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::containers::IngredientsContainer;
+
+    fn submit(queue: &mut OrderQueue, name: &str, priority: Priority) -> OrderId {
+        let reserved = IngredientsContainer {
+            water: 30.0,
+            coffee: 30.0,
+            ..Default::default()
+        };
+        queue.submit(
+            name.to_string(),
+            Order::new(name),
+            priority,
+            reserved,
+            SystemTime::UNIX_EPOCH,
+        )
+    }
+
+    #[test]
+    fn test_priorities_and_fifo() {
+        let mut queue = OrderQueue::new();
+        let first = submit(&mut queue, "Espresso", Priority::Normal);
+        let second = submit(&mut queue, "Latte", Priority::Normal);
+        let urgent = submit(&mut queue, "Americano", Priority::High);
+        assert_eq!(queue.position(urgent), Some(1));
+        assert_eq!(queue.position(second), Some(3));
+        assert_eq!(queue.reserved().coffee, 90.0);
+
+        assert_eq!(queue.start_next().unwrap().0, urgent);
+        assert_eq!(queue.reserved().coffee, 60.0);
+        assert_eq!(queue.start_next().unwrap().0, first);
+        queue.finish(
+            first,
+            Err(&MachineError::UnknownRecipe("Espresso".to_string())),
+        );
+        assert_eq!(
+            queue.get(first).unwrap().to_string(),
+            "#1 Espresso: failed, Unknown reciepe: Espresso"
+        );
+        assert_eq!(queue.start_next().unwrap().0, second);
+        assert!(queue.start_next().is_none());
+        assert_eq!(queue.reserved().coffee, 0.0);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut queue = OrderQueue::new();
+        let brewing = submit(&mut queue, "Espresso", Priority::Normal);
+        let waiting = submit(&mut queue, "Latte", Priority::Normal);
+        queue.start_next();
+        assert!(matches!(
+            queue.cancel(brewing),
+            Err(MachineError::InvalidInput(_))
+        ));
+        queue.cancel(waiting).unwrap();
+        assert_eq!(
+            queue.get(waiting).unwrap().to_string(),
+            "#2 Latte: cancelled"
+        );
+        assert!(matches!(
+            queue.cancel(7),
+            Err(MachineError::UnknownOrder(7))
+        ));
+
+        queue.interrupt();
+        assert!(matches!(
+            queue.get(brewing).unwrap().status,
+            OrderStatus::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn test_forget_finished_orders() {
+        let mut queue = OrderQueue::new();
+        for _ in 0..FINISHED_KEPT + 5 {
+            let id = submit(&mut queue, "Espresso", Priority::Normal);
+            queue.cancel(id).unwrap();
+        }
+        let waiting = submit(&mut queue, "Latte", Priority::Normal);
+        assert_eq!(queue.orders().len(), FINISHED_KEPT + 1);
+        assert!(queue.get(1).is_none());
+        assert_eq!(queue.position(waiting), Some(1));
+    }
+
+    #[test]
+    fn test_forget_the_orders_finished_longest_ago() {
+        let mut queue = OrderQueue::new();
+        let first = submit(&mut queue, "Latte", Priority::Normal);
+        for _ in 0..FINISHED_KEPT {
+            let id = submit(&mut queue, "Espresso", Priority::Normal);
+            queue.cancel(id).unwrap();
+        }
+        let (id, _) = queue.start_next().unwrap();
+        assert_eq!(id, first);
+        queue.finish(id, Err(&MachineError::UnknownOrder(id)));
+        assert!(queue.get(first).unwrap().status.is_finished());
+        assert!(queue.get(2).is_none());
+        assert_eq!(queue.orders().len(), FINISHED_KEPT);
+    }
+}
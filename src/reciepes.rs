@@ -1,9 +1,60 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::boiler::{BREW_TEMPERATURE, STEAM_TEMPERATURE};
 use crate::containers::IngredientsContainer;
+use crate::error::MachineError;
+use crate::program::{self, Step, StepKind};
+
+/// A reference to a reciepe, either by its menu number (starting at 1) or by its name
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RecipeRef {
+    Id(usize),
+    Name(String),
+}
+
+impl From<usize> for RecipeRef {
+    fn from(id: usize) -> Self {
+        RecipeRef::Id(id)
+    }
+}
 
-#[derive(Clone, Debug)]
+impl From<&str> for RecipeRef {
+    fn from(name: &str) -> Self {
+        RecipeRef::Name(name.to_string())
+    }
+}
+
+impl From<String> for RecipeRef {
+    fn from(name: String) -> Self {
+        RecipeRef::Name(name)
+    }
+}
+
+impl std::fmt::Display for RecipeRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecipeRef::Id(id) => write!(f, "#{}", id),
+            RecipeRef::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Reciepes {
     pub name: String,
     pub ingredients: IngredientsContainer,
+    pub description: String,
+    /// The price in cents
+    pub price: u32,
+    /// The brewing program, empty for reciepes that use their ingredients all at once
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+    /// The boiler temperature in °C, by default the brewing or, with milk, the steam temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
 }
 
 impl Reciepes {
@@ -35,12 +86,129 @@ impl Reciepes {
     ///    cacao: 0.0,
     /// });
     /// ```
-    pub fn new(name: String, ingredients: IngredientsContainer) -> Result<Self, std::io::Error> {
-        let reciepes = Reciepes { name, ingredients };
+    pub fn new(name: String, ingredients: IngredientsContainer) -> Result<Self, MachineError> {
+        let reciepes = Reciepes {
+            name,
+            ingredients,
+            description: String::new(),
+            price: 0,
+            steps: Vec::new(),
+            temperature: None,
+        };
 
         Ok(reciepes)
     }
 
+    /// Create a new reciepe from a brewing program
+    ///
+    /// The ingredients of the reciepe are the totals of the steps.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the reciepe
+    /// * `steps` - The steps of the brewing program, in order
+    ///
+    /// # Returns
+    ///
+    /// A new reciepe
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if creating the reciepe fails
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::program::{Step, StepKind};
+    /// use rusty_coffeemachine::Reciepes;
+    /// let reciepe = Reciepes::from_steps("Ristretto".to_string(), vec![
+    ///     Step::new(StepKind::Grind, 30.0, 3.0),
+    ///     Step::new(StepKind::Extract, 15.0, 3.0),
+    /// ]).unwrap();
+    /// assert_eq!(reciepe.ingredients.water, 15.0);
+    /// assert_eq!(reciepe.duration().as_secs(), 6);
+    /// ```
+    pub fn from_steps(name: String, steps: Vec<Step>) -> Result<Self, MachineError> {
+        let mut reciepe = Reciepes::new(name, program::totals(&steps))?;
+        reciepe.steps = steps;
+
+        Ok(reciepe)
+    }
+
+    /// Get the duration of the brewing program
+    ///
+    /// # Returns
+    ///
+    /// The sum of the durations of the steps, zero for reciepes without a brewing program
+    pub fn duration(&self) -> Duration {
+        program::duration(&self.steps)
+    }
+
+    /// Set the description of the reciepe
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - The description of the reciepe
+    ///
+    /// # Returns
+    ///
+    /// The reciepe with the description
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the price of the reciepe
+    ///
+    /// # Arguments
+    ///
+    /// * `price` - The price of the reciepe in cents
+    ///
+    /// # Returns
+    ///
+    /// The reciepe with the price
+    pub fn with_price(mut self, price: u32) -> Self {
+        self.price = price;
+        self
+    }
+
+    /// Set the boiler temperature of the reciepe
+    ///
+    /// # Arguments
+    ///
+    /// * `celsius` - The temperature in °C
+    ///
+    /// # Returns
+    ///
+    /// The reciepe with the temperature
+    pub fn with_temperature(mut self, celsius: f32) -> Self {
+        self.temperature = Some(celsius);
+        self
+    }
+
+    /// Get the temperature the boiler needs to brew the reciepe
+    ///
+    /// # Returns
+    ///
+    /// The temperature of the reciepe, or the steam temperature for reciepes with milk and the
+    /// brewing temperature for all others
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::Reciepes;
+    /// let reciepes = Reciepes::get_reciepes().unwrap();
+    /// assert_eq!(reciepes[0].target_temperature(), 93.0);
+    /// assert_eq!(reciepes[3].target_temperature(), 125.0);
+    /// ```
+    pub fn target_temperature(&self) -> f32 {
+        self.temperature.unwrap_or(if self.ingredients.milk > 0.0 {
+            STEAM_TEMPERATURE
+        } else {
+            BREW_TEMPERATURE
+        })
+    }
+
     /// Get a list of reciepes
     ///
     /// # Returns
@@ -57,61 +225,100 @@ impl Reciepes {
     /// use rusty_coffeemachine::Reciepes;
     /// let reciepes = Reciepes::get_reciepes();
     /// ```
-    pub fn get_reciepes() -> Result<Vec<Reciepes>, std::io::Error> {
+    pub fn get_reciepes() -> Result<Vec<Reciepes>, MachineError> {
+        let espresso = || {
+            vec![
+                Step::new(StepKind::Grind, 30.0, 3.0),
+                Step::new(StepKind::PreInfuse, 5.0, 2.0),
+                Step::new(StepKind::Extract, 25.0, 5.0),
+            ]
+        };
         let init_reciepes = vec![
-            Reciepes::new(
-                "Espresso".to_string(),
-                IngredientsContainer {
-                    water: 30.0,
-                    coffee: 30.0,
-                    milk: 0.0,
-                    sugar: 0.0,
-                    cacao: 0.0,
-                },
-            )?,
-            Reciepes::new(
+            Reciepes::from_steps("Espresso".to_string(), espresso())?
+                .with_description("A short and strong shot of coffee")
+                .with_price(180),
+            Reciepes::from_steps(
                 "Americano".to_string(),
-                IngredientsContainer {
-                    water: 30.0,
-                    coffee: 30.0,
-                    milk: 0.0,
-                    sugar: 0.0,
-                    cacao: 0.0,
-                },
-            )?,
-            Reciepes::new(
+                [espresso(), vec![Step::new(StepKind::AddWater, 30.0, 2.0)]].concat(),
+            )?
+            .with_description("An espresso topped up with hot water")
+            .with_price(220),
+            Reciepes::from_steps(
                 "Cappuccino".to_string(),
-                IngredientsContainer {
-                    water: 30.0,
-                    coffee: 30.0,
-                    milk: 30.0,
-                    sugar: 0.0,
-                    cacao: 0.0,
-                },
-            )?,
-            Reciepes::new(
+                [
+                    espresso(),
+                    vec![
+                        Step::new(StepKind::SteamMilk, 15.0, 2.0),
+                        Step::new(StepKind::Froth, 15.0, 3.0),
+                    ],
+                ]
+                .concat(),
+            )?
+            .with_description("An espresso with steamed and foamed milk")
+            .with_price(280),
+            Reciepes::from_steps(
                 "Latte".to_string(),
-                IngredientsContainer {
-                    water: 30.0,
-                    coffee: 30.0,
-                    milk: 50.0,
-                    sugar: 0.0,
-                    cacao: 0.0,
-                },
-            )?,
-            Reciepes::new(
+                [espresso(), vec![Step::new(StepKind::SteamMilk, 50.0, 4.0)]].concat(),
+            )?
+            .with_description("An espresso with plenty of steamed milk")
+            .with_price(300),
+            Reciepes::from_steps(
                 "Mocha".to_string(),
-                IngredientsContainer {
-                    water: 30.0,
-                    coffee: 30.0,
-                    milk: 30.0,
-                    sugar: 30.0,
-                    cacao: 30.0,
-                },
-            )?,
+                [
+                    espresso(),
+                    vec![
+                        Step::new(StepKind::AddCacao, 30.0, 1.0),
+                        Step::new(StepKind::AddSugar, 30.0, 1.0),
+                        Step::new(StepKind::SteamMilk, 30.0, 3.0),
+                        Step::new(StepKind::Stir, 0.0, 2.0),
+                    ],
+                ]
+                .concat(),
+            )?
+            .with_description("A sweet latte with chocolate")
+            .with_price(350),
         ];
         Ok(init_reciepes)
     }
+
+    /// Find a reciepe in a list of reciepes
+    ///
+    /// Names are compared case-insensitively, ids are the 1-based menu numbers.
+    ///
+    /// # Arguments
+    ///
+    /// * `reciepes` - The reciepes to search
+    /// * `reference` - The id or name of the reciepe
+    ///
+    /// # Returns
+    ///
+    /// The matching reciepe
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no reciepe matches the reference
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::Reciepes;
+    /// let reciepes = Reciepes::get_reciepes().unwrap();
+    /// let latte = Reciepes::find(&reciepes, &"latte".into()).unwrap();
+    /// assert_eq!(latte.name, "Latte");
+    /// ```
+    pub fn find<'a>(
+        reciepes: &'a [Reciepes],
+        reference: &RecipeRef,
+    ) -> Result<&'a Reciepes, MachineError> {
+        let found = match reference {
+            RecipeRef::Id(id) => id.checked_sub(1).and_then(|index| reciepes.get(index)),
+            RecipeRef::Name(name) => reciepes
+                .iter()
+                .find(|reciepe| reciepe.name.eq_ignore_ascii_case(name.trim())),
+        };
+
+        found.ok_or_else(|| MachineError::UnknownRecipe(reference.to_string()))
+    }
 }
 
 #[cfg(test)]
@@ -151,4 +358,45 @@ mod tests {
         assert_eq!(reciepes[3].name, "Latte");
         assert_eq!(reciepes[4].name, "Mocha");
     }
+
+    #[test]
+    fn test_reciepe_totals_are_derived_from_the_steps() {
+        let reciepes = Reciepes::get_reciepes().unwrap();
+        let mocha = &reciepes[4];
+
+        assert_eq!(mocha.steps.len(), 7);
+        assert_eq!(mocha.ingredients.water, 30.0);
+        assert_eq!(mocha.ingredients.coffee, 30.0);
+        assert_eq!(mocha.ingredients.milk, 30.0);
+        assert_eq!(mocha.ingredients.sugar, 30.0);
+        assert_eq!(mocha.ingredients.cacao, 30.0);
+        assert_eq!(mocha.duration(), Duration::from_secs(17));
+        assert_eq!(reciepes[1].ingredients.water, 60.0);
+    }
+
+    #[test]
+    fn test_find_reciepe() {
+        let reciepes = Reciepes::get_reciepes().unwrap();
+
+        assert_eq!(
+            Reciepes::find(&reciepes, &1.into()).unwrap().name,
+            "Espresso"
+        );
+        assert_eq!(
+            Reciepes::find(&reciepes, &"MOCHA".into()).unwrap().name,
+            "Mocha"
+        );
+        assert!(matches!(
+            Reciepes::find(&reciepes, &0.into()),
+            Err(MachineError::UnknownRecipe(_))
+        ));
+        assert!(matches!(
+            Reciepes::find(&reciepes, &6.into()),
+            Err(MachineError::UnknownRecipe(_))
+        ));
+        assert!(matches!(
+            Reciepes::find(&reciepes, &"Flat White".into()),
+            Err(MachineError::UnknownRecipe(_))
+        ));
+    }
 }
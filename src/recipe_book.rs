@@ -0,0 +1,584 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::boiler::{AMBIENT_TEMPERATURE, MAX_TEMPERATURE};
+use crate::containers::{Ingredient, IngredientsContainer};
+use crate::error::MachineError;
+use crate::program::{Step, StepKind};
+use crate::reciepes::Reciepes;
+
+/// The file formats a reciepe book can be written in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecipeFormat {
+    Toml,
+    Json,
+}
+
+impl RecipeFormat {
+    /// Get the format of a reciepe book from its file extension
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the reciepe book
+    ///
+    /// # Returns
+    ///
+    /// The format of the reciepe book
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the extension is neither `toml` nor `json`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::recipe_book::RecipeFormat;
+    /// let format = RecipeFormat::from_path("recipes.toml".as_ref()).unwrap();
+    /// assert_eq!(format, RecipeFormat::Toml);
+    /// ```
+    pub fn from_path(path: &Path) -> Result<Self, MachineError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => Ok(RecipeFormat::Toml),
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Ok(RecipeFormat::Json),
+            _ => Err(MachineError::InvalidRecipeBook {
+                line: None,
+                entry: None,
+                message: format!("{} is neither a .toml nor a .json file", path.display()),
+            }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecipeBook<E> {
+    recipes: Vec<E>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecipeEntry {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    price: f64,
+    ingredients: Option<IngredientsContainer>,
+    #[serde(default)]
+    steps: Vec<Step>,
+    temperature: Option<f32>,
+}
+
+/// Load a reciepe book from a file
+///
+/// # Arguments
+///
+/// * `path` - The path of the reciepe book, ending in `.toml` or `.json`
+///
+/// # Returns
+///
+/// The reciepes of the reciepe book
+///
+/// # Errors
+///
+/// This function will return an error if the file can't be read or the reciepe book is invalid
+///
+/// # Examples
+///
+/// ```no_run
+/// let reciepes = rusty_coffeemachine::recipe_book::load("recipes.toml".as_ref()).unwrap();
+/// ```
+pub fn load(path: &Path) -> Result<Vec<Reciepes>, MachineError> {
+    let format = RecipeFormat::from_path(path)?;
+    let source = std::fs::read_to_string(path)?;
+
+    parse(&source, format)
+}
+
+/// Parse a reciepe book
+///
+/// # Arguments
+///
+/// * `source` - The content of the reciepe book
+/// * `format` - The format of the reciepe book
+///
+/// # Returns
+///
+/// The reciepes of the reciepe book
+///
+/// # Errors
+///
+/// This function will return an error pointing at the offending entry and line if the reciepe
+/// book is invalid
+///
+/// # Examples
+///
+/// ```
+/// use rusty_coffeemachine::recipe_book::{parse, RecipeFormat};
+/// let source = r#"
+/// [[recipes]]
+/// name = "Ristretto"
+/// price = 1.5
+///
+/// [recipes.ingredients]
+/// water = 15.0
+/// coffee = 30.0
+/// "#;
+/// let reciepes = parse(source, RecipeFormat::Toml).unwrap();
+/// assert_eq!(reciepes[0].price, 150);
+/// ```
+pub fn parse(source: &str, format: RecipeFormat) -> Result<Vec<Reciepes>, MachineError> {
+    // The lines of the entries, serde_json keeps no spans so they are looked up on errors
+    let entries: Vec<(Option<usize>, RecipeEntry)> = match format {
+        RecipeFormat::Toml => {
+            let book: RecipeBook<toml::Spanned<RecipeEntry>> =
+                toml::from_str(source).map_err(|e| MachineError::InvalidRecipeBook {
+                    line: e.span().map(|span| line_of_offset(source, span.start)),
+                    entry: None,
+                    message: e.message().to_string(),
+                })?;
+            book.recipes
+                .into_iter()
+                .map(|entry| {
+                    let line = line_of_offset(source, entry.span().start);
+                    (Some(line), entry.into_inner())
+                })
+                .collect()
+        }
+        RecipeFormat::Json => {
+            let book: RecipeBook<RecipeEntry> =
+                serde_json::from_str(source).map_err(|e| MachineError::InvalidRecipeBook {
+                    line: Some(e.line()),
+                    entry: None,
+                    message: json_message(&e),
+                })?;
+            book.recipes
+                .into_iter()
+                .map(|entry| (None, entry))
+                .collect()
+        }
+    };
+
+    if entries.is_empty() {
+        return Err(MachineError::InvalidRecipeBook {
+            line: None,
+            entry: None,
+            message: "The reciepe book contains no reciepes".to_string(),
+        });
+    }
+
+    let mut names = HashSet::new();
+    let mut reciepes = Vec::with_capacity(entries.len());
+    for (index, (line, entry)) in entries.into_iter().enumerate() {
+        let invalid = |message: String| MachineError::InvalidRecipeBook {
+            line: line.or_else(|| json_entry_line(source, index)),
+            entry: Some(if entry.name.is_empty() {
+                format!("#{}", index + 1)
+            } else {
+                entry.name.clone()
+            }),
+            message,
+        };
+
+        if entry.name.trim().is_empty() {
+            return Err(invalid("The name must not be empty".to_string()));
+        }
+        if !names.insert(entry.name.to_lowercase()) {
+            return Err(invalid("The name is used more than once".to_string()));
+        }
+        if let Some(ingredients) = &entry.ingredients {
+            for ingredient in Ingredient::ALL {
+                let amount = ingredients.get(ingredient);
+                if !amount.is_finite() || amount < 0.0 {
+                    return Err(invalid(format!(
+                        "The amount of {} must be a positive number, got {}",
+                        ingredient, amount
+                    )));
+                }
+            }
+        }
+        for (number, step) in entry.steps.iter().enumerate() {
+            if !step.amount.is_finite() || step.amount < 0.0 {
+                return Err(invalid(format!(
+                    "The amount of step {} must be a positive number, got {}",
+                    number + 1,
+                    step.amount
+                )));
+            }
+            if !step.seconds.is_finite() || step.seconds < 0.0 {
+                return Err(invalid(format!(
+                    "The duration of step {} must be a positive number, got {}",
+                    number + 1,
+                    step.seconds
+                )));
+            }
+            if step.kind == StepKind::Stir && step.amount != 0.0 {
+                return Err(invalid(format!(
+                    "Step {} stirs and uses no ingredient, got an amount of {}",
+                    number + 1,
+                    step.amount
+                )));
+            }
+        }
+        if let Some(temperature) = entry.temperature {
+            if !(AMBIENT_TEMPERATURE..=MAX_TEMPERATURE).contains(&temperature) {
+                return Err(invalid(format!(
+                    "The temperature must be between {} and {} °C, got {}",
+                    AMBIENT_TEMPERATURE, MAX_TEMPERATURE, temperature
+                )));
+            }
+        }
+        if !entry.price.is_finite() || entry.price < 0.0 {
+            return Err(invalid(format!(
+                "The price must be a positive number, got {}",
+                entry.price
+            )));
+        }
+
+        let reciepe = match (entry.ingredients, entry.steps.is_empty()) {
+            (Some(ingredients), true) => Reciepes::new(entry.name, ingredients)?,
+            (None, false) => Reciepes::from_steps(entry.name, entry.steps)?,
+            (Some(_), false) => {
+                return Err(invalid(
+                    "The ingredients are derived from the steps, give only one of them".to_string(),
+                ))
+            }
+            (None, true) => {
+                return Err(invalid(
+                    "Either the ingredients or the steps are needed".to_string(),
+                ))
+            }
+        };
+        let mut reciepe = reciepe
+            .with_description(entry.description)
+            .with_price((entry.price * 100.0).round() as u32);
+        reciepe.temperature = entry.temperature;
+        reciepes.push(reciepe);
+    }
+
+    Ok(reciepes)
+}
+
+/// Get the line number of a byte offset
+fn line_of_offset(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+/// Get the message of a JSON error without its position
+fn json_message(error: &serde_json::Error) -> String {
+    error
+        .to_string()
+        .trim_end_matches(&format!(
+            " at line {} column {}",
+            error.line(),
+            error.column()
+        ))
+        .to_string()
+}
+
+/// Get the line an entry of a JSON reciepe book starts on
+///
+/// The entry is found by parsing the reciepe book again and stopping at the entry, so the parser
+/// reports where it stands.
+///
+/// # Arguments
+///
+/// * `source` - The content of the reciepe book
+/// * `index` - The index of the entry
+///
+/// # Returns
+///
+/// The line number, or `None` if the reciepe book has no such entry
+fn json_entry_line(source: &str, index: usize) -> Option<usize> {
+    let mut deserializer = serde_json::Deserializer::from_str(source);
+    match deserializer.deserialize_map(EntryFinder { index }) {
+        Err(e) if e.to_string().starts_with(ENTRY_FOUND) => Some(e.line()),
+        _ => None,
+    }
+}
+
+/// The error stopping the parser at the entry looked for
+const ENTRY_FOUND: &str = "entry found";
+
+/// A visitor of a reciepe book failing at one of its entries
+struct EntryFinder {
+    index: usize,
+}
+
+impl<'de> Visitor<'de> for EntryFinder {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a reciepe book with at least {} entries", self.index + 1)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "recipes" {
+                return map.next_value_seed(self);
+            }
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        for _ in 0..self.index {
+            seq.next_element::<IgnoredAny>()?;
+        }
+        seq.next_element_seed(EntryStop)?;
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for EntryFinder {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+/// An entry failing before the parser moves past its start
+struct EntryStop;
+
+impl<'de> DeserializeSeed<'de> for EntryStop {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, _: D) -> Result<(), D::Error> {
+        Err(de::Error::custom(ENTRY_FOUND))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML_BOOK: &str = r#"
+[[recipes]]
+name = "Ristretto"
+description = "A very short espresso"
+price = 1.5
+
+[recipes.ingredients]
+water = 15.0
+coffee = 30.0
+
+[[recipes]]
+name = "Hot Chocolate"
+price = 2.2
+
+[recipes.ingredients]
+milk = 60.0
+cacao = 20.0
+sugar = 10.0
+"#;
+
+    #[test]
+    fn test_parse_toml() {
+        let reciepes = parse(TOML_BOOK, RecipeFormat::Toml).unwrap();
+        assert_eq!(reciepes.len(), 2);
+        assert_eq!(reciepes[0].name, "Ristretto");
+        assert_eq!(reciepes[0].description, "A very short espresso");
+        assert_eq!(reciepes[0].price, 150);
+        assert_eq!(reciepes[0].ingredients.water, 15.0);
+        assert_eq!(reciepes[0].ingredients.milk, 0.0);
+        assert_eq!(reciepes[1].name, "Hot Chocolate");
+        assert_eq!(reciepes[1].price, 220);
+        assert_eq!(reciepes[1].ingredients.cacao, 20.0);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let source = r#"{
+  "recipes": [
+    {
+      "name": "Ristretto",
+      "price": 1.5,
+      "ingredients": { "water": 15.0, "coffee": 30.0 }
+    }
+  ]
+}"#;
+        let reciepes = parse(source, RecipeFormat::Json).unwrap();
+        assert_eq!(reciepes.len(), 1);
+        assert_eq!(reciepes[0].ingredients.coffee, 30.0);
+    }
+
+    #[test]
+    fn test_parse_invalid_amount_points_at_entry() {
+        let source = TOML_BOOK.replace("cacao = 20.0", "cacao = -20.0");
+        match parse(&source, RecipeFormat::Toml) {
+            Err(MachineError::InvalidRecipeBook {
+                line,
+                entry,
+                message,
+            }) => {
+                assert_eq!(line, Some(11));
+                assert_eq!(entry.as_deref(), Some("Hot Chocolate"));
+                assert!(message.contains("cacao"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_duplicate_name() {
+        let source = TOML_BOOK.replace("Hot Chocolate", "ristretto");
+        assert!(matches!(
+            parse(&source, RecipeFormat::Toml),
+            Err(MachineError::InvalidRecipeBook { line: Some(11), .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_syntax_errors_have_lines() {
+        let source = TOML_BOOK.replace("water = 15.0", "water = fifteen");
+        assert!(matches!(
+            parse(&source, RecipeFormat::Toml),
+            Err(MachineError::InvalidRecipeBook { line: Some(8), .. })
+        ));
+
+        let source = "{\n  \"recipes\": [\n    { \"name\": \"Ristretto\", \"ingredients\": { \"water\": true } }\n  ]\n}";
+        assert!(matches!(
+            parse(source, RecipeFormat::Json),
+            Err(MachineError::InvalidRecipeBook { line: Some(3), .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_unknown_ingredient() {
+        let source = TOML_BOOK.replace("sugar = 10.0", "honey = 10.0");
+        assert!(matches!(
+            parse(&source, RecipeFormat::Toml),
+            Err(MachineError::InvalidRecipeBook { line: Some(18), .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        let source = r#"
+[[recipes]]
+name = "Flat White"
+
+[[recipes.steps]]
+kind = "grind"
+amount = 40.0
+seconds = 4
+
+[[recipes.steps]]
+kind = "extract"
+amount = 30.0
+seconds = 5
+
+[[recipes.steps]]
+kind = "froth"
+amount = 40.0
+seconds = 3
+"#;
+        let reciepes = parse(source, RecipeFormat::Toml).unwrap();
+        assert_eq!(reciepes[0].steps.len(), 3);
+        assert_eq!(reciepes[0].steps[2].kind, StepKind::Froth);
+        assert_eq!(reciepes[0].ingredients.coffee, 40.0);
+        assert_eq!(reciepes[0].ingredients.milk, 40.0);
+
+        let source = source.replace("seconds = 3", "seconds = -3");
+        match parse(&source, RecipeFormat::Toml) {
+            Err(MachineError::InvalidRecipeBook { entry, message, .. }) => {
+                assert_eq!(entry.as_deref(), Some("Flat White"));
+                assert!(message.contains("step 3"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_temperature() {
+        let source = r#"{
+  "recipes": [
+    { "name": "Tea water", "temperature": 80, "ingredients": { "water": 50.0 } },
+    { "name": "Ristretto", "ingredients": { "water": 15.0, "coffee": 30.0 } }
+  ]
+}"#;
+        let reciepes = parse(source, RecipeFormat::Json).unwrap();
+        assert_eq!(reciepes[0].target_temperature(), 80.0);
+        assert_eq!(reciepes[1].temperature, None);
+
+        let source = source.replace("80", "180");
+        match parse(&source, RecipeFormat::Json) {
+            Err(MachineError::InvalidRecipeBook { entry, message, .. }) => {
+                assert_eq!(entry.as_deref(), Some("Tea water"));
+                assert!(message.contains("between 20 and 140"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_needs_ingredients_or_steps() {
+        let source = "[[recipes]]\nname = \"Nothing\"\n";
+        assert!(matches!(
+            parse(source, RecipeFormat::Toml),
+            Err(MachineError::InvalidRecipeBook { line: Some(1), .. })
+        ));
+
+        let source = format!(
+            "{}\n[[recipes.steps]]\nkind = \"stir\"\n",
+            TOML_BOOK.trim_end()
+        );
+        assert!(matches!(
+            parse(&source, RecipeFormat::Toml),
+            Err(MachineError::InvalidRecipeBook { line: Some(11), .. })
+        ));
+    }
+
+    #[test]
+    fn test_entry_lines_come_from_the_parser() {
+        let line = |source: &str, format| match parse(source, format) {
+            Err(MachineError::InvalidRecipeBook { line, .. }) => line,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        let source = "recipes = [\n  { name = \"A\", ingredients = { water = 1.0 } },\n  { name = \"a\", ingredients = { water = 1.0 } },\n]\n";
+        assert_eq!(line(source, RecipeFormat::Toml), Some(3));
+
+        let source = r#"
+[[recipes]]
+name = "A"
+description = """
+name = "not a reciepe"
+"""
+ingredients = { water = 1.0 }
+
+[[recipes]]
+name = "a"
+ingredients = { water = 1.0 }
+"#;
+        assert_eq!(line(source, RecipeFormat::Toml), Some(9));
+
+        let source = r#"{"recipes": [{"name": "A", "ingredients": {"water": 1}}, {"name": "a", "ingredients": {"water": 1}}]}"#;
+        assert_eq!(line(source, RecipeFormat::Json), Some(1));
+
+        let source = "{\n  \"recipes\": [\n    { \"name\": \"A\", \"ingredients\": { \"water\": 1 } },\n    {\n      \"name\": \"a\",\n      \"ingredients\": { \"water\": 1 }\n    }\n  ]\n}";
+        assert_eq!(line(source, RecipeFormat::Json), Some(4));
+    }
+
+    #[test]
+    fn test_parse_empty_book() {
+        assert!(matches!(
+            parse("recipes = []", RecipeFormat::Toml),
+            Err(MachineError::InvalidRecipeBook { line: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            RecipeFormat::from_path("book.JSON".as_ref()).unwrap(),
+            RecipeFormat::Json
+        );
+        assert!(RecipeFormat::from_path("book.yaml".as_ref()).is_err());
+    }
+}
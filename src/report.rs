@@ -0,0 +1,640 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+use crate::containers::{Ingredient, IngredientsContainer};
+use crate::error::MachineError;
+use crate::ledger::{unix_seconds, Ledger};
+use crate::{capitalize, format_price};
+
+/// The seconds of a day
+const DAY: u64 = 24 * 60 * 60;
+
+/// The time span a report covers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    /// A single day
+    Daily,
+    /// The week from Monday to Sunday
+    Weekly,
+}
+
+impl FromStr for Period {
+    type Err = MachineError;
+
+    fn from_str(period: &str) -> Result<Self, Self::Err> {
+        match period.to_lowercase().as_str() {
+            "daily" | "day" => Ok(Period::Daily),
+            "weekly" | "week" => Ok(Period::Weekly),
+            _ => Err(MachineError::InvalidInput(format!(
+                "Unknown report period: {}",
+                period
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::Daily => write!(f, "daily"),
+            Period::Weekly => write!(f, "weekly"),
+        }
+    }
+}
+
+/// The formats a report can be written in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = MachineError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "text" | "txt" => Ok(ReportFormat::Text),
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(MachineError::InvalidInput(format!(
+                "Unknown report format: {}",
+                format
+            ))),
+        }
+    }
+}
+
+/// The sales of a single reciepe
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecipeSales {
+    pub name: String,
+    pub units: u32,
+    /// The revenue in cents
+    pub revenue: u32,
+}
+
+/// An order that failed
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedOrder {
+    pub recipe: String,
+    pub reason: String,
+    /// The seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+/// A summary of the orders of a day or a week
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub period: Period,
+    /// The first day of the report, in days since the unix epoch
+    pub first_day: u64,
+    /// The last day of the report, in days since the unix epoch
+    pub last_day: u64,
+    /// The sales per reciepe, sorted by name
+    pub recipes: Vec<RecipeSales>,
+    /// The revenue in cents
+    pub revenue: u32,
+    pub consumption: IngredientsContainer,
+    pub failed: Vec<FailedOrder>,
+}
+
+impl Report {
+    /// Summarize the orders of the ledger
+    ///
+    /// # Arguments
+    ///
+    /// * `ledger` - The ledger to summarize
+    /// * `period` - The time span of the report
+    /// * `day` - A day in the time span
+    ///
+    /// # Returns
+    ///
+    /// The report of the day or the week containing the day
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::ledger::Ledger;
+    /// use rusty_coffeemachine::report::{parse_date, Period, Report};
+    /// let day = parse_date("2024-05-15").unwrap();
+    /// let report = Report::new(&Ledger::new(), Period::Weekly, day);
+    /// assert_eq!(report.revenue, 0);
+    /// assert!(report.recipes.is_empty());
+    /// ```
+    pub fn new(ledger: &Ledger, period: Period, day: SystemTime) -> Self {
+        let day = unix_seconds(day) / DAY;
+        let (first_day, last_day) = match period {
+            Period::Daily => (day, day),
+            Period::Weekly => {
+                // The unix epoch was a Thursday, its week is cut off at the epoch
+                let sunday = day + 6 - (day + 3) % 7;
+                (sunday.saturating_sub(6), sunday)
+            }
+        };
+        let from = UNIX_EPOCH + Duration::from_secs(first_day * DAY);
+        let to = UNIX_EPOCH + Duration::from_secs((last_day + 1) * DAY);
+
+        let mut recipes: BTreeMap<String, RecipeSales> = BTreeMap::new();
+        let mut consumption = IngredientsContainer::default();
+        let mut failed = Vec::new();
+        for entry in ledger.between(from, to) {
+            match &entry.failure {
+                None => {
+                    let sales =
+                        recipes
+                            .entry(entry.recipe.clone())
+                            .or_insert_with(|| RecipeSales {
+                                name: entry.recipe.clone(),
+                                units: 0,
+                                revenue: 0,
+                            });
+                    sales.units += 1;
+                    sales.revenue += entry.revenue();
+                }
+                Some(reason) => failed.push(FailedOrder {
+                    recipe: entry.recipe.clone(),
+                    reason: reason.clone(),
+                    timestamp: entry.timestamp,
+                }),
+            }
+            for ingredient in Ingredient::ALL {
+                *consumption.get_mut(ingredient) += entry.ingredients.get(ingredient);
+            }
+        }
+        let recipes: Vec<RecipeSales> = recipes.into_values().collect();
+
+        Report {
+            period,
+            first_day,
+            last_day,
+            revenue: recipes.iter().map(|sales| sales.revenue).sum(),
+            recipes,
+            consumption,
+            failed,
+        }
+    }
+
+    /// Get the number of beverages sold
+    pub fn units(&self) -> u32 {
+        self.recipes.iter().map(|sales| sales.units).sum()
+    }
+
+    /// Render the report
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format to render the report in
+    ///
+    /// # Returns
+    ///
+    /// The rendered report
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.render_text(),
+            ReportFormat::Csv => self.render_csv(),
+            ReportFormat::Json => self.render_json(),
+        }
+    }
+
+    /// Describe the days of the report
+    fn days(&self) -> String {
+        match self.period {
+            Period::Daily => format_date(self.first_day),
+            Period::Weekly => format!(
+                "{} to {}",
+                format_date(self.first_day),
+                format_date(self.last_day)
+            ),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "{} report {}",
+                capitalize(&self.period.to_string()),
+                self.days()
+            ),
+            "~".repeat(crate::LINE_AMOUNT),
+        ];
+        if self.recipes.is_empty() {
+            lines.push("Nothing sold".to_string());
+        }
+        for sales in &self.recipes {
+            lines.push(format!(
+                "{}: {} sold, {}",
+                sales.name,
+                sales.units,
+                format_price(sales.revenue)
+            ));
+        }
+        lines.push(format!(
+            "Revenue: {} for {} beverages",
+            format_price(self.revenue),
+            self.units()
+        ));
+        let consumption: Vec<String> = Ingredient::ALL
+            .into_iter()
+            .filter(|&ingredient| self.consumption.get(ingredient) > 0.0)
+            .map(|ingredient| {
+                format!(
+                    "{} {} {}",
+                    ingredient,
+                    self.consumption.get(ingredient),
+                    ingredient.unit()
+                )
+            })
+            .collect();
+        if consumption.is_empty() {
+            lines.push("Consumption: nothing".to_string());
+        } else {
+            lines.push(format!("Consumption: {}", consumption.join(", ")));
+        }
+        lines.push(format!("Failed orders: {}", self.failed.len()));
+        for order in &self.failed {
+            lines.push(format!(
+                "  {} {}: {}",
+                format_timestamp(order.timestamp),
+                order.recipe,
+                order.reason
+            ));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    fn render_csv(&self) -> String {
+        let mut rows = vec!["section,item,quantity,unit,revenue,detail".to_string()];
+        for sales in &self.recipes {
+            rows.push(csv_row(&[
+                "recipe",
+                &sales.name,
+                &sales.units.to_string(),
+                "cups",
+                &format_price(sales.revenue),
+                "",
+            ]));
+        }
+        for ingredient in Ingredient::ALL {
+            rows.push(csv_row(&[
+                "ingredient",
+                &ingredient.to_string(),
+                &self.consumption.get(ingredient).to_string(),
+                &ingredient.unit().to_string(),
+                "",
+                "",
+            ]));
+        }
+        for order in &self.failed {
+            rows.push(csv_row(&[
+                "failed",
+                &order.recipe,
+                "1",
+                "orders",
+                "",
+                &format!("{} {}", format_timestamp(order.timestamp), order.reason),
+            ]));
+        }
+        rows.push(csv_row(&[
+            "total",
+            &self.days(),
+            &self.units().to_string(),
+            "cups",
+            &format_price(self.revenue),
+            "",
+        ]));
+
+        rows.join("\n") + "\n"
+    }
+
+    fn render_json(&self) -> String {
+        let recipes: Vec<_> = self
+            .recipes
+            .iter()
+            .map(|sales| {
+                json!({
+                    "name": sales.name,
+                    "units": sales.units,
+                    "revenue": format_price(sales.revenue),
+                })
+            })
+            .collect();
+        let failed: Vec<_> = self
+            .failed
+            .iter()
+            .map(|order| {
+                json!({
+                    "recipe": order.recipe,
+                    "reason": order.reason,
+                    "time": format_timestamp(order.timestamp),
+                })
+            })
+            .collect();
+        json!({
+            "period": self.period.to_string(),
+            "from": format_date(self.first_day),
+            "to": format_date(self.last_day),
+            "recipes": recipes,
+            "units": self.units(),
+            "revenue": format_price(self.revenue),
+            "consumption": self.consumption,
+            "failed": failed,
+        })
+        .to_string()
+            + "\n"
+    }
+}
+
+/// Parse a date
+///
+/// # Arguments
+///
+/// * `date` - The date as `YYYY-MM-DD`
+///
+/// # Returns
+///
+/// The start of the day in UTC
+///
+/// # Errors
+///
+/// This function will return an error if the date is invalid
+///
+/// # Examples
+///
+/// ```
+/// use rusty_coffeemachine::report::{format_date, parse_date};
+/// use std::time::UNIX_EPOCH;
+/// let day = parse_date("1970-01-02").unwrap();
+/// assert_eq!(day.duration_since(UNIX_EPOCH).unwrap().as_secs(), 86400);
+/// assert!(parse_date("2024-02-30").is_err());
+/// ```
+pub fn parse_date(date: &str) -> Result<SystemTime, MachineError> {
+    let invalid = || MachineError::InvalidInput(format!("Invalid date: {}", date));
+    let parts: Vec<u32> = date
+        .split('-')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let days = days_from_civil(year as i64, month, day).ok_or_else(invalid)?;
+    if days < 0 {
+        return Err(invalid());
+    }
+
+    Ok(UNIX_EPOCH + Duration::from_secs(days as u64 * DAY))
+}
+
+/// Parse a point in time
+///
+/// # Arguments
+///
+/// * `time` - The time as `YYYY-MM-DD`, `YYYY-MM-DD HH:MM` or `YYYY-MM-DD HH:MM:SS`, a `T` may
+///   separate the date and the time of day
+///
+/// # Returns
+///
+/// The point in time in UTC, the start of the day if only a date is given
+///
+/// # Errors
+///
+/// This function will return an error if the time is invalid
+///
+/// # Examples
+///
+/// ```
+/// use rusty_coffeemachine::report::parse_time;
+/// use std::time::UNIX_EPOCH;
+/// let time = parse_time("1970-01-02T01:30").unwrap();
+/// assert_eq!(time.duration_since(UNIX_EPOCH).unwrap().as_secs(), 86400 + 5400);
+/// assert!(parse_time("1970-01-02 24:00").is_err());
+/// ```
+pub fn parse_time(time: &str) -> Result<SystemTime, MachineError> {
+    let invalid = || MachineError::InvalidInput(format!("Invalid time: {}", time));
+    let time = time.trim();
+    let (date, clock) = time.split_once(['T', ' ']).unwrap_or((time, ""));
+    let day = parse_date(date)?;
+    if clock.is_empty() {
+        return Ok(day);
+    }
+    let parts: Vec<u64> = clock
+        .split(':')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes] => (hours, minutes, 0),
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        _ => return Err(invalid()),
+    };
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return Err(invalid());
+    }
+
+    Ok(day + Duration::from_secs(hours * 3600 + minutes * 60 + seconds))
+}
+
+/// Format a day as `YYYY-MM-DD`
+///
+/// # Arguments
+///
+/// * `day` - The days since the unix epoch
+///
+/// # Returns
+///
+/// The formatted date
+pub fn format_date(day: u64) -> String {
+    // Howard Hinnant's civil_from_days
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format a timestamp as `YYYY-MM-DD HH:MM` in UTC
+pub(crate) fn format_timestamp(timestamp: u64) -> String {
+    let seconds = timestamp % DAY;
+    format!(
+        "{} {:02}:{:02}",
+        format_date(timestamp / DAY),
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+/// Get the days since the unix epoch of a date, if the date exists
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if day == 0 || day > days_in_month {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// Join the fields of a CSV row, quoting fields where necessary
+fn csv_row(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::LedgerEntry;
+    use crate::payment::PaymentMethod;
+
+    fn ledger() -> Ledger {
+        let wednesday = unix_seconds(parse_date("2024-05-15").unwrap());
+        let mut ledger = Ledger::new();
+        let mut record = |recipe: &str, price: u32, at: u64, failure: Option<&str>| {
+            let ingredients = IngredientsContainer {
+                water: 30.0,
+                coffee: 30.0,
+                ..Default::default()
+            };
+            ledger.record(LedgerEntry {
+                recipe: recipe.to_string(),
+                ingredients: if failure.is_none() {
+                    ingredients
+                } else {
+                    IngredientsContainer::default()
+                },
+                price,
+                payment: Some(PaymentMethod::Card),
+                failure: failure.map(str::to_string),
+                timestamp: at,
+            });
+        };
+        record("Espresso", 180, wednesday + 3600, None);
+        record(
+            "Latte",
+            300,
+            wednesday + 7200,
+            Some("Payment declined, sorry"),
+        );
+        record("Espresso", 180, wednesday + 9000, None);
+        record("Mocha", 350, wednesday + 2 * DAY, None);
+        record("Mocha", 350, wednesday + 6 * DAY, None);
+        ledger
+    }
+
+    #[test]
+    fn test_daily_report() {
+        let report = Report::new(&ledger(), Period::Daily, parse_date("2024-05-15").unwrap());
+        assert_eq!(
+            report.recipes,
+            vec![RecipeSales {
+                name: "Espresso".to_string(),
+                units: 2,
+                revenue: 360,
+            }]
+        );
+        assert_eq!(report.revenue, 360);
+        assert_eq!(report.consumption.water, 60.0);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(
+            report.render(ReportFormat::Text),
+            "Daily report 2024-05-15\n\
+             ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~\n\
+             Espresso: 2 sold, 3.60\n\
+             Revenue: 3.60 for 2 beverages\n\
+             Consumption: water 60 ml, coffee 60 g\n\
+             Failed orders: 1\n  \
+             2024-05-15 02:00 Latte: Payment declined, sorry\n"
+        );
+    }
+
+    #[test]
+    fn test_weekly_report_runs_from_monday_to_sunday() {
+        let report = Report::new(&ledger(), Period::Weekly, parse_date("2024-05-19").unwrap());
+        assert_eq!(format_date(report.first_day), "2024-05-13");
+        assert_eq!(format_date(report.last_day), "2024-05-19");
+        assert_eq!(report.units(), 3);
+        assert_eq!(report.revenue, 710);
+    }
+
+    #[test]
+    fn test_weekly_report_of_the_first_days() {
+        // The week of the unix epoch starts before it, the report starts on the epoch
+        let report = Report::new(
+            &Ledger::new(),
+            Period::Weekly,
+            parse_date("1970-01-02").unwrap(),
+        );
+        assert_eq!(format_date(report.first_day), "1970-01-01");
+        assert_eq!(format_date(report.last_day), "1970-01-04");
+    }
+
+    #[test]
+    fn test_csv_and_json_reports() {
+        let report = Report::new(&ledger(), Period::Daily, parse_date("2024-05-15").unwrap());
+        let csv = report.render(ReportFormat::Csv);
+        assert!(csv.starts_with(
+            "section,item,quantity,unit,revenue,detail\nrecipe,Espresso,2,cups,3.60,\n"
+        ));
+        assert!(csv.contains("ingredient,milk,0,ml,,\n"));
+        assert!(
+            csv.contains("failed,Latte,1,orders,,\"2024-05-15 02:00 Payment declined, sorry\"\n")
+        );
+        assert!(csv.ends_with("total,2024-05-15,2,cups,3.60,\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.render(ReportFormat::Json)).unwrap();
+        assert_eq!(json["revenue"], "3.60");
+        assert_eq!(json["recipes"][0]["units"], 2);
+        assert_eq!(json["failed"][0]["recipe"], "Latte");
+        assert_eq!(json["consumption"]["coffee"], 60.0);
+    }
+
+    #[test]
+    fn test_dates() {
+        for date in ["1970-01-01", "2000-02-29", "2024-12-31", "2100-03-01"] {
+            let day = unix_seconds(parse_date(date).unwrap()) / DAY;
+            assert_eq!(format_date(day), date);
+        }
+        for date in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-05",
+            "yesterday",
+            "1969-12-31",
+        ] {
+            assert!(parse_date(date).is_err(), "{}", date);
+        }
+    }
+}
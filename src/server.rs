@@ -0,0 +1,558 @@
+use std::sync::Arc;
+use std::thread;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Request, Response};
+
+use crate::cli::{
+    container_json, error_value, ingredients_json, order_json, recipes_json, records_json,
+    status_json,
+};
+use crate::coffeemachine::CoffeeMachine;
+use crate::console::Console;
+use crate::customization::Order;
+use crate::error::MachineError;
+use crate::handle::MachineHandle;
+use crate::queue::{OrderId, OrderQueue, Priority, Worker};
+use crate::state::MachineState;
+
+/// The port the server listens on by default
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// The number of requests the server accepts at the same time
+const THREADS: usize = 4;
+
+/// The seconds a client should wait before retrying an order while the machine is busy
+const RETRY_AFTER: u32 = 5;
+
+/// A response of the HTTP interface, with its status code and JSON body
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Reply {
+    /// Create a reply
+    fn new(status: u16, body: Value) -> Self {
+        Reply { status, body }
+    }
+
+    /// Create the reply to a failed request
+    fn error(error: &MachineError) -> Self {
+        Reply::new(status_code(error), error_value(error))
+    }
+}
+
+/// The body of an order, like `{"recipe": "Latte", "size": "large", "priority": "high"}`
+#[derive(Debug, Deserialize)]
+struct OrderRequest {
+    #[serde(flatten)]
+    order: Order,
+    #[serde(default)]
+    priority: Priority,
+}
+
+/// The body of a refill, like `{"ingredient": "milk", "amount": 50}`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RefillRequest {
+    ingredient: String,
+    amount: f32,
+}
+
+/// A local HTTP server ordering from a single coffee machine
+///
+/// Requests are handled by a few threads sharing the coffee machine with the queue worker, so
+/// they never operate on it at the same time. The ingredients, the grounds bin and the orders are
+/// read from the snapshot of the coffee machine, other requests arriving while it is held by
+/// another one or is brewing are answered with `503 Service Unavailable`.
+#[derive(Debug)]
+pub struct Server {
+    machine: MachineHandle,
+}
+
+impl Server {
+    /// Create a server for a coffee machine
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The coffee machine to take orders for
+    ///
+    /// # Returns
+    ///
+    /// The server
+    pub fn new(machine: CoffeeMachine) -> Self {
+        Server {
+            machine: MachineHandle::new(machine),
+        }
+    }
+
+    /// Get the coffee machine back once the server is done
+    ///
+    /// # Returns
+    ///
+    /// The coffee machine, or `None` if the queue worker still runs
+    pub fn into_machine(self) -> Option<CoffeeMachine> {
+        self.machine.into_inner()
+    }
+
+    /// Handle a request
+    ///
+    /// The routes are:
+    ///
+    /// * `GET /recipes` - The reciepes with their ingredients and prices
+    /// * `GET /status` - The state, the containers and the counters
+    /// * `GET /ingredients` - The ingredient levels
+    /// * `GET /garbage` - The level of the grounds bin
+    /// * `GET /orders` - The queued and the recently finished orders
+    /// * `POST /orders` - Queue an order, answered with `202 Accepted` and its number
+    /// * `GET /orders/{id}` - The status of an order, with the beverage once it is ready
+    /// * `DELETE /orders/{id}` - Cancel a queued order
+    /// * `POST /refill` - Refill a single ingredient
+    /// * `POST /service` - Refill all ingredients and empty the grounds bin
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The HTTP method
+    /// * `path` - The requested path, a query string is ignored
+    /// * `body` - The body of the request
+    ///
+    /// # Returns
+    ///
+    /// The reply, `404 Not Found` for unknown paths, reciepes and orders, `409 Conflict` if the
+    /// machine lacks ingredients or needs a service and `503 Service Unavailable` while it is busy
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::clock::VirtualClock;
+    /// use rusty_coffeemachine::server::Server;
+    /// use rusty_coffeemachine::CoffeeMachine;
+    /// let mut machine = CoffeeMachine::new().unwrap().with_clock(VirtualClock::new());
+    /// machine.power_on().unwrap();
+    /// let server = Server::new(machine);
+    /// let reply = server.handle("POST", "/orders", r#"{"recipe": "Latte"}"#);
+    /// assert_eq!(reply.status, 202);
+    /// assert_eq!(reply.body["status"], "queued");
+    /// let reply = server.handle("GET", "/orders/1", "");
+    /// assert_eq!(reply.body["name"], "Latte");
+    /// assert_eq!(server.handle("GET", "/teapot", "").status, 404);
+    /// ```
+    pub fn handle(&self, method: &str, path: &str, body: &str) -> Reply {
+        let path = path.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let allowed: &[&str] = match segments.as_slice() {
+            ["recipes"] | ["status"] | ["ingredients"] | ["garbage"] => &["GET"],
+            ["orders"] => &["GET", "POST"],
+            ["orders", _] => &["GET", "DELETE"],
+            ["refill"] | ["service"] => &["POST"],
+            _ => {
+                return Reply::new(
+                    404,
+                    json!({ "error": format!("Not found: {}", path), "code": 404 }),
+                )
+            }
+        };
+        let method = method.to_ascii_uppercase();
+        if !allowed.contains(&method.as_str()) {
+            return Reply::new(
+                405,
+                json!({
+                    "error": format!("{} only accepts {}", path, allowed.join(" and ")),
+                    "code": 405,
+                }),
+            );
+        }
+
+        let result = match (method.as_str(), segments.as_slice()) {
+            ("GET", ["ingredients"]) => {
+                let snapshot = self.machine.snapshot();
+                Ok(Reply::new(
+                    200,
+                    ingredients_json(&snapshot.tanks, &snapshot.thresholds),
+                ))
+            }
+            ("GET", ["garbage"]) => Ok(Reply::new(
+                200,
+                container_json(&self.machine.garbage().coffee_grounds),
+            )),
+            ("GET", ["orders"]) => Ok(orders(&self.machine.snapshot().queue)),
+            ("GET", ["orders", id]) => order_status(&self.machine.snapshot().queue, id),
+            (method, segments) => {
+                let written = self.machine.try_with(|machine| match (method, segments) {
+                    ("GET", ["recipes"]) => Ok(Reply::new(200, recipes_json(machine))),
+                    ("GET", ["status"]) => Ok(Reply::new(200, status_json(machine))),
+                    ("POST", ["orders"]) => order(machine, body),
+                    ("DELETE", ["orders", id]) => cancel(machine, id),
+                    ("POST", ["refill"]) => refill(machine, body),
+                    ("POST", ["service"]) => service(machine),
+                    _ => unreachable!("the method is checked against the route"),
+                });
+                match written {
+                    Some(result) => result,
+                    None => {
+                        return Reply::new(
+                            503,
+                            json!({
+                                "error": "The coffee machine is busy, please try again later",
+                                "code": 503,
+                            }),
+                        )
+                    }
+                }
+            }
+        };
+
+        result.unwrap_or_else(|e| Reply::error(&e))
+    }
+
+    /// Serve HTTP requests on localhost until the process is stopped
+    ///
+    /// The coffee machine is powered on before the first request is accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The port to listen on
+    /// * `console` - The console to announce the address on
+    ///
+    /// # Returns
+    ///
+    /// An empty result once the server stops
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if powering on fails or the port can't be opened
+    pub fn serve(&self, port: u16, console: &mut dyn Console) -> Result<(), MachineError> {
+        self.machine.with(|machine| {
+            if *machine.state() == MachineState::Off {
+                machine.power_on()?;
+            }
+            Ok::<(), MachineError>(())
+        })?;
+        let http = tiny_http::Server::http(("127.0.0.1", port))
+            .map_err(|e| MachineError::Io(std::io::Error::other(e.to_string())))?;
+        console.write_line(&format!("Listening on http://127.0.0.1:{}", port))?;
+        self.listen(Arc::new(http));
+
+        Ok(())
+    }
+
+    /// Answer the requests of an HTTP server with a few threads while the worker brews the queue
+    fn listen(&self, http: Arc<tiny_http::Server>) {
+        let worker = Worker::spawn(self.machine.clone());
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                let http = Arc::clone(&http);
+                scope.spawn(move || {
+                    while let Ok(request) = http.recv() {
+                        self.respond(request);
+                    }
+                });
+            }
+        });
+        worker.stop();
+    }
+
+    /// Answer a single request
+    fn respond(&self, mut request: Request) {
+        let mut body = String::new();
+        let reply = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => self.handle(request.method().as_str(), request.url(), &body),
+            Err(e) => Reply::error(&MachineError::InvalidInput(format!(
+                "The body is not UTF-8 text: {}",
+                e
+            ))),
+        };
+        let mut response = Response::from_string(reply.body.to_string())
+            .with_status_code(reply.status)
+            .with_header(header("Content-Type", "application/json"));
+        if reply.status == 503 {
+            response = response.with_header(header("Retry-After", &RETRY_AFTER.to_string()));
+        }
+        // The client may have hung up already, there is nobody left to tell
+        let _ = request.respond(response);
+    }
+}
+
+/// Get the HTTP status code for an error
+///
+/// # Arguments
+///
+/// * `error` - The error of a request
+///
+/// # Returns
+///
+/// `400` for invalid requests, `404` for unknown reciepes and orders, `409` if the machine can't brew in
+/// its current condition and `500` for failures of the machine itself
+///
+/// # Examples
+///
+/// ```
+/// use rusty_coffeemachine::server::status_code;
+/// use rusty_coffeemachine::MachineError;
+/// assert_eq!(status_code(&MachineError::UnknownRecipe("Tea".to_string())), 404);
+/// assert_eq!(status_code(&MachineError::InsufficientIngredients(vec![])), 409);
+/// ```
+pub fn status_code(error: &MachineError) -> u16 {
+    match error {
+        MachineError::InvalidInput(_) => 400,
+        MachineError::InsufficientPayment { .. }
+        | MachineError::PaymentDeclined(_)
+        | MachineError::PaymentCancelled => 402,
+        MachineError::UnknownRecipe(_) | MachineError::UnknownOrder(_) => 404,
+        MachineError::InsufficientIngredients(_)
+        | MachineError::GarbageFull { .. }
+        | MachineError::Overfill { .. }
+        | MachineError::IllegalTransition { .. }
+        | MachineError::NoChange { .. }
+        | MachineError::MaintenanceRequired(_) => 409,
+        MachineError::HardwareFault(_)
+        | MachineError::InvalidRecipeBook { .. }
+        | MachineError::CorruptState { .. }
+        | MachineError::Io(_) => 500,
+    }
+}
+
+/// List the orders in the queue
+fn orders(queue: &OrderQueue) -> Reply {
+    let orders: Vec<Value> = queue
+        .orders()
+        .iter()
+        .map(|queued| order_json(queue, queued))
+        .collect();
+
+    Reply::new(200, Value::Array(orders))
+}
+
+/// Queue an order
+fn order(machine: &mut CoffeeMachine, body: &str) -> Result<Reply, MachineError> {
+    let request: OrderRequest = parse_body(body)?;
+    let id = machine.enqueue(request.order, request.priority)?;
+    queued_order(&machine.queue, id, 202)
+}
+
+/// Get the status of an order
+fn order_status(queue: &OrderQueue, id: &str) -> Result<Reply, MachineError> {
+    queued_order(queue, parse_order_id(id)?, 200)
+}
+
+/// Cancel a queued order
+fn cancel(machine: &mut CoffeeMachine, id: &str) -> Result<Reply, MachineError> {
+    let id = parse_order_id(id)?;
+    machine.cancel_order(id)?;
+    queued_order(&machine.queue, id, 200)
+}
+
+/// Describe an order in the queue
+fn queued_order(queue: &OrderQueue, id: OrderId, status: u16) -> Result<Reply, MachineError> {
+    let queued = queue.get(id).ok_or(MachineError::UnknownOrder(id))?;
+
+    Ok(Reply::new(status, order_json(queue, queued)))
+}
+
+/// Parse the number of an order in a path
+fn parse_order_id(id: &str) -> Result<OrderId, MachineError> {
+    id.parse()
+        .map_err(|_| MachineError::InvalidInput(format!("Invalid order number: {}", id)))
+}
+
+/// Refill a single ingredient
+fn refill(machine: &mut CoffeeMachine, body: &str) -> Result<Reply, MachineError> {
+    let request: RefillRequest = parse_body(body)?;
+    let logged = machine.service_log.len();
+    machine.refill(request.ingredient.parse()?, request.amount)?;
+
+    Ok(Reply::new(
+        200,
+        records_json(&machine.service_log[logged..]),
+    ))
+}
+
+/// Refill all ingredients and empty the grounds bin
+fn service(machine: &mut CoffeeMachine) -> Result<Reply, MachineError> {
+    let logged = machine.service_log.len();
+    machine.take_service()?;
+
+    Ok(Reply::new(
+        200,
+        records_json(&machine.service_log[logged..]),
+    ))
+}
+
+/// Parse the JSON body of a request
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, MachineError> {
+    serde_json::from_str(body)
+        .map_err(|e| MachineError::InvalidInput(format!("Invalid request body: {}", e)))
+}
+
+/// Create an HTTP header
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("the header is valid ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::containers::Ingredient;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    fn server() -> Server {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        Server::new(machine)
+    }
+
+    #[test]
+    fn test_read_endpoints() {
+        let server = server();
+        let reply = server.handle("GET", "/recipes", "");
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body[0]["name"], "Espresso");
+        let reply = server.handle("GET", "/status?verbose", "");
+        assert_eq!(reply.body["state"], "idle");
+        let reply = server.handle("GET", "/ingredients", "");
+        assert_eq!(reply.body["milk"]["level"], 100.0);
+        let reply = server.handle("GET", "/garbage", "");
+        assert_eq!(reply.body["level"], 0.0);
+        assert_eq!(server.handle("POST", "/status", "").status, 405);
+        assert_eq!(server.handle("PUT", "/orders", "").status, 405);
+        assert_eq!(server.handle("GET", "/orders/x", "").status, 400);
+        assert_eq!(server.handle("GET", "/orders/7", "").status, 404);
+    }
+
+    #[test]
+    fn test_orders() {
+        let server = server();
+        let reply = server.handle(
+            "POST",
+            "/orders",
+            r#"{"recipe": 1, "size": "large", "extra_sugar": true}"#,
+        );
+        assert_eq!(reply.status, 202);
+        assert_eq!(reply.body["id"], 1);
+        assert_eq!(reply.body["position"], 1);
+        let reply = server.handle(
+            "POST",
+            "/orders",
+            r#"{"recipe": "Latte", "priority": "high"}"#,
+        );
+        assert_eq!(reply.body["position"], 1);
+
+        let reply = server.handle("POST", "/orders", r#"{"recipe": "Tea"}"#);
+        assert_eq!(reply.status, 404);
+        assert_eq!(reply.body["code"], 4);
+        let reply = server.handle("POST", "/orders", r#"{"size": "large"}"#);
+        assert_eq!(reply.status, 400);
+        // The large espresso and the latte reserve 75 g of the 100 g of coffee
+        let reply = server.handle("POST", "/orders", r#"{"recipe": "Latte"}"#);
+        assert_eq!(reply.status, 409);
+        assert_eq!(reply.body["code"], 3);
+
+        while server.machine.process_next().is_some() {}
+        let reply = server.handle("GET", "/orders/1", "");
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body["status"], "ready");
+        assert_eq!(reply.body["beverage"]["ingredients"]["sugar"], 10.0);
+        let reply = server.handle("GET", "/orders", "");
+        assert_eq!(reply.body[1]["name"], "Latte");
+    }
+
+    #[test]
+    fn test_cancel_orders() {
+        let server = server();
+        server.handle("POST", "/orders", r#"{"recipe": "Latte"}"#);
+        let reply = server.handle("DELETE", "/orders/1", "");
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body["status"], "cancelled");
+        assert_eq!(server.handle("DELETE", "/orders/1", "").status, 400);
+        assert_eq!(server.handle("DELETE", "/orders/2", "").status, 404);
+    }
+
+    #[test]
+    fn test_refill_and_service() {
+        let server = server();
+        server.handle("POST", "/orders", r#"{"recipe": "Latte"}"#);
+        server.machine.process_next();
+        let reply = server.handle("POST", "/refill", r#"{"ingredient": "milk", "amount": 50}"#);
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body["performed"][0]["action"], "refill milk");
+        let reply = server.handle("POST", "/refill", r#"{"ingredient": "milk", "amount": 50}"#);
+        assert_eq!(reply.status, 409);
+        let reply = server.handle("POST", "/service", "");
+        assert_eq!(reply.status, 200);
+
+        let machine = server.into_machine().unwrap();
+        assert_eq!(machine.tanks.get(Ingredient::Milk).level, 100.0);
+        assert_eq!(machine.counters.services, 1);
+    }
+
+    #[test]
+    fn test_busy_machine() {
+        let server = server();
+        server.handle("POST", "/orders", r#"{"recipe": "Espresso"}"#);
+        server.machine.with(|_| {
+            let reply = server.handle("POST", "/orders", r#"{"recipe": "Espresso"}"#);
+            assert_eq!(reply.status, 503);
+            // Reads are answered from the snapshot while the machine is busy
+            let reply = server.handle("GET", "/orders/1", "");
+            assert_eq!(reply.body["status"], "queued");
+            let reply = server.handle("GET", "/ingredients", "");
+            assert_eq!(reply.body["coffee"]["level"], 100.0);
+        });
+    }
+
+    /// Send a request to the server and read the response
+    fn request(port: u16, method: &str, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_http_round_trip() {
+        let server = server();
+        let http = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let port = http.server_addr().to_ip().unwrap().port();
+        thread::scope(|scope| {
+            let listener = Arc::clone(&http);
+            let server = &server;
+            scope.spawn(move || server.listen(listener));
+
+            let response = request(port, "POST", "/orders", r#"{"recipe": "Mocha"}"#);
+            assert!(response.starts_with("HTTP/1.1 202"));
+            assert!(response.contains("Content-Type: application/json"));
+            // The worker brews the order in the background
+            let ready = (0..500).any(|_| {
+                let response = request(port, "GET", "/orders/1", "");
+                if response.contains(r#""status":"ready""#) {
+                    return true;
+                }
+                thread::sleep(Duration::from_millis(10));
+                false
+            });
+            assert!(ready);
+
+            for _ in 0..THREADS {
+                http.unblock();
+            }
+        });
+        assert_eq!(server.into_machine().unwrap().counters.beverages_brewed, 1);
+    }
+}
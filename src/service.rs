@@ -0,0 +1,56 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::containers::{Ingredient, Unit};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServiceAction {
+    Refill(Ingredient),
+    EmptyGroundsBin,
+}
+
+/// A service operation performed on the coffee machine
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceRecord {
+    pub action: ServiceAction,
+    pub amount: f32,
+    pub unit: Unit,
+    pub performed_at: SystemTime,
+}
+
+impl fmt::Display for ServiceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            ServiceAction::Refill(ingredient) => {
+                write!(f, "Added {} {} {}", self.amount, self.unit, ingredient)
+            }
+            ServiceAction::EmptyGroundsBin => {
+                write!(f, "Removed {} {} coffee grounds", self.amount, self.unit)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_record_display() {
+        let record = ServiceRecord {
+            action: ServiceAction::Refill(Ingredient::Water),
+            amount: 50.0,
+            unit: Unit::Millilitre,
+            performed_at: SystemTime::UNIX_EPOCH,
+        };
+        assert_eq!(record.to_string(), "Added 50 ml water");
+
+        let record = ServiceRecord {
+            action: ServiceAction::EmptyGroundsBin,
+            amount: 30.0,
+            unit: Unit::Gram,
+            performed_at: SystemTime::UNIX_EPOCH,
+        };
+        assert_eq!(record.to_string(), "Removed 30 g coffee grounds");
+    }
+}
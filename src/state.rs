@@ -0,0 +1,189 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::MachineError;
+
+/// The state of the coffee machine
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MachineState {
+    Off,
+    HeatingUp,
+    Idle,
+    Brewing,
+    Servicing,
+    Descaling,
+    Cleaning,
+    Error(String),
+}
+
+/// An operation changing the state of the coffee machine
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    PowerOn,
+    Ready,
+    StartBrew,
+    FinishBrew,
+    StartService,
+    FinishService,
+    StartDescaling,
+    FinishDescaling,
+    StartCleaning,
+    FinishCleaning,
+    Fail(String),
+    Reset,
+    PowerOff,
+}
+
+impl MachineState {
+    /// Get the state after an operation
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation to perform
+    ///
+    /// # Returns
+    ///
+    /// The new state of the coffee machine
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the operation is not allowed in the current state
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::state::{MachineState, Operation};
+    /// let state = MachineState::Idle.transition(&Operation::StartBrew).unwrap();
+    /// assert_eq!(state, MachineState::Brewing);
+    /// assert!(MachineState::Descaling.transition(&Operation::StartBrew).is_err());
+    /// ```
+    pub fn transition(&self, operation: &Operation) -> Result<MachineState, MachineError> {
+        let next = match (self, operation) {
+            (MachineState::Off, Operation::PowerOn) => MachineState::HeatingUp,
+            (MachineState::HeatingUp, Operation::Ready) => MachineState::Idle,
+            (MachineState::Idle, Operation::StartBrew) => MachineState::Brewing,
+            (MachineState::Brewing, Operation::FinishBrew) => MachineState::Idle,
+            (MachineState::Idle, Operation::StartService) => MachineState::Servicing,
+            (MachineState::Servicing, Operation::FinishService) => MachineState::Idle,
+            (MachineState::Idle, Operation::StartDescaling) => MachineState::Descaling,
+            (MachineState::Descaling, Operation::FinishDescaling) => MachineState::Idle,
+            (MachineState::Idle, Operation::StartCleaning) => MachineState::Cleaning,
+            (MachineState::Cleaning, Operation::FinishCleaning) => MachineState::Idle,
+            (MachineState::Error(_), Operation::Reset) => MachineState::Idle,
+            (
+                MachineState::HeatingUp | MachineState::Idle | MachineState::Error(_),
+                Operation::PowerOff,
+            ) => MachineState::Off,
+            (state, Operation::Fail(reason)) if *state != MachineState::Off => {
+                MachineState::Error(reason.clone())
+            }
+            _ => {
+                return Err(MachineError::IllegalTransition {
+                    state: self.clone(),
+                    operation: operation.clone(),
+                })
+            }
+        };
+
+        Ok(next)
+    }
+
+    /// Check if the coffee machine is ready to brew
+    pub fn is_ready(&self) -> bool {
+        *self == MachineState::Idle
+    }
+}
+
+impl fmt::Display for MachineState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineState::Off => write!(f, "off"),
+            MachineState::HeatingUp => write!(f, "heating up"),
+            MachineState::Idle => write!(f, "idle"),
+            MachineState::Brewing => write!(f, "brewing"),
+            MachineState::Servicing => write!(f, "servicing"),
+            MachineState::Descaling => write!(f, "descaling"),
+            MachineState::Cleaning => write!(f, "cleaning"),
+            MachineState::Error(reason) => write!(f, "error ({})", reason),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::PowerOn => write!(f, "power on"),
+            Operation::Ready => write!(f, "finish heating up"),
+            Operation::StartBrew => write!(f, "brew"),
+            Operation::FinishBrew => write!(f, "finish brewing"),
+            Operation::StartService => write!(f, "service"),
+            Operation::FinishService => write!(f, "finish the service"),
+            Operation::StartDescaling => write!(f, "descale"),
+            Operation::FinishDescaling => write!(f, "finish descaling"),
+            Operation::StartCleaning => write!(f, "clean"),
+            Operation::FinishCleaning => write!(f, "finish cleaning"),
+            Operation::Fail(_) => write!(f, "fail"),
+            Operation::Reset => write!(f, "reset"),
+            Operation::PowerOff => write!(f, "power off"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_cycle() {
+        let state = MachineState::Off;
+        let state = state.transition(&Operation::PowerOn).unwrap();
+        assert_eq!(state, MachineState::HeatingUp);
+        let state = state.transition(&Operation::Ready).unwrap();
+        assert_eq!(state, MachineState::Idle);
+        let state = state.transition(&Operation::PowerOff).unwrap();
+        assert_eq!(state, MachineState::Off);
+    }
+
+    #[test]
+    fn test_illegal_transitions() {
+        let illegal = [
+            (MachineState::Descaling, Operation::StartBrew),
+            (MachineState::Brewing, Operation::StartService),
+            (MachineState::Servicing, Operation::StartBrew),
+            (MachineState::Cleaning, Operation::StartBrew),
+            (MachineState::Descaling, Operation::StartCleaning),
+            (MachineState::Off, Operation::StartBrew),
+            (MachineState::HeatingUp, Operation::StartBrew),
+            (MachineState::Brewing, Operation::PowerOff),
+            (MachineState::Idle, Operation::FinishBrew),
+            (MachineState::Off, Operation::Fail("boiler".to_string())),
+        ];
+        for (state, operation) in illegal {
+            match state.transition(&operation) {
+                Err(MachineError::IllegalTransition {
+                    state: from,
+                    operation: rejected,
+                }) => {
+                    assert_eq!(from, state);
+                    assert_eq!(rejected, operation);
+                }
+                other => panic!("{} during {}: {:?}", operation, state, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_and_reset() {
+        let state = MachineState::Brewing
+            .transition(&Operation::Fail("pump blocked".to_string()))
+            .unwrap();
+        assert_eq!(state, MachineState::Error("pump blocked".to_string()));
+        assert!(state.transition(&Operation::StartBrew).is_err());
+        assert_eq!(
+            state.transition(&Operation::Reset).unwrap(),
+            MachineState::Idle
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::containers::{Container, Ingredient, IngredientsContainer};
+use crate::error::{MachineError, Shortfall};
+
+/// The default level below which an ingredient is running low
+pub const DEFAULT_WARNING: f32 = 30.0;
+
+/// The default level below which an ingredient is critically low
+pub const DEFAULT_CRITICAL: f32 = 10.0;
+
+/// How far an ingredient has run down
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StockLevel {
+    Ok,
+    Low,
+    Critical,
+}
+
+impl fmt::Display for StockLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StockLevel::Ok => write!(f, "ok"),
+            StockLevel::Low => write!(f, "low"),
+            StockLevel::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// The levels below which the ingredients are reported as running low, per ingredient
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StockThresholds {
+    pub warning: IngredientsContainer,
+    pub critical: IngredientsContainer,
+}
+
+impl Default for StockThresholds {
+    fn default() -> Self {
+        let all = |amount| IngredientsContainer {
+            water: amount,
+            coffee: amount,
+            milk: amount,
+            sugar: amount,
+            cacao: amount,
+        };
+        StockThresholds {
+            warning: all(DEFAULT_WARNING),
+            critical: all(DEFAULT_CRITICAL),
+        }
+    }
+}
+
+impl StockThresholds {
+    /// Change the thresholds of an ingredient
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredient` - The ingredient
+    /// * `warning` - The level below which the ingredient is running low
+    /// * `critical` - The level below which the ingredient is critically low
+    ///
+    /// # Returns
+    ///
+    /// The thresholds with the changed ingredient
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a threshold is negative or the critical threshold is
+    /// above the warning threshold
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_coffeemachine::containers::Ingredient;
+    /// use rusty_coffeemachine::stock::{StockLevel, StockThresholds};
+    /// let thresholds = StockThresholds::default()
+    ///     .with_threshold(Ingredient::Milk, 50.0, 20.0)
+    ///     .unwrap();
+    /// assert_eq!(thresholds.level(Ingredient::Milk, 40.0), StockLevel::Low);
+    /// assert_eq!(thresholds.level(Ingredient::Water, 40.0), StockLevel::Ok);
+    /// assert!(StockThresholds::default()
+    ///     .with_threshold(Ingredient::Milk, 10.0, 20.0)
+    ///     .is_err());
+    /// ```
+    pub fn with_threshold(
+        mut self,
+        ingredient: Ingredient,
+        warning: f32,
+        critical: f32,
+    ) -> Result<Self, MachineError> {
+        if !(warning.is_finite() && critical.is_finite() && critical >= 0.0 && critical <= warning)
+        {
+            return Err(MachineError::InvalidInput(format!(
+                "Invalid thresholds for {}: the critical threshold {} has to be between 0 and the warning threshold {}",
+                ingredient, critical, warning
+            )));
+        }
+        *self.warning.get_mut(ingredient) = warning;
+        *self.critical.get_mut(ingredient) = critical;
+
+        Ok(self)
+    }
+
+    /// Get the stock level of an ingredient
+    ///
+    /// # Arguments
+    ///
+    /// * `ingredient` - The ingredient
+    /// * `amount` - The amount of the ingredient left
+    ///
+    /// # Returns
+    ///
+    /// The stock level of the ingredient
+    pub fn level(&self, ingredient: Ingredient, amount: f32) -> StockLevel {
+        if amount < self.critical.get(ingredient) {
+            StockLevel::Critical
+        } else if amount < self.warning.get(ingredient) {
+            StockLevel::Low
+        } else {
+            StockLevel::Ok
+        }
+    }
+}
+
+/// An ingredient that is running low
+#[derive(Clone, Debug, PartialEq)]
+pub struct StockWarning {
+    pub ingredient: Ingredient,
+    pub level: StockLevel,
+    pub tank: Container,
+}
+
+impl fmt::Display for StockWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            StockLevel::Critical => {
+                write!(f, "{} is critically low ({})", self.ingredient, self.tank)
+            }
+            _ => write!(f, "{} is running low ({})", self.ingredient, self.tank),
+        }
+    }
+}
+
+/// A reciepe that can't be brewed with the ingredients left
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnavailableRecipe {
+    /// The menu number, starting at 1
+    pub id: usize,
+    pub name: String,
+    /// The ingredients blocking the reciepe
+    pub shortfalls: Vec<Shortfall>,
+}
+
+impl UnavailableRecipe {
+    /// Get the names of the ingredients blocking the reciepe
+    ///
+    /// # Returns
+    ///
+    /// The ingredients, separated by commas
+    pub fn blocking(&self) -> String {
+        self.shortfalls
+            .iter()
+            .map(|shortfall| shortfall.ingredient.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl fmt::Display for UnavailableRecipe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (out of {})", self.name, self.blocking())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::containers::Unit;
+
+    #[test]
+    fn test_stock_levels() {
+        let thresholds = StockThresholds::default();
+        assert_eq!(thresholds.level(Ingredient::Water, 30.0), StockLevel::Ok);
+        assert_eq!(thresholds.level(Ingredient::Water, 29.0), StockLevel::Low);
+        assert_eq!(
+            thresholds.level(Ingredient::Water, 9.5),
+            StockLevel::Critical
+        );
+        assert!(StockLevel::Critical > StockLevel::Low);
+    }
+
+    #[test]
+    fn test_warning_display() {
+        let warning = StockWarning {
+            ingredient: Ingredient::Milk,
+            level: StockLevel::Critical,
+            tank: Container {
+                level: 5.0,
+                capacity: 100.0,
+                unit: Unit::Millilitre,
+            },
+        };
+        assert_eq!(warning.to_string(), "milk is critically low (5/100 ml)");
+    }
+}
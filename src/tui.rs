@@ -0,0 +1,553 @@
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use ratatui::crossterm::event::{
+    self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::coffeemachine::CoffeeMachine;
+use crate::console::Console;
+use crate::containers::{Container, Ingredient};
+use crate::customization::Order;
+use crate::error::MachineError;
+use crate::events::{ChannelSubscriber, Event, EventKind};
+use crate::handle::{MachineHandle, MachineSnapshot};
+use crate::payment::Payment;
+use crate::reciepes::Reciepes;
+use crate::state::MachineState;
+use crate::stock::StockLevel;
+use crate::{capitalize, format_price, ProgressBar};
+
+/// How long to wait for a key before redrawing the live panels
+const TICK: Duration = Duration::from_millis(100);
+
+/// The number of lines kept in the log pane
+const LOG_LINES: usize = 100;
+
+/// The share of the grounds bin above which its gauge turns yellow
+const GROUNDS_WARNING: f64 = 0.8;
+
+/// The keys of the terminal UI, shown in the footer
+const KEYS: &str = "↑/↓ select  Enter brew (card)  s service  e empty grounds bin  q quit";
+
+/// Check if the terminal can show the full-screen UI
+///
+/// # Returns
+///
+/// `true` if stdout is a terminal which is not a dumb one
+pub fn supported() -> bool {
+    std::io::stdout().is_terminal()
+        && !matches!(std::env::var("TERM").as_deref(), Ok("dumb") | Err(_))
+}
+
+/// Run the coffee machine in the full-screen terminal UI
+///
+/// The coffee machine is started up if it is switched off and powered off again on quitting, like
+/// in the line-based mode. Brewing and servicing run in the background, so the gauges and the
+/// log stay live while the coffee machine is busy.
+///
+/// # Arguments
+///
+/// * `machine` - The coffee machine, which is operated on by the UI until it quits
+///
+/// # Returns
+///
+/// An empty result
+///
+/// # Errors
+///
+/// This function will return an error if the terminal can't be set up or drawn to
+pub fn run(machine: &mut CoffeeMachine) -> Result<(), MachineError> {
+    let handle = MachineHandle::new(std::mem::replace(machine, CoffeeMachine::new()?));
+    let mut app = App::new(handle.clone());
+    if handle.snapshot().state == MachineState::Off {
+        app.spawn(|machine, console| machine.start_up(console));
+    }
+    let result = ratatui::try_init()
+        .map_err(MachineError::from)
+        .and_then(|mut terminal| {
+            let result = app.run(&mut terminal);
+            ratatui::restore();
+            result
+        });
+    app.finish();
+    // Closing the event channel disconnects the subscriber of the UI
+    drop(app);
+    if handle.snapshot().state == MachineState::Idle {
+        handle.with(|machine| machine.power_off())?;
+    }
+    if let Some(operated) = handle.into_inner() {
+        *machine = operated;
+    }
+
+    result
+}
+
+/// A message from an operation running in the background
+#[derive(Debug)]
+enum Update {
+    /// A line written to the console
+    Log(String),
+    /// A frame of a progress bar
+    Progress { progress: f32, max: f32 },
+    /// The operation finished
+    Done,
+}
+
+/// A console forwarding the output of an operation to the terminal UI
+///
+/// Lines go to the log pane and progress bars to the brewing panel. There is nobody to type an
+/// answer, so reading a line fails.
+#[derive(Debug)]
+struct TuiConsole {
+    updates: Sender<Update>,
+    line: String,
+}
+
+impl TuiConsole {
+    /// Create a console sending its output into a channel
+    fn new(updates: Sender<Update>) -> Self {
+        TuiConsole {
+            updates,
+            line: String::new(),
+        }
+    }
+
+    /// Send an update, the UI may have quit already
+    fn send(&self, update: Update) {
+        let _ = self.updates.send(update);
+    }
+}
+
+impl Console for TuiConsole {
+    fn read_line(&mut self) -> Result<String, MachineError> {
+        Err(MachineError::InvalidInput(
+            "The terminal UI doesn't read lines".to_string(),
+        ))
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), MachineError> {
+        self.line.push_str(text);
+        while let Some(end) = self.line.find('\n') {
+            let line: String = self.line.drain(..=end).collect();
+            let line = line.trim_end();
+            if !line.is_empty() {
+                self.send(Update::Log(line.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), MachineError> {
+        Ok(())
+    }
+
+    fn draw_progress(&mut self, progress_bar: &ProgressBar) -> Result<(), MachineError> {
+        self.send(Update::Progress {
+            progress: progress_bar.progress,
+            max: progress_bar.max,
+        });
+
+        Ok(())
+    }
+}
+
+/// The state of the terminal UI
+#[derive(Debug)]
+struct App {
+    machine: MachineHandle,
+    reciepes: Vec<Reciepes>,
+    selected: ListState,
+    log: VecDeque<String>,
+    /// The step shown above the progress bar, while an operation draws one
+    step: Option<String>,
+    progress: ProgressBar,
+    events: Receiver<Event>,
+    updates: Receiver<Update>,
+    sender: Sender<Update>,
+    job: Option<JoinHandle<()>>,
+    quit: bool,
+}
+
+impl App {
+    /// Create the terminal UI for a shared coffee machine, subscribing to its events
+    fn new(machine: MachineHandle) -> Self {
+        let (subscriber, events) = ChannelSubscriber::new();
+        let reciepes = machine.with(|machine| {
+            machine.subscribe(subscriber);
+            machine.reciepes.clone()
+        });
+        let (sender, updates) = mpsc::channel();
+        App {
+            machine,
+            reciepes,
+            selected: ListState::default().with_selected(Some(0)),
+            log: VecDeque::new(),
+            step: None,
+            progress: ProgressBar::new(100.0),
+            events,
+            updates,
+            sender,
+            job: None,
+            quit: false,
+        }
+    }
+
+    /// Handle keys and redraw until the user quits
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), MachineError> {
+        while !self.quit {
+            self.update();
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(TICK)? {
+                if let TermEvent::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.on_key(key);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a key press
+    fn on_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.selected.select_next(),
+            KeyCode::Enter => {
+                let Some(index) = self.selected.selected() else {
+                    return;
+                };
+                if self.reciepes.is_empty() {
+                    return;
+                }
+                let order = Order::new(index.min(self.reciepes.len() - 1) + 1);
+                self.spawn(move |machine, console| {
+                    let sale = machine.buy_with_progress(order, Payment::Card, console)?;
+                    console.write_line(&format!("{} charged to the card", format_price(sale.price)))
+                });
+            }
+            KeyCode::Char('s') => self.spawn(|machine, _| machine.take_service()),
+            KeyCode::Char('e') => self.spawn(|machine, _| machine.empty_grounds_bin().map(|_| ())),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            _ => {}
+        }
+    }
+
+    /// Run an operation on the coffee machine in the background, unless one is running already
+    fn spawn(
+        &mut self,
+        operation: impl FnOnce(&mut CoffeeMachine, &mut TuiConsole) -> Result<(), MachineError>
+            + Send
+            + 'static,
+    ) {
+        if self.busy() {
+            self.push_log("The coffee machine is busy".to_string());
+            return;
+        }
+        let machine = self.machine.clone();
+        let mut console = TuiConsole::new(self.sender.clone());
+        self.job = Some(thread::spawn(move || {
+            if let Err(e) = machine.with(|machine| operation(machine, &mut console)) {
+                console.send(Update::Log(format!("Error: {}", e)));
+            }
+            console.send(Update::Done);
+        }));
+    }
+
+    /// Check if an operation is running in the background
+    fn busy(&self) -> bool {
+        self.job.as_ref().is_some_and(|job| !job.is_finished())
+    }
+
+    /// Wait for the operation running in the background and take its last updates
+    fn finish(&mut self) {
+        if let Some(job) = self.job.take() {
+            // A panicking operation already left the coffee machine to the next one
+            let _ = job.join();
+        }
+        self.update();
+    }
+
+    /// Take the output of the operations and the events of the coffee machine
+    fn update(&mut self) {
+        while let Ok(update) = self.updates.try_recv() {
+            match update {
+                Update::Log(line) => {
+                    if line.starts_with("Step ") || line.starts_with("Heating up") {
+                        self.step = Some(line.clone());
+                    }
+                    self.push_log(line);
+                }
+                Update::Progress { progress, max } => {
+                    self.progress.max = max;
+                    let _ = self.progress.set_progress(progress);
+                }
+                Update::Done => {
+                    self.step = None;
+                    self.progress = ProgressBar::new(100.0);
+                }
+            }
+        }
+        while let Ok(event) = self.events.try_recv() {
+            match event.kind {
+                // The state is shown in the status panel and failures are logged as errors
+                EventKind::StateChanged { .. } | EventKind::BrewFailed { .. } => {}
+                _ => self.push_log(event.to_string()),
+            }
+        }
+    }
+
+    /// Add a line to the log pane, dropping the oldest one if it is full
+    fn push_log(&mut self, line: String) {
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+
+    /// Draw the panels
+    fn draw(&mut self, frame: &mut Frame) {
+        let snapshot = self.machine.snapshot();
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [menu, panels] =
+            Layout::horizontal([Constraint::Length(36), Constraint::Min(0)]).areas(main);
+        let [status, brewing, log] = Layout::vertical([
+            Constraint::Length(Ingredient::ALL.len() as u16 + 3),
+            Constraint::Length(3),
+            Constraint::Min(3),
+        ])
+        .areas(panels);
+
+        self.draw_recipes(frame, menu, &snapshot);
+        draw_status(frame, status, &snapshot);
+        self.draw_brewing(frame, brewing);
+        self.draw_log(frame, log);
+        frame.render_widget(
+            Paragraph::new(KEYS).style(Style::default().add_modifier(Modifier::DIM)),
+            footer,
+        );
+    }
+
+    /// Draw the reciepes with their prices, greying out the unavailable ones
+    fn draw_recipes(&mut self, frame: &mut Frame, area: Rect, snapshot: &MachineSnapshot) {
+        let items: Vec<ListItem> = self
+            .reciepes
+            .iter()
+            .enumerate()
+            .map(|(i, reciepe)| {
+                let line = format!("{:<20} {:>7}", reciepe.name, format_price(reciepe.price));
+                match snapshot
+                    .unavailable
+                    .iter()
+                    .find(|recipe| recipe.id == i + 1)
+                {
+                    Some(unavailable) => ListItem::new(vec![
+                        Line::from(line),
+                        Line::from(format!("  out of {}", unavailable.blocking())),
+                    ])
+                    .style(Style::default().fg(Color::DarkGray)),
+                    None => ListItem::new(line),
+                }
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(" Recipes "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, area, &mut self.selected);
+    }
+
+    /// Draw the progress bar of the running operation
+    fn draw_brewing(&self, frame: &mut Frame, area: Rect) {
+        let title = self.step.as_deref().unwrap_or("Brewing");
+        let ratio = (self.progress.progress / self.progress.max).clamp(0.0, 1.0);
+        let label = if self.busy() || self.step.is_some() {
+            format!("{}%", (ratio * 100.0) as usize)
+        } else {
+            "Idle".to_string()
+        };
+        let gauge = Gauge::default()
+            .block(Block::bordered().title(format!(" {} ", title)))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(ratio as f64)
+            .label(label);
+        frame.render_widget(gauge, area);
+    }
+
+    /// Draw the latest lines of the log
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let visible = area.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self
+            .log
+            .iter()
+            .skip(self.log.len().saturating_sub(visible))
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Log ")),
+            area,
+        );
+    }
+}
+
+/// Draw a gauge for every ingredient and the grounds bin
+fn draw_status(frame: &mut Frame, area: Rect, snapshot: &MachineSnapshot) {
+    let block = Block::bordered().title(format!(" Status: {} ", snapshot.state));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows =
+        Layout::vertical(vec![Constraint::Length(1); Ingredient::ALL.len() + 1]).split(inner);
+    for (ingredient, row) in Ingredient::ALL.into_iter().zip(rows.iter()) {
+        let tank = snapshot.tanks.get(ingredient);
+        let color = match snapshot.thresholds.level(ingredient, tank.level) {
+            StockLevel::Ok => Color::Green,
+            StockLevel::Low => Color::Yellow,
+            StockLevel::Critical => Color::Red,
+        };
+        draw_gauge(
+            frame,
+            *row,
+            &capitalize(&ingredient.to_string()),
+            tank,
+            color,
+        );
+    }
+    let grounds = &snapshot.garbage_container.coffee_grounds;
+    let color = if fill(grounds) >= GROUNDS_WARNING {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+    draw_gauge(
+        frame,
+        rows[Ingredient::ALL.len()],
+        "Grounds bin",
+        grounds,
+        color,
+    );
+}
+
+/// Draw the level of a container as a bar with its name and amount in front
+fn draw_gauge(frame: &mut Frame, area: Rect, name: &str, container: &Container, color: Color) {
+    let [label, bar] = Layout::horizontal([Constraint::Length(26), Constraint::Min(0)]).areas(area);
+    frame.render_widget(
+        Paragraph::new(format!("{:<12}{:>13}", name, container.to_string())),
+        label,
+    );
+    let ratio = fill(container);
+    frame.render_widget(
+        Gauge::default()
+            .gauge_style(Style::default().fg(color))
+            .ratio(ratio)
+            .label(format!("{}%", (ratio * 100.0).round())),
+        bar,
+    );
+}
+
+/// Get how full a container is, between 0 and 1
+fn fill(container: &Container) -> f64 {
+    if container.capacity <= 0.0 {
+        return 0.0;
+    }
+    (container.level / container.capacity).clamp(0.0, 1.0) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn app() -> App {
+        let mut machine = CoffeeMachine::new()
+            .unwrap()
+            .with_clock(VirtualClock::new());
+        machine.power_on().unwrap();
+        App::new(MachineHandle::new(machine))
+    }
+
+    fn render(app: &mut App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 24)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_navigate_and_brew() {
+        let mut app = app();
+        app.on_key(KeyCode::Down.into());
+        app.on_key(KeyCode::Down.into());
+        app.on_key(KeyCode::Up.into());
+        assert_eq!(app.selected.selected(), Some(1));
+
+        app.on_key(KeyCode::Enter.into());
+        app.finish();
+        let screen = render(&mut app);
+        assert!(screen.contains(&format!("{} ready", app.reciepes[1].name)));
+        assert!(screen.contains("Step 1/"));
+        assert!(screen.contains("charged to the card"));
+        assert!(!app.busy());
+
+        app.on_key(KeyCode::Char('q').into());
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_gauges() {
+        let mut app = app();
+        app.machine.brew("Espresso").unwrap();
+        app.machine.with(|machine| machine.tanks.milk.level = 40.0);
+        app.update();
+        let screen = render(&mut app);
+        assert!(screen.contains("Status: idle"));
+        assert!(screen.contains("Water"));
+        assert!(screen.contains("Grounds bin"));
+        assert!(screen.contains("30/100 g"));
+        assert!(screen.contains("out of milk"));
+        assert!(screen.contains("Espresso ready"));
+        assert!(screen.contains("Idle"));
+    }
+
+    #[test]
+    fn test_console_forwards_lines_and_progress() {
+        let (sender, updates) = mpsc::channel();
+        let mut console = TuiConsole::new(sender);
+        console.write("Step 1/2: ").unwrap();
+        console.write_line("Grind").unwrap();
+        let mut progress_bar = ProgressBar::new(100.0);
+        progress_bar.set_progress(40.0).unwrap();
+        console.draw_progress(&progress_bar).unwrap();
+        assert!(console.read_line().is_err());
+
+        let updates: Vec<Update> = updates.try_iter().collect();
+        assert!(matches!(&updates[0], Update::Log(line) if line == "Step 1/2: Grind"));
+        assert!(matches!(
+            updates[1],
+            Update::Progress {
+                progress,
+                max
+            } if progress == 40.0 && max == 100.0
+        ));
+    }
+}